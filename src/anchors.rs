@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::priority::sort_by_priority;
+use super::{ChunkLocation, Dimension};
+
+/// Identifies an anchor within its dimension
+pub type AnchorId = u64;
+
+/// A point the world should be kept ready around, like a player or a camera
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Anchor {
+    pub location: ChunkLocation,
+    /// how many chunks out from location should be kept ready
+    pub radius: u32,
+}
+
+impl Anchor {
+    /// If the chunk lies within the anchor's radius
+    pub fn covers(&self, location: ChunkLocation) -> bool {
+        location.x.abs_diff(self.location.x) <= self.radius
+            && location.y.abs_diff(self.location.y) <= self.radius
+            && location.z.abs_diff(self.location.z) <= self.radius
+    }
+}
+
+/// All the anchors of a dimension
+#[derive(Clone, Default)]
+pub struct Anchors {
+    anchors: BTreeMap<AnchorId, Anchor>,
+    next_id: AnchorId,
+}
+
+impl Anchors {
+    pub fn new() -> Anchors {
+        Anchors::default()
+    }
+
+    pub fn get(&self, id: AnchorId) -> Option<&Anchor> {
+        self.anchors.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&AnchorId, &Anchor)> {
+        self.anchors.iter()
+    }
+
+    /// If the chunk lies within the radius of any anchor
+    pub fn covers(&self, location: ChunkLocation) -> bool {
+        self.anchors.values().any(|anchor| anchor.covers(location))
+    }
+
+    /// Every chunk within the radius of an anchor, ordered by the squared distance to the
+    /// closest anchor so the most urgent chunks come first
+    pub fn covered_chunks(&self) -> Vec<ChunkLocation> {
+        let mut distances: HashMap<ChunkLocation, u64> = HashMap::new();
+        for anchor in self.anchors.values() {
+            let center = anchor.location;
+            // no chunk lies further out than i32::MAX on any axis
+            let radius = anchor.radius.min(i32::MAX as u32) as i32;
+            for z in center.z.saturating_sub(radius)..=center.z.saturating_add(radius) {
+                for y in center.y.saturating_sub(radius)..=center.y.saturating_add(radius) {
+                    for x in center.x.saturating_sub(radius)..=center.x.saturating_add(radius) {
+                        let location = ChunkLocation::new(x, y, z);
+                        let distance = (x.abs_diff(center.x) as u64).pow(2)
+                            + (y.abs_diff(center.y) as u64).pow(2)
+                            + (z.abs_diff(center.z) as u64).pow(2);
+                        let entry = distances.entry(location).or_insert(distance);
+                        *entry = (*entry).min(distance);
+                    }
+                }
+            }
+        }
+        let mut chunks: Vec<(ChunkLocation, u64)> = distances.into_iter().collect();
+        chunks.sort_by_key(|(location, distance)| (*distance, location.z, location.y, location.x));
+        chunks.into_iter().map(|(location, _)| location).collect()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Keeps the chunks within radius of location ready, returning an id to move or remove
+    /// the anchor with later
+    pub fn add_anchor(&mut self, location: ChunkLocation, radius: u32) -> AnchorId {
+        let id = self.anchors.next_id;
+        self.anchors.next_id += 1;
+        let anchor = Anchor { location, radius };
+        self.anchors.anchors.insert(id, anchor);
+        self.chunks_visited(&anchor);
+        id
+    }
+
+    /// Moves an anchor, returning false if it doesn't exist
+    pub fn move_anchor(&mut self, id: AnchorId, location: ChunkLocation) -> bool {
+        match self.anchors.anchors.get_mut(&id) {
+            Some(anchor) => {
+                anchor.location = location;
+                let anchor = *anchor;
+                self.chunks_visited(&anchor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an anchor, returning false if it doesn't exist
+    pub fn remove_anchor(&mut self, id: AnchorId) -> bool {
+        self.anchors.anchors.remove(&id).is_some()
+    }
+
+    pub fn anchors(&self) -> &Anchors {
+        &self.anchors
+    }
+
+    /// Spends spare time until deadline getting chunks around the anchors ready ahead of
+    /// when they are needed, nearest chunks first, so that moving anchors don't cause
+    /// spikes of loading later. The chunk priority, if set, decides the order instead.
+    /// Returns how many chunks were made resident
+    pub fn idle_work(&mut self, deadline: Instant) -> usize {
+        // regions someone is waiting on come first
+        let mut loaded = self.drive_region_loads(deadline);
+        let mut chunks = self.anchors.covered_chunks();
+        if let Some(priority) = &self.chunk_priority {
+            sort_by_priority(&mut chunks, priority.as_ref());
+        }
+        for location in chunks {
+            if Instant::now() >= deadline {
+                break;
+            }
+            if self.chunk_available(location) && !self.chunk_loaded(location) {
+                // a chunk that fails to load is left for whoever needs it to report
+                if self.load_chunk(location).is_ok() {
+                    loaded += 1;
+                }
+            }
+        }
+        loaded
+    }
+}
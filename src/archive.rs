@@ -0,0 +1,79 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use super::codec::VoxelCodec;
+use super::error::{Error, ErrorContext, Operation, Result, ResultExt};
+use super::occupancy::VoxelClass;
+use super::Dimension;
+
+/// The zstd level archives are written with, favoring size since archives are for sharing
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 9;
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Saves the world and bundles everything in its disk cache (metadata, region files,
+    /// layers) into one zstd compressed tar file, for sharing and backups. Chunks in cold
+    /// storage aren't in the disk cache, so the archive only lists them
+    pub fn export_archive<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let folder = self.disk_cache.clone().ok_or(Error::NoDiskCache)?;
+        self.flush()?;
+        let path = path.as_ref();
+        let export = || -> io::Result<()> {
+            let encoder = zstd::Encoder::new(
+                BufWriter::new(File::create(path)?),
+                ARCHIVE_COMPRESSION_LEVEL,
+            )?;
+            let mut builder = tar::Builder::new(encoder);
+            builder.append_dir_all(".", &folder)?;
+            builder.into_inner()?.finish()?;
+            Ok(())
+        };
+        export().with_context(|| ErrorContext::new(Operation::ExportArchive).path(path))
+    }
+
+    /// Replaces the contents of the disk cache with an archive made by export_archive and
+    /// reads the world back from it. Loaded chunks are dropped without being saved, so
+    /// they are read from the archive next time they are needed. The archive is unpacked
+    /// next to the disk cache and only then swapped in, so a broken archive leaves the
+    /// world as it was
+    pub fn import_archive<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let folder = self.disk_cache.clone().ok_or(Error::NoDiskCache)?;
+        let path = path.as_ref();
+        let import = || -> io::Result<()> {
+            let folder = Path::new(&folder);
+            let sibling = |suffix: &str| {
+                let mut name = folder.file_name().unwrap_or_default().to_os_string();
+                name.push(suffix);
+                folder.with_file_name(name)
+            };
+            let (staging, replaced) = (sibling(".importing"), sibling(".replaced"));
+            // left behind by an import that was cut short
+            for stale in [&staging, &replaced] {
+                if stale.exists() {
+                    fs::remove_dir_all(stale)?;
+                }
+            }
+            fs::create_dir_all(&staging)?;
+            let unpacked = zstd::Decoder::new(BufReader::new(File::open(path)?))
+                .and_then(|decoder| tar::Archive::new(decoder).unpack(&staging));
+            if let Err(err) = unpacked {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(err);
+            }
+            if folder.exists() {
+                fs::rename(folder, &replaced)?;
+            }
+            fs::rename(&staging, folder)?;
+            fs::remove_dir_all(&replaced).or_else(|err| match err.kind() {
+                io::ErrorKind::NotFound => Ok(()),
+                _ => Err(err),
+            })
+        };
+        import().with_context(|| ErrorContext::new(Operation::ImportArchive).path(path))?;
+        let loaded: Vec<_> = self.loaded_chunks.keys().copied().collect();
+        for location in loaded {
+            self.discard_chunk(location);
+        }
+        self.load_metadata()
+    }
+}
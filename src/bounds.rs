@@ -0,0 +1,86 @@
+use super::codec::VoxelCodec;
+use super::error::{Error, Result};
+use super::occupancy::VoxelClass;
+use super::{Aabb, Dimension, GlobalLocation, Point3D};
+
+/// What happens when a voxel outside the world bounds is accessed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OutOfBounds {
+    /// The access fails with Error::OutOfBounds
+    Error,
+    /// The nearest voxel inside the bounds is used instead
+    Clamp,
+    /// Reads produce the default voxel and writes are dropped
+    Default,
+    /// The world repeats, so leaving one side enters through the other
+    Wrap,
+}
+
+/// Wraps value into [start, end)
+fn wrap_axis(value: i32, start: i32, end: i32) -> i32 {
+    let size = end as i64 - start as i64;
+    ((value as i64 - start as i64).rem_euclid(size) + start as i64) as i32
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Limits the world to a box, with out of bounds accesses handled according to behavior
+    pub fn set_bounds(&mut self, bounds: Aabb, behavior: OutOfBounds) {
+        assert!(
+            !bounds.is_empty(),
+            "world bounds must contain at least one voxel"
+        );
+        self.bounds = Some(bounds);
+        self.out_of_bounds = behavior;
+    }
+
+    /// Makes the world unbounded again
+    pub fn clear_bounds(&mut self) {
+        self.bounds = None;
+    }
+
+    pub fn world_bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    /// Maps a location onto the voxel that should actually be accessed. Ok(None) means the
+    /// access should behave as if the voxel were default
+    pub fn resolve_location(&self, location: GlobalLocation) -> Result<Option<GlobalLocation>> {
+        let bounds = match self.bounds {
+            Some(bounds) if !bounds.contains(location) => bounds,
+            _ => return Ok(Some(location)),
+        };
+        match self.out_of_bounds {
+            OutOfBounds::Error => Err(Error::OutOfBounds { location }),
+            OutOfBounds::Default => Ok(None),
+            OutOfBounds::Clamp => Ok(Some(Point3D::new(
+                location.x.clamp(bounds.start.x, bounds.end.x - 1),
+                location.y.clamp(bounds.start.y, bounds.end.y - 1),
+                location.z.clamp(bounds.start.z, bounds.end.z - 1),
+            ))),
+            OutOfBounds::Wrap => Ok(Some(Point3D::new(
+                wrap_axis(location.x, bounds.start.x, bounds.end.x),
+                wrap_axis(location.y, bounds.start.y, bounds.end.y),
+                wrap_axis(location.z, bounds.start.z, bounds.end.z),
+            ))),
+        }
+    }
+
+    /// Gets a voxel, failing instead of panicking if it is out of bounds
+    pub fn try_get_voxel(&mut self, location: GlobalLocation) -> Result<T> {
+        if self.resolve_structures {
+            if let Some(value) = self.structures.voxel_at(location) {
+                return Ok(value);
+            }
+        }
+        if let Some(value) = self.void.voxel(location) {
+            return Ok(value);
+        }
+        match self.resolve_location(location)? {
+            Some(location) => {
+                let chunk = self.get_chunk(Self::get_chunk_location(location))?;
+                Ok(chunk.get(Self::get_voxel_location(location)))
+            }
+            None => Ok(T::default()),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Dimension, GlobalLocation};
+
+/// A voxel that changed, stamped with the world tick and time it changed at
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ChangeEvent<T> {
+    pub tick: u64,
+    /// how long after the dimension was created the change happened, from a monotonic
+    /// clock so it never goes backwards
+    pub timestamp: Duration,
+    pub location: GlobalLocation,
+    pub old: T,
+    pub new: T,
+}
+
+/// Called on every voxel change, e.g. to forward it to a renderer
+pub type ChangeListener<T> = Arc<dyn Fn(&ChangeEvent<T>) + Send + Sync>;
+
+/// The world tick, and the latest changes kept so consumers that fell behind can catch up
+#[derive(Clone)]
+pub struct ChangeFeed<T> {
+    tick: u64,
+    started: Instant,
+    listener: Option<ChangeListener<T>>,
+    capacity: usize,
+    history: VecDeque<ChangeEvent<T>>,
+    /// the first tick whose changes are all still in the history
+    complete_from: u64,
+}
+
+impl<T: Copy> ChangeFeed<T> {
+    pub fn new() -> ChangeFeed<T> {
+        ChangeFeed {
+            tick: 0,
+            started: Instant::now(),
+            listener: None,
+            capacity: 0,
+            history: VecDeque::new(),
+            complete_from: 0,
+        }
+    }
+
+    pub fn record(&mut self, location: GlobalLocation, old: T, new: T) {
+        if self.capacity == 0 {
+            // a change that isn't kept, so its tick can't be caught up on
+            self.complete_from = self.tick + 1;
+            if self.listener.is_none() {
+                return;
+            }
+        }
+        let event = ChangeEvent {
+            tick: self.tick,
+            timestamp: self.started.elapsed(),
+            location,
+            old,
+            new,
+        };
+        if let Some(listener) = &self.listener {
+            listener(&event);
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.history.len() == self.capacity {
+            let dropped = self.history.pop_front().unwrap();
+            self.complete_from = self.complete_from.max(dropped.tick + 1);
+        }
+        self.history.push_back(event);
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The current world tick, which changes are stamped with
+    pub fn current_tick(&self) -> u64 {
+        self.changes.tick
+    }
+
+    /// Moves on to the next tick, returning it. Called by the game loop once per tick
+    pub fn advance_tick(&mut self) -> u64 {
+        self.changes.tick += 1;
+        self.decay_damage();
+        self.tick_weather();
+        self.changes.tick
+    }
+
+    pub fn set_change_listener(&mut self, listener: ChangeListener<T>) {
+        self.changes.listener = Some(listener);
+    }
+
+    pub fn clear_change_listener(&mut self) {
+        self.changes.listener = None;
+    }
+
+    /// Keeps the latest capacity changes for events_since. 0, the default, keeps none
+    pub fn set_change_history(&mut self, capacity: usize) {
+        let feed = &mut self.changes;
+        feed.capacity = capacity;
+        while feed.history.len() > capacity {
+            let dropped = feed.history.pop_front().unwrap();
+            feed.complete_from = feed.complete_from.max(dropped.tick + 1);
+        }
+    }
+
+    /// The changes made at tick and after, oldest first, for a consumer that has seen
+    /// every change before tick. None if the history doesn't reach back that far, and the
+    /// consumer has to resync from the world itself
+    pub fn events_since(&self, tick: u64) -> Option<Vec<ChangeEvent<T>>> {
+        let feed = &self.changes;
+        if tick < feed.complete_from {
+            return None;
+        }
+        Some(
+            feed.history
+                .iter()
+                .filter(|event| event.tick >= tick)
+                .copied()
+                .collect(),
+        )
+    }
+}
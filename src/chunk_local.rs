@@ -0,0 +1,101 @@
+use super::faces::Face;
+use super::{Chunk, Point3D, VoxelLocation, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE};
+
+const SIZE: Point3D = Point3D {
+    x: CHUNK_X_SIZE as i32,
+    y: CHUNK_Y_SIZE as i32,
+    z: CHUNK_Z_SIZE as i32,
+};
+
+/// Where a voxel lies in its chunk: how many of the chunk's faces it touches, and which.
+/// Faces are listed x first, then y, then z
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Boundary {
+    /// touches no face, so everything around it is in the same chunk
+    Interior,
+    Face(Face),
+    Edge(Face, Face),
+    Corner(Face, Face, Face),
+}
+
+impl Boundary {
+    /// The faces of the chunk the voxel touches, whose neighbors see it
+    pub fn faces(self) -> Vec<Face> {
+        match self {
+            Boundary::Interior => vec![],
+            Boundary::Face(face) => vec![face],
+            Boundary::Edge(a, b) => vec![a, b],
+            Boundary::Corner(a, b, c) => vec![a, b, c],
+        }
+    }
+}
+
+/// The locations from start up to end, x fastest
+fn between(start: VoxelLocation, end: VoxelLocation) -> impl Iterator<Item = VoxelLocation> {
+    (start.z..end.z).flat_map(move |z| {
+        (start.y..end.y).flat_map(move |y| (start.x..end.x).map(move |x| Point3D::new(x, y, z)))
+    })
+}
+
+impl Point3D {
+    /// Every voxel location of a chunk, in the order chunks store them
+    pub fn all() -> impl Iterator<Item = VoxelLocation> {
+        between(Point3D::new(0, 0, 0), SIZE)
+    }
+}
+
+/// The layer of voxel locations of a chunk that touch face
+pub fn boundary_voxels(face: Face) -> impl Iterator<Item = VoxelLocation> {
+    let (mut start, mut end) = (Point3D::new(0, 0, 0), SIZE);
+    match face {
+        Face::East => start.x = SIZE.x - 1,
+        Face::West => end.x = 1,
+        Face::North => start.y = SIZE.y - 1,
+        Face::South => end.y = 1,
+        Face::Up => start.z = SIZE.z - 1,
+        Face::Down => end.z = 1,
+    }
+    between(start, end)
+}
+
+/// The voxel locations of a chunk that touch none of its faces
+pub fn interior_voxels() -> impl Iterator<Item = VoxelLocation> {
+    between(Point3D::new(1, 1, 1), SIZE - Point3D::new(1, 1, 1))
+}
+
+/// Which faces of its chunk a voxel location touches
+pub fn classify(location: VoxelLocation) -> Boundary {
+    let side = |value: i32, size: i32, low: Face, high: Face| {
+        if value == 0 {
+            Some(low)
+        } else if value == size - 1 {
+            Some(high)
+        } else {
+            None
+        }
+    };
+    let faces = [
+        side(location.x, SIZE.x, Face::West, Face::East),
+        side(location.y, SIZE.y, Face::South, Face::North),
+        side(location.z, SIZE.z, Face::Down, Face::Up),
+    ];
+    let mut touching = faces.iter().flatten().copied();
+    match (touching.next(), touching.next(), touching.next()) {
+        (None, _, _) => Boundary::Interior,
+        (Some(a), None, _) => Boundary::Face(a),
+        (Some(a), Some(b), None) => Boundary::Edge(a, b),
+        (Some(a), Some(b), Some(c)) => Boundary::Corner(a, b, c),
+    }
+}
+
+impl<T> Chunk<T> {
+    /// The locations of the voxels of the chunk that touch face, like boundary_voxels
+    pub fn boundary_voxels(&self, face: Face) -> impl Iterator<Item = VoxelLocation> {
+        boundary_voxels(face)
+    }
+
+    /// The locations of the voxels of the chunk that touch none of its faces
+    pub fn interior_voxels(&self) -> impl Iterator<Item = VoxelLocation> {
+        interior_voxels()
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::error::{Error, Result};
+use super::metadata::{read_point, read_string, write_point, write_string};
+use super::spatial_index::{EntryId, SpatialIndex};
+use super::{Aabb, GlobalLocation};
+
+/// Decides whether an edit at a location inside a claim is allowed
+pub type Permission = Arc<dyn Fn(&Claim, GlobalLocation) -> bool + Send + Sync>;
+
+/// What happens to a batch of edits when some of them fall inside a claim
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClaimPolicy {
+    /// The whole batch is rejected
+    Reject,
+    /// Only the protected edits are dropped
+    Filter,
+}
+
+/// A named box of the world that is protected from edits
+#[derive(Clone)]
+pub struct Claim {
+    pub name: String,
+    pub bounds: Aabb,
+}
+
+/// All the protected regions of a dimension
+#[derive(Clone)]
+pub struct Claims {
+    /// the id of each claim in the index, by name
+    names: HashMap<String, EntryId>,
+    index: SpatialIndex<Claim>,
+    /// consulted for edits inside claims, if absent every such edit is denied
+    permission: Option<Permission>,
+    policy: ClaimPolicy,
+}
+
+/// The size of the cells claims are bucketed into
+const CLAIM_CELL_SIZE: u32 = 64;
+
+impl Claims {
+    pub fn new() -> Claims {
+        Claims {
+            names: HashMap::new(),
+            index: SpatialIndex::new(CLAIM_CELL_SIZE),
+            permission: None,
+            policy: ClaimPolicy::Reject,
+        }
+    }
+
+    /// Protects a box, replacing any claim with the same name
+    pub fn claim(&mut self, name: &str, bounds: Aabb) {
+        self.unclaim(name);
+        let id = self.index.insert(
+            bounds,
+            Claim {
+                name: String::from(name),
+                bounds,
+            },
+        );
+        self.names.insert(String::from(name), id);
+    }
+
+    /// Removes a claim, returning false if it didn't exist
+    pub fn unclaim(&mut self, name: &str) -> bool {
+        match self.names.remove(name) {
+            Some(id) => self.index.remove(id).is_some(),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Claim> {
+        self.names
+            .get(name)
+            .and_then(|id| self.index.get(*id))
+            .map(|(_, claim)| claim)
+    }
+
+    /// Every claim covering the location
+    pub fn claims_at(&self, location: GlobalLocation) -> Vec<&Claim> {
+        self.index
+            .query_point(location)
+            .into_iter()
+            .map(|id| &self.index.get(id).unwrap().1)
+            .collect()
+    }
+
+    pub fn set_permission(&mut self, permission: Permission) {
+        self.permission = Some(permission);
+    }
+
+    /// Goes back to denying every edit inside a claim
+    pub fn clear_permission(&mut self) {
+        self.permission = None;
+    }
+
+    pub fn set_policy(&mut self, policy: ClaimPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn policy(&self) -> ClaimPolicy {
+        self.policy
+    }
+
+    /// Checks an edit at location against every claim covering it
+    pub fn check(&self, location: GlobalLocation) -> Result<()> {
+        for claim in self.claims_at(location) {
+            let allowed = match &self.permission {
+                Some(permission) => permission(claim, location),
+                None => false,
+            };
+            if !allowed {
+                return Err(Error::Protected {
+                    claim: claim.name.clone(),
+                    location,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the claims (but not the permission callback) for the world metadata
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut names: Vec<&String> = self.names.keys().collect();
+        names.sort();
+        out.write_u8(match self.policy {
+            ClaimPolicy::Reject => 0,
+            ClaimPolicy::Filter => 1,
+        })?;
+        out.write_u32::<LittleEndian>(names.len() as u32)?;
+        for name in names {
+            let claim = self.get(name).unwrap();
+            write_string(out, &claim.name)?;
+            write_point(out, claim.bounds.start)?;
+            write_point(out, claim.bounds.end)?;
+        }
+        Ok(())
+    }
+
+    /// Reads claims written by write_to
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Claims> {
+        let mut claims = Claims::new();
+        claims.policy = match input.read_u8()? {
+            0 => ClaimPolicy::Reject,
+            1 => ClaimPolicy::Filter,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "bad claim policy",
+                ))
+            }
+        };
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let name = read_string(input)?;
+            let start = read_point(input)?;
+            let end = read_point(input)?;
+            claims.claim(&name, Aabb::new(start, end));
+        }
+        Ok(claims)
+    }
+}
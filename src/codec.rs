@@ -0,0 +1,121 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use twox_hash::XxHash64;
+
+use super::occupancy::ChunkSummary;
+use super::occupancy::VoxelClass;
+use super::storage::ChunkStorage;
+use super::{Chunk, DataSegment, Voxel};
+
+/// Voxel types that can be written to and read back from bytes
+pub trait VoxelCodec: Sized {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(input: &mut R) -> io::Result<Self>;
+}
+
+impl VoxelCodec for u8 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u8(*self)
+    }
+
+    fn decode<R: Read>(input: &mut R) -> io::Result<u8> {
+        input.read_u8()
+    }
+}
+
+impl VoxelCodec for u16 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u16::<LittleEndian>(*self)
+    }
+
+    fn decode<R: Read>(input: &mut R) -> io::Result<u16> {
+        input.read_u16::<LittleEndian>()
+    }
+}
+
+impl VoxelCodec for u32 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u32::<LittleEndian>(*self)
+    }
+
+    fn decode<R: Read>(input: &mut R) -> io::Result<u32> {
+        input.read_u32::<LittleEndian>()
+    }
+}
+
+/// An optional data segment is a presence byte followed by the data if present
+fn encode_data_segment<W: Write>(out: &mut W, segment: &Option<DataSegment>) -> io::Result<()> {
+    match segment {
+        Some(segment) => {
+            out.write_u8(1)?;
+            out.write_all(&segment.data)
+        }
+        None => out.write_u8(0),
+    }
+}
+
+fn decode_data_segment<R: Read>(input: &mut R) -> io::Result<Option<DataSegment>> {
+    match input.read_u8()? {
+        0 => Ok(None),
+        1 => {
+            let mut segment = DataSegment::new();
+            input.read_exact(&mut segment.data)?;
+            Ok(Some(segment))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bad data segment marker",
+        )),
+    }
+}
+
+impl VoxelCodec for Voxel {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u32::<LittleEndian>(self.id)?;
+        out.write_u8(self.shape)?;
+        encode_data_segment(out, &self.extra_data)
+    }
+
+    fn decode<R: Read>(input: &mut R) -> io::Result<Voxel> {
+        Ok(Voxel {
+            id: input.read_u32::<LittleEndian>()?,
+            shape: input.read_u8()?,
+            extra_data: decode_data_segment(input)?,
+        })
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Chunk<T> {
+    /// The chunk as uncompressed bytes: its extra data, its voxels in the form they are
+    /// stored in, and the summary of its voxels
+    pub fn encode_payload(&self) -> io::Result<Vec<u8>> {
+        let mut payload = Vec::new();
+        encode_data_segment(&mut payload, &self.extra_data)?;
+        self.voxels.write_to(&mut payload)?;
+        self.summary.write_to(&mut payload)?;
+        Ok(payload)
+    }
+
+    /// A hash of the chunk's voxels and extra data, the same on every run and platform and
+    /// however the voxels are stored, so caches of things derived from the chunk can be
+    /// keyed on its content
+    pub fn content_hash(&self) -> u64 {
+        // writing into a Vec can't fail
+        let mut content = Vec::new();
+        encode_data_segment(&mut content, &self.extra_data).unwrap();
+        for voxel in self.voxels.to_vec() {
+            voxel.encode(&mut content).unwrap();
+        }
+        XxHash64::oneshot(0, &content)
+    }
+
+    /// Reads a chunk back from encode_payload's output
+    pub fn decode_payload(mut payload: &[u8]) -> io::Result<Chunk<T>> {
+        let mut chunk =
+            Chunk::from_value_with_extra_data(T::default(), decode_data_segment(&mut payload)?);
+        chunk.voxels = ChunkStorage::read_from(&mut payload)?;
+        chunk.summary = ChunkSummary::read_from(&mut payload)?;
+        Ok(chunk)
+    }
+}
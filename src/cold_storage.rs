@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::error::{Error, ErrorContext, Operation, Result, ResultExt};
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::region::RegionFile;
+use super::store::{chunk_file_name, ChunkStore, FolderStore};
+use super::tombstones::now;
+use super::{ChunkLocation, Dimension};
+
+/// Somewhere chunks nobody has touched in a long time are moved to, like a slower disk or
+/// an object store, to keep the disk cache small. Chunks are kept as the records the
+/// region files hold, so they stay compressed and encrypted as they were
+pub trait ColdStore: Send + Sync {
+    fn put(&self, location: ChunkLocation, record: &[u8]) -> Result<()>;
+
+    /// The record of a chunk, None if the store doesn't have it
+    fn get(&self, location: ChunkLocation) -> Result<Option<Vec<u8>>>;
+
+    fn remove(&self, location: ChunkLocation) -> Result<()>;
+}
+
+/// A folder of its own can be a cold store
+impl ColdStore for FolderStore {
+    fn put(&self, location: ChunkLocation, record: &[u8]) -> Result<()> {
+        self.store(location, record)
+    }
+
+    fn get(&self, location: ChunkLocation) -> Result<Option<Vec<u8>>> {
+        self.fetch(location)
+    }
+
+    fn remove(&self, location: ChunkLocation) -> Result<()> {
+        match fs::remove_file(self.folder().join(chunk_file_name(location))) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// When chunks are moved to cold storage
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ColdStoragePolicy {
+    /// how long a chunk goes without being loaded or saved before it is moved
+    pub cold_after: Duration,
+    /// the most chunks moved by one call to migrate_cold_chunks, so a flush never stalls
+    /// on a backlog
+    pub batch_limit: usize,
+}
+
+impl Default for ColdStoragePolicy {
+    fn default() -> ColdStoragePolicy {
+        ColdStoragePolicy {
+            cold_after: Duration::from_secs(30 * 24 * 60 * 60),
+            batch_limit: 256,
+        }
+    }
+}
+
+/// Which chunks are in cold storage, and when the others were last used
+#[derive(Clone, Default)]
+pub struct ColdTier {
+    store: Option<Arc<dyn ColdStore>>,
+    policy: ColdStoragePolicy,
+    /// when each chunk was last loaded or saved, in seconds since the unix epoch
+    last_used: HashMap<ChunkLocation, u64>,
+    cold: HashSet<ChunkLocation>,
+}
+
+impl ColdTier {
+    pub fn new() -> ColdTier {
+        ColdTier::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cold.is_empty() && self.last_used.is_empty()
+    }
+
+    /// Writes the cold chunks, and when the others were last used unless saves must come out
+    /// the same for the same content
+    pub fn write_to<W: Write>(&self, out: &mut W, with_times: bool) -> io::Result<()> {
+        let mut cold: Vec<&ChunkLocation> = self.cold.iter().collect();
+        cold.sort_by_key(|location| (location.z, location.y, location.x));
+        out.write_u32::<LittleEndian>(cold.len() as u32)?;
+        for location in cold {
+            write_point(out, *location)?;
+        }
+        let mut last_used: Vec<(&ChunkLocation, &u64)> = if with_times {
+            self.last_used.iter().collect()
+        } else {
+            Vec::new()
+        };
+        last_used.sort_by_key(|(location, _)| (location.z, location.y, location.x));
+        out.write_u32::<LittleEndian>(last_used.len() as u32)?;
+        for (location, time) in last_used {
+            write_point(out, *location)?;
+            out.write_u64::<LittleEndian>(*time)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the cold chunks and times back into the tier, keeping its store and policy
+    pub fn read_from<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        self.cold.clear();
+        self.last_used.clear();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            self.cold.insert(read_point(input)?);
+        }
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let location = read_point(input)?;
+            self.last_used
+                .insert(location, input.read_u64::<LittleEndian>()?);
+        }
+        Ok(())
+    }
+
+    pub fn cold_chunks(&self) -> impl Iterator<Item = &ChunkLocation> {
+        self.cold.iter()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Moves chunks that go unused for as long as the policy says into a cold store. Cold
+    /// chunks are moved back into the disk cache as soon as they're loaded again
+    pub fn set_cold_storage(&mut self, store: Arc<dyn ColdStore>, policy: ColdStoragePolicy) {
+        self.cold_storage.store = Some(store);
+        self.cold_storage.policy = policy;
+    }
+
+    /// Stops moving chunks into cold storage. The chunks already there can only be loaded
+    /// again once a store holding them is set
+    pub fn clear_cold_storage(&mut self) {
+        self.cold_storage.store = None;
+    }
+
+    pub fn cold_storage_policy(&self) -> ColdStoragePolicy {
+        self.cold_storage.policy
+    }
+
+    pub fn is_cold(&self, location: ChunkLocation) -> bool {
+        self.cold_storage.cold.contains(&location)
+    }
+
+    /// Notes that a chunk was just used, putting off its move to cold storage
+    pub fn touch_cold_timer(&mut self, location: ChunkLocation) {
+        if self.cold_storage.store.is_some() {
+            self.cold_storage.last_used.insert(location, now());
+        }
+    }
+
+    /// Moves the chunks unused for longer than the policy allows into the cold store,
+    /// returning how many were moved. Only saved chunks that aren't loaded are moved. Called
+    /// on every flush
+    pub fn migrate_cold_chunks(&mut self) -> Result<usize> {
+        let store = match &self.cold_storage.store {
+            Some(store) => Arc::clone(store),
+            None => return Ok(0),
+        };
+        let policy = self.cold_storage.policy;
+        let now = now();
+        let mut candidates: Vec<(u64, ChunkLocation)> = Vec::new();
+        for location in self.all_chunk_locations.iter() {
+            if self.is_cold(*location)
+                || self.chunk_loaded(*location)
+                || self.chunk_dirty(*location)
+            {
+                continue;
+            }
+            // chunks saved before the tier was set start their clock now
+            let last_used = *self.cold_storage.last_used.entry(*location).or_insert(now);
+            if now.saturating_sub(last_used) >= policy.cold_after.as_secs() {
+                candidates.push((last_used, *location));
+            }
+        }
+        // the longest unused first
+        candidates
+            .sort_by_key(|(last_used, location)| (*last_used, location.z, location.y, location.x));
+        candidates.truncate(policy.batch_limit);
+
+        let mut moved = 0;
+        for (_, location) in candidates {
+            let path = match self.region_path(location) {
+                Some(path) if path.exists() => path,
+                _ => continue,
+            };
+            let context = || {
+                ErrorContext::new(Operation::MigrateChunk)
+                    .chunk(location)
+                    .path(&path)
+            };
+            let mut region = RegionFile::open(&path).with_context(context)?;
+            let slot = RegionFile::slot(location);
+            let record = match region.read(slot).with_context(context)? {
+                Some(record) => record,
+                None => continue,
+            };
+            // put away before it is removed, so a crash in between leaves it in both places
+            store.put(location, &record).with_context(context)?;
+            region.remove(slot).with_context(context)?;
+            self.cold_storage.cold.insert(location);
+            self.cold_storage.last_used.remove(&location);
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    /// Moves a cold chunk back into the disk cache, returning false if it wasn't cold.
+    /// Loading a cold chunk does this on its own
+    pub fn promote_chunk(&mut self, location: ChunkLocation) -> Result<bool> {
+        if !self.is_cold(location) {
+            return Ok(false);
+        }
+        let context = || ErrorContext::new(Operation::PromoteChunk).chunk(location);
+        let store = match &self.cold_storage.store {
+            Some(store) => Arc::clone(store),
+            None => return Err(Error::NoColdStore).with_context(context),
+        };
+        let path = self.region_path(location).ok_or(Error::NoDiskCache)?;
+        let record = store
+            .get(location)
+            .with_context(context)?
+            .ok_or(Error::UndefinedChunk { chunk: location })
+            .with_context(context)?;
+        fs::create_dir_all(path.parent().unwrap()).with_context(context)?;
+        RegionFile::open(&path)
+            .and_then(|mut region| region.write(RegionFile::slot(location), &record))
+            .with_context(|| context().path(&path))?;
+        store.remove(location).with_context(context)?;
+        self.cold_storage.cold.remove(&location);
+        self.touch_cold_timer(location);
+        Ok(true)
+    }
+
+    /// Deletes a cold chunk from the cold store, for when the chunk is deleted
+    pub fn delete_cold_chunk(&mut self, location: ChunkLocation) -> Result<()> {
+        self.cold_storage.last_used.remove(&location);
+        if !self.cold_storage.cold.remove(&location) {
+            return Ok(());
+        }
+        match &self.cold_storage.store {
+            Some(store) => store
+                .remove(location)
+                .with_context(|| ErrorContext::new(Operation::DeleteChunk).chunk(location)),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use rapier3d::math::Vector;
+use rapier3d::prelude::{
+    ColliderBuilder, ColliderHandle, ColliderSet, IslandManager, RigidBodySet,
+};
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Aabb, Chunk, ChunkLocation, Dimension, GlobalLocation, Point3D, VoxelRead};
+
+/// Covers the solid voxels from start up to end with as few boxes as greedily possible.
+/// Each box grows along x first, then y, then z, as far as it stays solid, so flat
+/// terrain turns into a handful of slabs
+pub fn merge_solid_boxes<T: VoxelClass, M: VoxelRead<T> + ?Sized>(
+    map: &M,
+    start: GlobalLocation,
+    end: GlobalLocation,
+) -> Vec<Aabb> {
+    let size = end - start;
+    if size.x <= 0 || size.y <= 0 || size.z <= 0 {
+        return Vec::new();
+    }
+    let index = |x: i32, y: i32, z: i32| ((z * size.y + y) * size.x + x) as usize;
+    let mut open = vec![false; (size.x * size.y * size.z) as usize];
+    for z in 0..size.z {
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let location = start + Point3D::new(x, y, z);
+                open[index(x, y, z)] = map.within_bounds(location) && map.get(location).is_solid();
+            }
+        }
+    }
+
+    let mut boxes = Vec::new();
+    for z in 0..size.z {
+        for y in 0..size.y {
+            for x in 0..size.x {
+                if !open[index(x, y, z)] {
+                    continue;
+                }
+                let mut x_end = x + 1;
+                while x_end < size.x && open[index(x_end, y, z)] {
+                    x_end += 1;
+                }
+                let mut y_end = y + 1;
+                while y_end < size.y && (x..x_end).all(|x| open[index(x, y_end, z)]) {
+                    y_end += 1;
+                }
+                let mut z_end = z + 1;
+                while z_end < size.z
+                    && (y..y_end).all(|y| (x..x_end).all(|x| open[index(x, y, z_end)]))
+                {
+                    z_end += 1;
+                }
+                for z in z..z_end {
+                    for y in y..y_end {
+                        for x in x..x_end {
+                            open[index(x, y, z)] = false;
+                        }
+                    }
+                }
+                boxes.push(Aabb::new(
+                    start + Point3D::new(x, y, z),
+                    start + Point3D::new(x_end, y_end, z_end),
+                ));
+            }
+        }
+    }
+    boxes
+}
+
+/// A fixed cuboid collider filling a box of voxels
+pub fn box_collider(bounds: Aabb) -> ColliderBuilder {
+    let size = bounds.end - bounds.start;
+    let half = Vector::new(size.x as f32, size.y as f32, size.z as f32) / 2.0;
+    let center = Vector::new(
+        bounds.start.x as f32,
+        bounds.start.y as f32,
+        bounds.start.z as f32,
+    ) + half;
+    ColliderBuilder::cuboid(half.x, half.y, half.z).translation(center)
+}
+
+/// The colliders of a chunk, along with the content they were built from
+struct ChunkEntry {
+    hash: u64,
+    handles: Vec<ColliderHandle>,
+}
+
+/// Keeps a rapier collider set in step with the solid voxels of a dimension, one set of
+/// merged box colliders per chunk. Only chunks whose content changed are rebuilt
+#[derive(Default)]
+pub struct VoxelColliders {
+    chunks: HashMap<ChunkLocation, ChunkEntry>,
+}
+
+impl VoxelColliders {
+    pub fn new() -> VoxelColliders {
+        VoxelColliders::default()
+    }
+
+    /// The colliders currently standing in for a chunk
+    pub fn chunk_colliders(&self, location: ChunkLocation) -> &[ColliderHandle] {
+        self.chunks
+            .get(&location)
+            .map_or(&[], |entry| &entry.handles[..])
+    }
+
+    /// Brings the colliders of the given chunks up to date: chunks that changed since they
+    /// were last synced are rebuilt, and chunks that are no longer loaded lose theirs.
+    /// Returns how many chunks were rebuilt or removed
+    pub fn sync<
+        T: Copy + Default + VoxelClass + VoxelCodec,
+        I: IntoIterator<Item = ChunkLocation>,
+    >(
+        &mut self,
+        dimension: &Dimension<T>,
+        chunks: I,
+        colliders: &mut ColliderSet,
+        islands: &mut IslandManager,
+        bodies: &mut RigidBodySet,
+    ) -> usize {
+        let mut changed = 0;
+        for location in chunks {
+            let hash = dimension
+                .loaded_chunks
+                .get(&location)
+                .map(Chunk::content_hash);
+            if self.chunks.get(&location).map(|entry| entry.hash) == hash {
+                continue;
+            }
+            changed += 1;
+            self.remove_chunk(location, colliders, islands, bodies);
+            let hash = match hash {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let bounds = Dimension::<T>::get_chunk_bounds(location);
+            let handles = merge_solid_boxes(dimension, bounds.start, bounds.end)
+                .into_iter()
+                .map(|bounds| colliders.insert(box_collider(bounds)))
+                .collect();
+            self.chunks.insert(location, ChunkEntry { hash, handles });
+        }
+        changed
+    }
+
+    /// Syncs every loaded chunk, and drops the colliders of the chunks unloaded since
+    pub fn sync_loaded<T: Copy + Default + VoxelClass + VoxelCodec>(
+        &mut self,
+        dimension: &Dimension<T>,
+        colliders: &mut ColliderSet,
+        islands: &mut IslandManager,
+        bodies: &mut RigidBodySet,
+    ) -> usize {
+        let mut chunks: Vec<ChunkLocation> = dimension.loaded_chunks.keys().copied().collect();
+        chunks.extend(
+            self.chunks
+                .keys()
+                .filter(|location| !dimension.chunk_loaded(**location)),
+        );
+        self.sync(dimension, chunks, colliders, islands, bodies)
+    }
+
+    /// Removes a chunk's colliders from the set
+    pub fn remove_chunk(
+        &mut self,
+        location: ChunkLocation,
+        colliders: &mut ColliderSet,
+        islands: &mut IslandManager,
+        bodies: &mut RigidBodySet,
+    ) {
+        if let Some(entry) = self.chunks.remove(&location) {
+            for handle in entry.handles {
+                colliders.remove(handle, islands, bodies, true);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{self, Read, Write};
+
+use twox_hash::XxHash64;
+
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::Dimension;
+
+/// The zstd level chunk payloads are compressed with
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The hash records name a dictionary by. 0 stands for no dictionary
+pub fn hash_dictionary(dictionary: Option<&[u8]>) -> u64 {
+    dictionary.map_or(0, |dictionary| XxHash64::oneshot(0, dictionary))
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Trains a zstd dictionary of at most dictionary_size bytes over up to max_samples of
+    /// the loaded chunks, and keeps it in the world metadata. Small chunk payloads have
+    /// too little data to compress well on their own, but share a lot of structure with
+    /// each other, which the dictionary captures. The dictionary it replaces is kept for
+    /// reading the chunks already compressed with it
+    pub fn train_dictionary(&mut self, max_samples: usize, dictionary_size: usize) -> Result<()> {
+        let mut locations: Vec<_> = self.loaded_chunks.keys().copied().collect();
+        locations.sort_by_key(|location| (location.z, location.y, location.x));
+        // spread the samples evenly over the world rather than taking one corner
+        let step = std::cmp::max(1, locations.len() / std::cmp::max(1, max_samples));
+        let mut samples = Vec::new();
+        for location in locations.iter().step_by(step).take(max_samples) {
+            samples.push(self.loaded_chunks[location].encode_payload()?);
+        }
+        let dictionary = zstd::dict::from_samples(&samples, dictionary_size)?;
+        self.retire_dictionary();
+        self.retired_dictionaries
+            .remove(&hash_dictionary(Some(&dictionary)));
+        self.compression_dictionary = Some(dictionary);
+        Ok(())
+    }
+
+    /// Stops compressing with the trained dictionary. It is kept for reading the chunks
+    /// already compressed with it
+    pub fn clear_dictionary(&mut self) {
+        self.retire_dictionary();
+    }
+
+    /// Moves the current dictionary to the ones kept for reading old records. Cached
+    /// generated chunks were compressed with it, so they are dropped
+    fn retire_dictionary(&mut self) {
+        if let Some(dictionary) = self.compression_dictionary.take() {
+            self.retired_dictionaries
+                .insert(hash_dictionary(Some(&dictionary)), dictionary);
+            if let Some(cache) = &mut self.generation_cache {
+                cache.clear();
+            }
+        }
+    }
+
+    /// The dictionaries chunks were compressed with before the current one, by hash
+    pub fn retired_dictionaries(&self) -> impl Iterator<Item = (&u64, &Vec<u8>)> {
+        self.retired_dictionaries.iter()
+    }
+
+    pub fn compression_dictionary(&self) -> Option<&[u8]> {
+        self.compression_dictionary.as_deref()
+    }
+
+    /// Compresses a chunk payload, using the trained dictionary if there is one
+    pub fn compress_payload(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        let dictionary = self.compression_dictionary.as_deref().unwrap_or(&[]);
+        let mut encoder =
+            zstd::stream::Encoder::with_dictionary(Vec::new(), COMPRESSION_LEVEL, dictionary)?;
+        encoder.write_all(payload)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Decompresses a payload compressed by compress_payload with the same dictionary
+    pub fn decompress_payload(&self, compressed: &[u8]) -> Result<Vec<u8>> {
+        let hash = hash_dictionary(self.compression_dictionary.as_deref());
+        self.decompress_payload_with(compressed, hash)
+    }
+
+    /// Decompresses a payload compressed with the dictionary of the given hash, the current
+    /// one or one it replaced
+    pub fn decompress_payload_with(&self, compressed: &[u8], hash: u64) -> Result<Vec<u8>> {
+        let current = self.compression_dictionary.as_deref();
+        let dictionary: &[u8] = if hash == hash_dictionary(current) {
+            current.unwrap_or(&[])
+        } else if hash == 0 {
+            &[]
+        } else {
+            match self.retired_dictionaries.get(&hash) {
+                Some(dictionary) => dictionary,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "chunk was compressed with an unknown dictionary",
+                    )
+                    .into())
+                }
+            }
+        };
+        let mut decoder = zstd::stream::Decoder::with_dictionary(compressed, dictionary)?;
+        let mut payload = Vec::new();
+        decoder.read_to_end(&mut payload)?;
+        Ok(payload)
+    }
+}
+
+/// Checks that a dictionary stored in the metadata is one zstd can use
+pub fn validate_dictionary(dictionary: &[u8]) -> io::Result<()> {
+    zstd::stream::Decoder::with_dictionary(&[][..], dictionary).map(|_| ())
+}
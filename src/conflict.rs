@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::codec::VoxelCodec;
+use super::edit::Edit;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::{ChunkLocation, Dimension, GlobalLocation};
+
+/// Identifies one of the parties editing a world concurrently
+pub type WriterId = u32;
+
+/// A point in world time, as counted by the writers
+pub type Tick = u64;
+
+/// The latest tick seen from each writer
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct VersionVector {
+    versions: BTreeMap<WriterId, Tick>,
+}
+
+impl VersionVector {
+    pub fn new() -> VersionVector {
+        VersionVector::default()
+    }
+
+    /// The latest tick seen from a writer, 0 if none
+    pub fn get(&self, writer: WriterId) -> Tick {
+        self.versions.get(&writer).copied().unwrap_or(0)
+    }
+
+    /// Records a write by writer at tick
+    pub fn observe(&mut self, writer: WriterId, tick: Tick) {
+        let version = self.versions.entry(writer).or_insert(0);
+        *version = (*version).max(tick);
+    }
+
+    /// Takes the newest version from each writer of both vectors
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (writer, tick) in other.versions.iter() {
+            self.observe(*writer, *tick);
+        }
+    }
+
+    /// If this vector has seen everything the other has
+    pub fn dominates(&self, other: &VersionVector) -> bool {
+        other
+            .versions
+            .iter()
+            .all(|(writer, tick)| self.get(*writer) >= *tick)
+    }
+
+    /// If neither vector has seen everything the other has, meaning both sides made edits
+    /// the other doesn't know about
+    pub fn concurrent_with(&self, other: &VersionVector) -> bool {
+        !self.dominates(other) && !other.dominates(self)
+    }
+}
+
+/// An edit made by one of several writers
+#[derive(Copy, Clone)]
+pub struct RemoteEdit<T> {
+    pub writer: WriterId,
+    pub tick: Tick,
+    pub edit: Edit<T>,
+}
+
+/// Decides the value of a voxel written by two different writers, given the current write
+/// and the incoming one
+pub type MergeFn<T> = Arc<dyn Fn(&RemoteEdit<T>, &RemoteEdit<T>) -> T + Send + Sync>;
+
+/// How two writes to the same voxel by different writers are reconciled
+#[derive(Clone)]
+pub enum ResolutionPolicy<T> {
+    /// The write with the later tick wins, ties broken by the larger writer id
+    LastWriterWins,
+    /// A callback decides the resulting value
+    Custom(MergeFn<T>),
+}
+
+/// Merges the edit streams of several writers deterministically. Every voxel keeps the
+/// (tick, writer) of its newest write across calls, so under LastWriterWins replicas that
+/// feed the same edits through a resolver end up with the same voxels whatever order the
+/// edits arrived in, even over several calls. A Custom merge is handed writes in the order
+/// they are resolved, so it only gets the same writes in any arrival order within a single
+/// merge_into call
+#[derive(Clone)]
+pub struct ConflictResolver<T> {
+    policy: ResolutionPolicy<T>,
+    /// the winning write of every voxel written so far, stamped with the newest (tick,
+    /// writer) seen for the voxel
+    last_writes: HashMap<GlobalLocation, RemoteEdit<T>>,
+    /// the writes each chunk has seen
+    chunk_versions: HashMap<ChunkLocation, VersionVector>,
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> ConflictResolver<T> {
+    pub fn new(policy: ResolutionPolicy<T>) -> ConflictResolver<T> {
+        ConflictResolver {
+            policy,
+            last_writes: HashMap::new(),
+            chunk_versions: HashMap::new(),
+        }
+    }
+
+    /// The writes a chunk has seen, so replicas can tell whether they are out of date
+    pub fn chunk_version(&self, location: ChunkLocation) -> Option<&VersionVector> {
+        self.chunk_versions.get(&location)
+    }
+
+    /// Resolves an incoming edit against the last write to the same voxel, returning the
+    /// edit that should be applied, or None if the incoming edit loses
+    pub fn resolve(&mut self, incoming: RemoteEdit<T>) -> Option<Edit<T>> {
+        let location = incoming.edit.location;
+        self.chunk_versions
+            .entry(Dimension::<T>::get_chunk_location(location))
+            .or_default()
+            .observe(incoming.writer, incoming.tick);
+
+        let resolved = match self.last_writes.get(&location) {
+            Some(current) if current.writer != incoming.writer => match &self.policy {
+                ResolutionPolicy::LastWriterWins => {
+                    if (incoming.tick, incoming.writer) < (current.tick, current.writer) {
+                        return None;
+                    }
+                    incoming
+                }
+                ResolutionPolicy::Custom(merge) => {
+                    // the merged write keeps the newer stamp, so a late edit can't make
+                    // older ones win again
+                    let newest =
+                        if (incoming.tick, incoming.writer) > (current.tick, current.writer) {
+                            incoming
+                        } else {
+                            *current
+                        };
+                    RemoteEdit {
+                        writer: newest.writer,
+                        tick: newest.tick,
+                        edit: Edit {
+                            location,
+                            value: merge(current, &incoming),
+                        },
+                    }
+                }
+            },
+            Some(current) if incoming.tick < current.tick => return None,
+            _ => incoming,
+        };
+        self.last_writes.insert(location, resolved);
+        Some(resolved.edit)
+    }
+
+    /// Merges edit streams from several writers into a dimension. Edits are resolved in
+    /// (tick, writer) order so the result doesn't depend on the order of the streams
+    /// within the call, and are applied through the edit path, so claims still apply.
+    /// Returns the edits the claims filtered out
+    pub fn merge_into(
+        &mut self,
+        dimension: &mut Dimension<T>,
+        streams: Vec<Vec<RemoteEdit<T>>>,
+    ) -> Result<Vec<Edit<T>>> {
+        let mut edits: Vec<RemoteEdit<T>> = streams.into_iter().flatten().collect();
+        // stable, so edits from one writer at the same tick keep their order
+        edits.sort_by_key(|edit| (edit.tick, edit.writer));
+        let winners: Vec<Edit<T>> = edits
+            .into_iter()
+            .filter_map(|edit| self.resolve(edit))
+            .collect();
+        dimension.apply_edits(winners)
+    }
+
+    /// Forgets the write history of a chunk, e.g. once all writers have acknowledged it
+    pub fn forget_chunk(&mut self, location: ChunkLocation) {
+        self.chunk_versions.remove(&location);
+        self.last_writes
+            .retain(|voxel, _| Dimension::<T>::get_chunk_location(*voxel) != location);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+use super::pathfinding::{find_path, Flyer};
+use super::registry::VoxelRegistry;
+use super::{Dimension, GlobalLocation, Point3D, Volume, Voxel};
+
+const HELP: &str = "commands:
+  get <x> <y> <z>                              the voxel at a location
+  set <x> <y> <z> <type>                       sets the voxel at a location
+  fill box <x> <y> <z> <x> <y> <z> <type>      fills the box between two corners
+  fill sphere <x> <y> <z> <radius> <type>      fills the ball around a center
+  point <name> <x> <y> <z>                     names a location for later commands
+  points                                       lists the named locations
+  path <from> <to>                             a flying path between two named points
+  help                                         this text
+types are given by name or id";
+
+/// Turns text commands like "set 10 20 30 stone" into changes to a world, for developer
+/// tools and server consoles. Named points are kept between commands
+#[derive(Clone, Default)]
+pub struct Console {
+    points: BTreeMap<String, GlobalLocation>,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Console::default()
+    }
+
+    pub fn point(&self, name: &str) -> Option<GlobalLocation> {
+        self.points.get(name).copied()
+    }
+
+    /// Runs one command, returning what it printed. Errors are printed too, rather than
+    /// returned, since they're for whoever typed the command
+    pub fn run(&mut self, dimension: &mut Dimension<Voxel>, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match self.execute(dimension, &words) {
+            Ok(output) => output,
+            Err(message) => format!("error: {}", message),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        dimension: &mut Dimension<Voxel>,
+        words: &[&str],
+    ) -> Result<String, String> {
+        match words {
+            [] => Ok(String::new()),
+            ["help"] => Ok(String::from(HELP)),
+            ["get", x, y, z] => {
+                let location = parse_location(x, y, z)?;
+                let voxel = dimension
+                    .try_get_voxel(location)
+                    .map_err(|err| err.to_string())?;
+                Ok(type_name(dimension, voxel.id))
+            }
+            ["set", x, y, z, voxel_type] => {
+                let location = parse_location(x, y, z)?;
+                let voxel = parse_voxel(dimension, voxel_type)?;
+                dimension
+                    .write_voxel(location, voxel)
+                    .map_err(|err| err.to_string())?;
+                Ok(String::from("set 1 voxel"))
+            }
+            ["fill", "box", x1, y1, z1, x2, y2, z2, voxel_type] => {
+                let a = parse_location(x1, y1, z1)?;
+                let b = parse_location(x2, y2, z2)?;
+                let voxel = parse_voxel(dimension, voxel_type)?;
+                let start = Point3D::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+                let end =
+                    Point3D::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)) + Point3D::new(1, 1, 1);
+                let volume = Volume::new(start, end, voxel);
+                dimension
+                    .set_volume(&volume)
+                    .map_err(|err| err.to_string())?;
+                Ok(format!("set {} voxels", volume.voxels.len()))
+            }
+            ["fill", "sphere", x, y, z, radius, voxel_type] => {
+                let center = parse_location(x, y, z)?;
+                let radius: i32 = parse_number(radius)?;
+                let voxel = parse_voxel(dimension, voxel_type)?;
+                let mut count = 0;
+                for dz in -radius..=radius {
+                    for dy in -radius..=radius {
+                        for dx in -radius..=radius {
+                            if dx * dx + dy * dy + dz * dz > radius * radius {
+                                continue;
+                            }
+                            dimension
+                                .write_voxel(center + Point3D::new(dx, dy, dz), voxel)
+                                .map_err(|err| err.to_string())?;
+                            count += 1;
+                        }
+                    }
+                }
+                Ok(format!("set {} voxels", count))
+            }
+            ["point", name, x, y, z] => {
+                let location = parse_location(x, y, z)?;
+                self.points.insert(String::from(*name), location);
+                Ok(format!("{} is at {}", name, show_location(location)))
+            }
+            ["points"] => Ok(self
+                .points
+                .iter()
+                .map(|(name, location)| format!("{} {}", name, show_location(*location)))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["path", from, to] => {
+                let start = self.named_point(from)?;
+                let goal = self.named_point(to)?;
+                match find_path(&*dimension, start, goal, &Flyer) {
+                    Some(path) => Ok(format!(
+                        "{} steps: {}",
+                        path.len().saturating_sub(1),
+                        path.iter()
+                            .map(|location| show_location(*location))
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    )),
+                    None => Ok(String::from("no path")),
+                }
+            }
+            [command, ..] => Err(format!(
+                "unknown or malformed command {:?}, try \"help\"",
+                command
+            )),
+        }
+    }
+
+    fn named_point(&self, name: &str) -> Result<GlobalLocation, String> {
+        self.point(name)
+            .ok_or_else(|| format!("no point named {:?}", name))
+    }
+}
+
+fn parse_number(word: &str) -> Result<i32, String> {
+    word.parse()
+        .map_err(|_| format!("{:?} is not a number", word))
+}
+
+fn parse_location(x: &str, y: &str, z: &str) -> Result<GlobalLocation, String> {
+    Ok(Point3D::new(
+        parse_number(x)?,
+        parse_number(y)?,
+        parse_number(z)?,
+    ))
+}
+
+fn registry_of(dimension: &Dimension<Voxel>) -> &VoxelRegistry {
+    dimension
+        .registry()
+        .unwrap_or_else(|| VoxelRegistry::builtin())
+}
+
+/// A voxel of a type given by name or id
+fn parse_voxel(dimension: &Dimension<Voxel>, word: &str) -> Result<Voxel, String> {
+    let registry = registry_of(dimension);
+    let id = match word.parse::<u32>() {
+        Ok(id) if registry.get(id).is_some() => id,
+        _ => registry
+            .id_of(word)
+            .ok_or_else(|| format!("no voxel type {:?}", word))?,
+    };
+    Ok(Voxel {
+        id,
+        ..Voxel::default()
+    })
+}
+
+fn type_name(dimension: &Dimension<Voxel>, id: u32) -> String {
+    match registry_of(dimension).get(id) {
+        Some(voxel_type) => voxel_type.name.clone(),
+        None => format!("#{}", id),
+    }
+}
+
+fn show_location(location: GlobalLocation) -> String {
+    format!("({}, {}, {})", location.x, location.y, location.z)
+}
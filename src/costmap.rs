@@ -0,0 +1,116 @@
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Aabb, Dimension, Point3D, VoxelRead};
+
+/// The cost values of a costmap, the same ones ROS costmap_2d uses
+pub const FREE_SPACE: u8 = 0;
+/// Close enough to an obstacle that a robot centered here would touch it
+pub const INSCRIBED_OBSTACLE: u8 = 253;
+pub const LETHAL_OBSTACLE: u8 = 254;
+pub const NO_INFORMATION: u8 = 255;
+
+/// How obstacles spread cost into the cells around them, so planners keep robots away from
+/// walls. Distances are in voxels
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Inflation {
+    /// cells this close to an obstacle are INSCRIBED_OBSTACLE
+    pub inscribed_radius: f32,
+    /// cells further than this from every obstacle aren't inflated
+    pub inflation_radius: f32,
+    /// how quickly the cost falls off past the inscribed radius
+    pub cost_scaling_factor: f32,
+}
+
+impl Inflation {
+    /// Inflation out to radius, with no inscribed radius and a cost that falls off by a
+    /// factor of e per voxel
+    pub fn new(radius: f32) -> Inflation {
+        Inflation {
+            inscribed_radius: 0.0,
+            inflation_radius: radius,
+            cost_scaling_factor: 1.0,
+        }
+    }
+
+    /// The cost of a cell at distance from the nearest obstacle
+    fn cost(&self, distance: f32) -> u8 {
+        if distance == 0.0 {
+            LETHAL_OBSTACLE
+        } else if distance <= self.inscribed_radius {
+            INSCRIBED_OBSTACLE
+        } else if distance <= self.inflation_radius {
+            let decay = (-self.cost_scaling_factor * (distance - self.inscribed_radius)).exp();
+            ((INSCRIBED_OBSTACLE - 1) as f32 * decay) as u8
+        } else {
+            FREE_SPACE
+        }
+    }
+}
+
+/// Spreads the cost of every lethal cell of a grid into its neighborhood
+fn inflate(grid: &mut [Vec<u8>], inflation: &Inflation) {
+    let radius = inflation.inflation_radius.max(0.0).ceil() as i64;
+    let obstacles: Vec<(usize, usize)> = grid
+        .iter()
+        .enumerate()
+        .flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, cost)| **cost == LETHAL_OBSTACLE)
+                .map(move |(x, _)| (x, y))
+        })
+        .collect();
+    let height = grid.len() as i64;
+    for (x, y) in obstacles {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (cx, cy) = (x as i64 + dx, y as i64 + dy);
+                if cy < 0 || cy >= height || cx < 0 || cx >= grid[cy as usize].len() as i64 {
+                    continue;
+                }
+                let cell = &mut grid[cy as usize][cx as usize];
+                // cells nothing is known about stay unknown
+                if *cell == NO_INFORMATION {
+                    continue;
+                }
+                let cost = inflation.cost(((dx * dx + dy * dy) as f32).sqrt());
+                *cell = (*cell).max(cost);
+            }
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Flattens the loaded world inside region into a 2D occupancy grid for robotics
+    /// planners, indexed [y][x] from region's start. A column is LETHAL_OBSTACLE if any of
+    /// its voxels between region's z bounds is solid, NO_INFORMATION if otherwise any of
+    /// them can't be read, and FREE_SPACE if not. Obstacles are then inflated, if asked to
+    pub fn to_costmap_2d(&self, region: Aabb, inflation: Option<Inflation>) -> Vec<Vec<u8>> {
+        let mut grid: Vec<Vec<u8>> = (region.start.y..region.end.y)
+            .map(|y| {
+                (region.start.x..region.end.x)
+                    .map(|x| {
+                        let mut unknown = false;
+                        for z in region.start.z..region.end.z {
+                            let location = Point3D::new(x, y, z);
+                            if !self.within_bounds(location) {
+                                unknown = true;
+                            } else if self.get(location).is_solid() {
+                                return LETHAL_OBSTACLE;
+                            }
+                        }
+                        if unknown {
+                            NO_INFORMATION
+                        } else {
+                            FREE_SPACE
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        if let Some(inflation) = inflation {
+            inflate(&mut grid, &inflation);
+        }
+        grid
+    }
+}
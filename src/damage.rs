@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::drops::{DropContext, ItemDrop};
+use super::error::Result;
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::{Dimension, GlobalLocation};
+
+/// How well a tool breaks each kind of voxel. Damage dealt with it is multiplied by the
+/// multiplier of the voxel's type, or by base for types without one
+#[derive(Clone, PartialEq, Debug)]
+pub struct ToolProfile {
+    pub base: f32,
+    /// multipliers by voxel type id, like a pickaxe's for stone
+    pub multipliers: HashMap<u32, f32>,
+}
+
+impl ToolProfile {
+    /// A tool dealing damage times base to every type
+    pub fn new(base: f32) -> ToolProfile {
+        ToolProfile {
+            base,
+            multipliers: HashMap::new(),
+        }
+    }
+
+    /// Bare hands, dealing damage as it is
+    pub fn hand() -> ToolProfile {
+        ToolProfile::new(1.0)
+    }
+
+    pub fn with_multiplier(mut self, type_id: u32, multiplier: f32) -> ToolProfile {
+        self.multipliers.insert(type_id, multiplier);
+        self
+    }
+
+    pub fn multiplier(&self, type_id: u32) -> f32 {
+        self.multipliers.get(&type_id).copied().unwrap_or(self.base)
+    }
+}
+
+/// How much damage voxels take to break, and how fast damage wears off
+#[derive(Clone, PartialEq, Debug)]
+pub struct DamageRules {
+    /// the damage voxels of each type id take to break. Types with an infinite hardness
+    /// can't be broken
+    pub hardness: HashMap<u32, f32>,
+    /// the hardness of types without one of their own
+    pub default_hardness: f32,
+    /// how many ticks damage stays after the last hit before it starts wearing off
+    pub decay_delay: u64,
+    /// the damage that wears off every tick after that
+    pub decay_per_tick: f32,
+}
+
+impl Default for DamageRules {
+    fn default() -> DamageRules {
+        DamageRules {
+            hardness: HashMap::new(),
+            default_hardness: 1.0,
+            decay_delay: 20,
+            decay_per_tick: 0.05,
+        }
+    }
+}
+
+impl DamageRules {
+    pub fn hardness_of(&self, type_id: u32) -> f32 {
+        self.hardness
+            .get(&type_id)
+            .copied()
+            .unwrap_or(self.default_hardness)
+    }
+}
+
+/// A broken voxel. It has been replaced with an empty voxel by the time the event is
+/// handed out
+#[derive(Clone, PartialEq, Debug)]
+pub struct BreakEvent<T> {
+    pub location: GlobalLocation,
+    /// the voxel that was broken
+    pub voxel: T,
+    pub tick: u64,
+    /// what the drop tables say breaking it left behind
+    pub drops: Vec<ItemDrop>,
+}
+
+/// The damage done to one voxel
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Damage {
+    /// the type that was damaged, so damage doesn't carry over to a voxel placed after it
+    type_id: u32,
+    amount: f32,
+    last_hit: u64,
+}
+
+/// The damage done to voxels that haven't broken yet. It isn't saved with the world unless
+/// asked to, since half mined voxels usually don't outlive a session
+#[derive(Clone, Default)]
+pub struct DamageMap {
+    rules: DamageRules,
+    damage: HashMap<GlobalLocation, Damage>,
+    persist: bool,
+}
+
+impl DamageMap {
+    pub fn new() -> DamageMap {
+        DamageMap::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.damage.is_empty()
+    }
+
+    /// If the damage is saved with the world
+    pub fn persists(&self) -> bool {
+        self.persist
+    }
+
+    /// The damage left of an entry at tick, once decay has worn some of it off
+    fn decayed(&self, damage: &Damage, tick: u64) -> f32 {
+        let idle = tick.saturating_sub(damage.last_hit + self.rules.decay_delay);
+        (damage.amount - self.rules.decay_per_tick * idle as f32).max(0.0)
+    }
+
+    /// Drops the damage that has worn off entirely by tick
+    fn prune(&mut self, tick: u64) {
+        let rules = &self.rules;
+        self.damage.retain(|_, damage| {
+            let idle = tick.saturating_sub(damage.last_hit + rules.decay_delay);
+            damage.amount > rules.decay_per_tick * idle as f32
+        });
+    }
+
+    /// Writes the damage as it stands at tick
+    pub fn write_to<W: Write>(&self, out: &mut W, tick: u64) -> io::Result<()> {
+        let mut damage: Vec<(&GlobalLocation, &Damage)> = self.damage.iter().collect();
+        damage.sort_by_key(|(location, _)| (location.z, location.y, location.x));
+        out.write_u32::<LittleEndian>(damage.len() as u32)?;
+        for (location, entry) in damage {
+            write_point(out, *location)?;
+            out.write_u32::<LittleEndian>(entry.type_id)?;
+            out.write_f32::<LittleEndian>(self.decayed(entry, tick))?;
+        }
+        Ok(())
+    }
+
+    /// Reads saved damage back into the map, as if it was all dealt at tick
+    pub fn read_from<R: Read>(&mut self, input: &mut R, tick: u64) -> io::Result<()> {
+        self.damage.clear();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let location = read_point(input)?;
+            let damage = Damage {
+                type_id: input.read_u32::<LittleEndian>()?,
+                amount: input.read_f32::<LittleEndian>()?,
+                last_hit: tick,
+            };
+            self.damage.insert(location, damage);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    pub fn set_damage_rules(&mut self, rules: DamageRules) {
+        self.damage.rules = rules;
+    }
+
+    pub fn damage_rules(&self) -> &DamageRules {
+        &self.damage.rules
+    }
+
+    /// Saves the damage of voxels that haven't broken yet with the world's metadata
+    pub fn set_persist_damage(&mut self, persist: bool) {
+        self.damage.persist = persist;
+    }
+
+    /// Deals damage to the voxel at location with a tool, adding to the damage it took
+    /// before, less what has worn off since. Once the damage reaches the voxel's hardness
+    /// the voxel is broken like break_voxel does, and the break is returned. Empty and
+    /// unbreakable voxels take no damage
+    pub fn apply_damage(
+        &mut self,
+        location: GlobalLocation,
+        amount: f32,
+        tool: &ToolProfile,
+    ) -> Result<Option<BreakEvent<T>>> {
+        let voxel = self.try_get_voxel(location)?;
+        let type_id = voxel.type_id();
+        let hardness = self.damage.rules.hardness_of(type_id);
+        if voxel.is_empty() || hardness.is_infinite() {
+            return Ok(None);
+        }
+        let tick = self.current_tick();
+        let before = match self.damage.damage.get(&location) {
+            Some(damage) if damage.type_id == type_id => self.damage.decayed(damage, tick),
+            _ => 0.0,
+        };
+        let amount = before + amount * tool.multiplier(type_id);
+        if amount < hardness {
+            self.damage.damage.insert(
+                location,
+                Damage {
+                    type_id,
+                    amount,
+                    last_hit: tick,
+                },
+            );
+            return Ok(None);
+        }
+        self.break_voxel(location, tool)
+    }
+
+    /// Breaks the voxel at location at once, however hard it is, replacing it with an
+    /// empty voxel through the same checks as try_set_voxel. The break comes back with
+    /// what the drop tables say the voxel drops for the tool. None if the voxel was
+    /// already empty
+    pub fn break_voxel(
+        &mut self,
+        location: GlobalLocation,
+        tool: &ToolProfile,
+    ) -> Result<Option<BreakEvent<T>>> {
+        let voxel = self.try_get_voxel(location)?;
+        if voxel.is_empty() {
+            return Ok(None);
+        }
+        self.try_set_voxel(location, T::empty())?;
+        self.damage.damage.remove(&location);
+        let tick = self.current_tick();
+        let drops = self.drop_tables.drops(
+            &DropContext {
+                type_id: voxel.type_id(),
+                location,
+                tool,
+                tick,
+            },
+            &mut *self.rng,
+        );
+        Ok(Some(BreakEvent {
+            location,
+            voxel,
+            tick,
+            drops,
+        }))
+    }
+
+    /// How far along breaking the voxel at location is, from 0 for untouched to 1 for
+    /// about to break, e.g. for drawing cracks. Damage dealt to a voxel that has since
+    /// been replaced doesn't count
+    pub fn damage_progress(&self, location: GlobalLocation) -> f32 {
+        let damage = match self.damage.damage.get(&location) {
+            Some(damage) => damage,
+            None => return 0.0,
+        };
+        let hardness = self.damage.rules.hardness_of(damage.type_id);
+        let current = self
+            .loaded_chunks
+            .get(&Self::get_chunk_location(location))
+            .map(|chunk| chunk.get(Self::get_voxel_location(location)).type_id());
+        if current != Some(damage.type_id) {
+            return 0.0;
+        }
+        (self.damage.decayed(damage, self.current_tick()) / hardness).min(1.0)
+    }
+
+    /// Forgets the damage dealt to the voxel at location
+    pub fn clear_damage(&mut self, location: GlobalLocation) {
+        self.damage.damage.remove(&location);
+    }
+
+    /// The locations of the voxels carrying damage
+    pub fn damaged_voxels(&self) -> impl Iterator<Item = &GlobalLocation> {
+        self.damage.damage.keys()
+    }
+
+    /// Drops the damage that has worn off entirely. Called on every tick
+    pub fn decay_damage(&mut self) {
+        let tick = self.current_tick();
+        self.damage.prune(tick);
+    }
+}
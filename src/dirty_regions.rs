@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::{Aabb, ChunkLocation, Dimension, GlobalLocation, Point3D};
+
+/// The part of a chunk written since the dirty regions were last taken
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub chunk: ChunkLocation,
+    /// the smallest box holding every voxel of the chunk written, in world coordinates
+    pub bounds: Aabb,
+}
+
+/// The box of written voxels of every chunk written to, kept in chunk order
+#[derive(Clone, Default)]
+pub struct DirtyRegions {
+    chunks: BTreeMap<(i32, i32, i32), Aabb>,
+}
+
+impl DirtyRegions {
+    pub fn new() -> DirtyRegions {
+        DirtyRegions::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Grows the box of chunk to hold location
+    fn mark(&mut self, chunk: ChunkLocation, location: GlobalLocation) {
+        let one = Point3D::new(1, 1, 1);
+        self.chunks
+            .entry((chunk.z, chunk.y, chunk.x))
+            .and_modify(|bounds| {
+                bounds.start = Point3D::new(
+                    bounds.start.x.min(location.x),
+                    bounds.start.y.min(location.y),
+                    bounds.start.z.min(location.z),
+                );
+                bounds.end = Point3D::new(
+                    bounds.end.x.max(location.x + 1),
+                    bounds.end.y.max(location.y + 1),
+                    bounds.end.z.max(location.z + 1),
+                );
+            })
+            .or_insert_with(|| Aabb::new(location, location + one));
+    }
+
+    fn regions(&self) -> Vec<DirtyRegion> {
+        self.chunks
+            .iter()
+            .map(|((z, y, x), bounds)| DirtyRegion {
+                chunk: ChunkLocation::new(*x, *y, *z),
+                bounds: *bounds,
+            })
+            .collect()
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u32::<LittleEndian>(self.chunks.len() as u32)?;
+        for region in self.regions() {
+            write_point(out, region.chunk)?;
+            write_point(out, region.bounds.start)?;
+            write_point(out, region.bounds.end)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<DirtyRegions> {
+        let mut regions = DirtyRegions::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let chunk = read_point(input)?;
+            let bounds = Aabb::new(read_point(input)?, read_point(input)?);
+            regions.chunks.insert((chunk.z, chunk.y, chunk.x), bounds);
+        }
+        Ok(regions)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Starts remembering which parts of which chunks are written, for take_dirty_regions.
+    /// The regions not taken yet are saved with the world and come back tracked when it is
+    /// loaded, so consumers that keep their output on disk, like minimap tiles, catch up
+    /// after a restart
+    pub fn track_dirty_regions(&mut self) {
+        if self.dirty_regions.is_none() {
+            self.dirty_regions = Some(DirtyRegions::new());
+        }
+    }
+
+    pub fn untrack_dirty_regions(&mut self) {
+        self.dirty_regions = None;
+    }
+
+    /// The regions written since they were last taken, in chunk order, without taking them
+    pub fn dirty_regions(&self) -> Vec<DirtyRegion> {
+        self.dirty_regions
+            .as_ref()
+            .map_or_else(Vec::new, |regions| regions.regions())
+    }
+
+    /// The regions written since the last call, one box per chunk in chunk order, so
+    /// meshing, lighting, network sync and the like can all work from the same minimal set
+    /// of updates. Empty unless dirty regions are tracked
+    pub fn take_dirty_regions(&mut self) -> Vec<DirtyRegion> {
+        match &mut self.dirty_regions {
+            Some(regions) => std::mem::take(regions).regions(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Marks a written voxel's chunk as dirty up to location, if dirty regions are tracked
+    pub fn voxel_region_dirtied(&mut self, location: GlobalLocation) {
+        if let Some(regions) = &mut self.dirty_regions {
+            regions.mark(Self::get_chunk_location(location), location);
+        }
+    }
+}
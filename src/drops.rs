@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::codec::VoxelCodec;
+use super::damage::ToolProfile;
+use super::occupancy::VoxelClass;
+use super::rng::WorldRng;
+use super::{Dimension, GlobalLocation};
+
+/// Items a broken voxel leaves behind, by the game's own item ids
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ItemDrop {
+    pub item: u32,
+    pub count: u32,
+}
+
+/// What a drop table gets to decide on
+#[derive(Copy, Clone, Debug)]
+pub struct DropContext<'a> {
+    /// the type id of the broken voxel
+    pub type_id: u32,
+    pub location: GlobalLocation,
+    pub tool: &'a ToolProfile,
+    pub tick: u64,
+}
+
+/// Decides what breaking a voxel drops, e.g. ore only for the right pickaxe. Chance drops
+/// draw from the world's random source, so they come out the same when replayed
+pub type DropTable = Arc<dyn Fn(&DropContext, &mut dyn WorldRng) -> Vec<ItemDrop> + Send + Sync>;
+
+/// The drop tables of voxel types, so loot logic plugs into breaking without touching the
+/// edit code. Types without a table drop nothing
+#[derive(Clone, Default)]
+pub struct DropTables {
+    tables: HashMap<u32, DropTable>,
+    /// the table of types without one of their own
+    fallback: Option<DropTable>,
+}
+
+impl DropTables {
+    pub fn new() -> DropTables {
+        DropTables::default()
+    }
+
+    pub fn set_table(&mut self, type_id: u32, table: DropTable) {
+        self.tables.insert(type_id, table);
+    }
+
+    /// Makes a type always drop count of an item, whatever broke it
+    pub fn set_fixed(&mut self, type_id: u32, item: u32, count: u32) {
+        self.set_table(
+            type_id,
+            Arc::new(move |_, _| vec![ItemDrop { item, count }]),
+        );
+    }
+
+    pub fn remove_table(&mut self, type_id: u32) {
+        self.tables.remove(&type_id);
+    }
+
+    pub fn set_fallback(&mut self, table: DropTable) {
+        self.fallback = Some(table);
+    }
+
+    pub fn clear_fallback(&mut self) {
+        self.fallback = None;
+    }
+
+    /// What breaking a voxel drops, with rng for the tables to roll their chances with
+    pub fn drops(&self, context: &DropContext, rng: &mut dyn WorldRng) -> Vec<ItemDrop> {
+        match self.tables.get(&context.type_id).or(self.fallback.as_ref()) {
+            Some(table) => table(context, rng)
+                .into_iter()
+                .filter(|drop| drop.count > 0)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    pub fn drop_tables(&self) -> &DropTables {
+        &self.drop_tables
+    }
+
+    pub fn drop_tables_mut(&mut self) -> &mut DropTables {
+        &mut self.drop_tables
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::claims::ClaimPolicy;
+use super::codec::VoxelCodec;
+use super::error::{Error, Result};
+use super::occupancy::VoxelClass;
+use super::{Dimension, GlobalLocation};
+
+/// A single voxel modification
+#[derive(Copy, Clone)]
+pub struct Edit<T> {
+    pub location: GlobalLocation,
+    pub value: T,
+}
+
+/// Why a validator turned an edit down
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Rejection {
+    pub reason: String,
+}
+
+impl Rejection {
+    pub fn new(reason: &str) -> Rejection {
+        Rejection {
+            reason: String::from(reason),
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+/// Game rules every edit has to pass before it touches a chunk, like reach distance or
+/// what may be placed where. Run on the server so clients can't get around them
+pub type Validator<T> = Arc<dyn Fn(&Edit<T>) -> std::result::Result<(), Rejection> + Send + Sync>;
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Checks that an edit is inside the world, not protected, and passes the validator
+    fn check_edit(&self, edit: &Edit<T>) -> Result<()> {
+        if let Some(location) = self.resolve_location(edit.location)? {
+            self.claims.check(location)?;
+        }
+        if let Some(validator) = &self.validator {
+            validator(edit).map_err(|rejection| Error::Rejected {
+                location: edit.location,
+                reason: rejection.reason,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Runs every edit through the validator before it is applied
+    pub fn set_validator(&mut self, validator: Validator<T>) {
+        self.validator = Some(validator);
+    }
+
+    pub fn clear_validator(&mut self) {
+        self.validator = None;
+    }
+
+    /// Sets a voxel, unless it is out of bounds, protected by a claim or rejected by the
+    /// validator
+    pub fn try_set_voxel(&mut self, location: GlobalLocation, value: T) -> Result<()> {
+        self.check_edit(&Edit { location, value })?;
+        self.write_voxel(location, value)
+    }
+
+    /// Applies a batch of edits after checking them against the bounds, claims and
+    /// validator. Under ClaimPolicy::Reject nothing is applied if any edit fails, under
+    /// ClaimPolicy::Filter the failing edits are skipped and handed back. Every chunk the
+    /// batch writes to is loaded before the first write, so a chunk that can't be loaded
+    /// fails the batch without applying any of it
+    pub fn apply_edits(&mut self, edits: Vec<Edit<T>>) -> Result<Vec<Edit<T>>> {
+        let mut allowed = Vec::with_capacity(edits.len());
+        let mut denied = Vec::new();
+        for edit in edits {
+            match self.check_edit(&edit) {
+                Ok(()) => allowed.push(edit),
+                Err(err) => match self.claims.policy() {
+                    ClaimPolicy::Reject => return Err(err),
+                    ClaimPolicy::Filter => denied.push(edit),
+                },
+            }
+        }
+        for edit in allowed.iter() {
+            match self.resolve_location(edit.location)? {
+                Some(location) if !self.void.covers(location.z) => {
+                    self.get_chunk(Self::get_chunk_location(location))?;
+                }
+                _ => {}
+            }
+        }
+        for edit in allowed {
+            self.write_voxel(edit.location, edit.value)?;
+        }
+        Ok(denied)
+    }
+}
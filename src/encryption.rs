@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use super::codec::VoxelCodec;
+use super::error::{ErrorContext, Operation, Result, ResultExt};
+use super::occupancy::VoxelClass;
+use super::region::RegionFile;
+use super::{ChunkLocation, Dimension};
+
+/// The size of a ChaCha20-Poly1305 nonce
+const NONCE_SIZE: usize = 12;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A 256 bit ChaCha20-Poly1305 key, and the id records encrypted with it are tagged with so
+/// the right key is found to decrypt them
+#[derive(Clone)]
+pub struct EncryptionKey {
+    id: u32,
+    cipher: ChaCha20Poly1305,
+}
+
+impl EncryptionKey {
+    pub fn new(id: u32, key: &[u8; 32]) -> EncryptionKey {
+        EncryptionKey {
+            id,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// A random key, returned along with its bytes since they are needed to read the world
+    /// back later
+    pub fn generate(id: u32) -> (EncryptionKey, [u8; 32]) {
+        let key: [u8; 32] = ChaCha20Poly1305::generate_key(&mut OsRng).into();
+        (EncryptionKey::new(id, &key), key)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Keeps the key out of logs
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+/// The key chunks are encrypted with, and the keys they were encrypted with before a
+/// rotation, which are still accepted when reading
+#[derive(Clone, Debug)]
+pub struct Keyring {
+    current: EncryptionKey,
+    previous: BTreeMap<u32, EncryptionKey>,
+}
+
+impl Keyring {
+    pub fn new(current: EncryptionKey) -> Keyring {
+        Keyring {
+            current,
+            previous: BTreeMap::new(),
+        }
+    }
+
+    /// Also accepts a key chunks were encrypted with before
+    pub fn with_previous(mut self, key: EncryptionKey) -> Keyring {
+        self.previous.insert(key.id, key);
+        self
+    }
+
+    pub fn current_id(&self) -> u32 {
+        self.current.id
+    }
+
+    /// Encrypts with a new key from now on, keeping the current one to read what it
+    /// encrypted
+    pub fn rotate(&mut self, key: EncryptionKey) {
+        let old = std::mem::replace(&mut self.current, key);
+        self.previous.insert(old.id, old);
+    }
+
+    /// Drops the previous keys, once nothing is encrypted with them anymore
+    pub fn forget_previous(&mut self) {
+        self.previous.clear();
+    }
+
+    fn key(&self, id: u32) -> Option<&EncryptionKey> {
+        if self.current.id == id {
+            Some(&self.current)
+        } else {
+            self.previous.get(&id)
+        }
+    }
+
+    /// Encrypts a plaintext with the current key into the key id, a random nonce and the
+    /// ciphertext. The associated data is authenticated but not stored, so the same has to
+    /// be given to open it
+    pub fn seal(&self, associated_data: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .current
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| invalid("failed to encrypt chunk"))?;
+        let mut sealed = Vec::with_capacity(4 + NONCE_SIZE + ciphertext.len());
+        sealed.write_u32::<LittleEndian>(self.current.id)?;
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts what seal produced, failing if the key is unknown or the data was tampered
+    /// with
+    pub fn open(&self, associated_data: &[u8], mut sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let key = self
+            .key(sealed.read_u32::<LittleEndian>()?)
+            .ok_or_else(|| invalid("chunk was encrypted with an unknown key"))?;
+        let mut nonce = [0; NONCE_SIZE];
+        sealed.read_exact(&mut nonce)?;
+        key.cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: sealed,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| invalid("chunk failed to decrypt, wrong key or corrupted"))
+    }
+
+    /// The id of the key a sealed record was encrypted with
+    pub fn sealed_key_id(mut sealed: &[u8]) -> io::Result<u32> {
+        sealed.read_u32::<LittleEndian>()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Encrypts chunks as they are written to the disk cache, so worlds can be kept on
+    /// storage others can read. Chunks already on disk stay as they are until they are
+    /// written again or reencrypt_chunks is called. The world metadata isn't encrypted
+    pub fn set_encryption(&mut self, keyring: Keyring) {
+        self.encryption = Some(keyring);
+    }
+
+    /// Writes chunks in plain text from now on. Chunks encrypted before can't be read
+    /// without setting the keys again
+    pub fn clear_encryption(&mut self) {
+        self.encryption = None;
+    }
+
+    pub fn encryption(&self) -> Option<&Keyring> {
+        self.encryption.as_ref()
+    }
+
+    /// Switches to a new key, keeping the old one to read the chunks still encrypted with
+    /// it. Does nothing if encryption isn't set
+    pub fn rotate_encryption_key(&mut self, key: EncryptionKey) {
+        if let Some(keyring) = &mut self.encryption {
+            keyring.rotate(key);
+        }
+    }
+
+    /// Rewrites every chunk in the disk cache that isn't encrypted with the current key, e.g.
+    /// after a rotation so the previous keys can be forgotten. Returns how many chunks were
+    /// rewritten
+    pub fn reencrypt_chunks(&mut self) -> Result<usize> {
+        let current = match &self.encryption {
+            Some(keyring) => keyring.current_id(),
+            None => return Ok(0),
+        };
+        let mut regions: BTreeMap<PathBuf, Vec<ChunkLocation>> = BTreeMap::new();
+        for location in self.all_chunk_locations.iter() {
+            if let Some(path) = self.region_path(*location) {
+                regions.entry(path).or_default().push(*location);
+            }
+        }
+        let mut rewritten = 0;
+        for (path, locations) in regions {
+            if !path.exists() {
+                continue;
+            }
+            let mut region = RegionFile::open(&path)
+                .with_context(|| ErrorContext::new(Operation::SaveChunk).path(&path))?;
+            for location in locations {
+                let slot = RegionFile::slot(location);
+                let offset = region.offset(slot);
+                let context = || {
+                    ErrorContext::new(Operation::SaveChunk)
+                        .chunk(location)
+                        .path(&path)
+                        .offset(offset)
+                };
+                let record = match region.read(slot).with_context(context)? {
+                    Some(record) => record,
+                    None => continue,
+                };
+                if self.record_key_id(&record).with_context(context)? == Some(current) {
+                    continue;
+                }
+                let (kind, compressed) =
+                    self.open_record(location, &record).with_context(context)?;
+                let record = self
+                    .seal_record(location, kind, &compressed)
+                    .with_context(context)?;
+                region.write(slot, &record).with_context(context)?;
+                rewritten += 1;
+            }
+        }
+        Ok(rewritten)
+    }
+}
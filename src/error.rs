@@ -0,0 +1,237 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use super::schema::VoxelSchema;
+use super::{ChunkLocation, GlobalLocation};
+
+/// Everything that can go wrong when working with a dimension
+#[derive(Debug, Error)]
+pub enum Error {
+    /// An edit touched a voxel inside a protected claim
+    #[error(
+        "voxel ({}, {}, {}) is protected by claim \"{claim}\"",
+        location.x,
+        location.y,
+        location.z
+    )]
+    Protected {
+        claim: String,
+        location: GlobalLocation,
+    },
+    /// The edit validator turned an edit down
+    #[error(
+        "edit of voxel ({}, {}, {}) was rejected: {reason}",
+        location.x,
+        location.y,
+        location.z
+    )]
+    Rejected {
+        location: GlobalLocation,
+        reason: String,
+    },
+    /// A source submitted edits faster than its rate limit allows
+    #[error("edit source {source_id} is over its rate limit")]
+    RateLimited { source_id: u32 },
+    /// A chunk that isn't defined and can't be generated was accessed
+    #[error("chunk ({}, {}, {}) is not defined", chunk.x, chunk.y, chunk.z)]
+    UndefinedChunk { chunk: ChunkLocation },
+    /// A voxel outside of the world bounds was accessed
+    #[error(
+        "voxel ({}, {}, {}) is outside of the world bounds",
+        location.x,
+        location.y,
+        location.z
+    )]
+    OutOfBounds { location: GlobalLocation },
+    /// The world was saved with a different voxel layout than the one it is opened with
+    #[error("world was saved with voxel schema {found:?} but opened with {expected:?}")]
+    SchemaMismatch {
+        expected: VoxelSchema,
+        found: VoxelSchema,
+    },
+    /// A voxel registry is malformed, e.g. it uses an id twice
+    #[error("invalid voxel registry: {0}")]
+    InvalidRegistry(String),
+    /// A voxel id means a different type than when the world was saved
+    #[error(
+        "voxel id {id} was \"{saved}\" when the world was saved but is now {}",
+        current.as_ref().map_or(String::from("undefined"), |name| format!("\"{}\"", name))
+    )]
+    UnstableVoxelId {
+        id: u32,
+        saved: String,
+        current: Option<String>,
+    },
+    /// The operation needs a disk cache but none is set
+    #[error("dimension has no disk cache")]
+    NoDiskCache,
+    /// A chunk in cold storage was accessed but no cold store is set
+    #[error("dimension has no cold store")]
+    NoColdStore,
+    /// A remote chunk store couldn't be reached or answered with an error
+    #[error("remote chunk store failed: {0}")]
+    Remote(String),
+    /// Reading or writing the disk cache failed
+    #[error("disk cache i/o failed: {0}")]
+    Io(#[from] io::Error),
+    /// Another error, along with what was being done when it happened
+    #[error("{context}: {source}")]
+    Context {
+        context: ErrorContext,
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What the dimension was doing when an error happened
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Operation {
+    LoadChunk,
+    SaveChunk,
+    DeleteChunk,
+    RemoveChunk,
+    RestoreChunk,
+    ScanRegions,
+    CompactRegions,
+    LoadMetadata,
+    SaveMetadata,
+    ExportArchive,
+    ImportArchive,
+    FetchChunk,
+    PublishChunk,
+    MigrateChunk,
+    PromoteChunk,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operation::LoadChunk => "loading chunk",
+            Operation::SaveChunk => "saving chunk",
+            Operation::DeleteChunk => "deleting chunk",
+            Operation::RemoveChunk => "removing chunk",
+            Operation::RestoreChunk => "restoring chunk",
+            Operation::ScanRegions => "scanning region files",
+            Operation::CompactRegions => "compacting region files",
+            Operation::LoadMetadata => "loading world metadata",
+            Operation::SaveMetadata => "saving world metadata",
+            Operation::ExportArchive => "exporting archive",
+            Operation::ImportArchive => "importing archive",
+            Operation::FetchChunk => "fetching chunk",
+            Operation::PublishChunk => "publishing chunk",
+            Operation::MigrateChunk => "moving chunk to cold storage",
+            Operation::PromoteChunk => "moving chunk out of cold storage",
+        })
+    }
+}
+
+/// Where an error happened: the operation, and the chunk, file and offset in the file it
+/// was working on, where they are known
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ErrorContext {
+    pub operation: Operation,
+    pub chunk: Option<ChunkLocation>,
+    pub path: Option<PathBuf>,
+    pub offset: Option<u64>,
+}
+
+impl ErrorContext {
+    pub fn new(operation: Operation) -> ErrorContext {
+        ErrorContext {
+            operation,
+            chunk: None,
+            path: None,
+            offset: None,
+        }
+    }
+
+    pub fn chunk(mut self, location: ChunkLocation) -> ErrorContext {
+        self.chunk = Some(location);
+        self
+    }
+
+    pub fn path(mut self, path: &Path) -> ErrorContext {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn offset(mut self, offset: u64) -> ErrorContext {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.operation)?;
+        if let Some(chunk) = self.chunk {
+            write!(f, " ({}, {}, {})", chunk.x, chunk.y, chunk.z)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " in {}", path.display())?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// The innermost error, without any of the context wrapped around it
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// The outermost context of the error, if any
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The chunk the error happened in, if known
+    pub fn chunk(&self) -> Option<ChunkLocation> {
+        match self {
+            Error::Context { context, source } => context.chunk.or_else(|| source.chunk()),
+            _ => None,
+        }
+    }
+
+    /// The underlying i/o error, if the error came from the disk
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self.root() {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Attaches an ErrorContext to failures
+pub trait ResultExt<T> {
+    fn context(self, context: ErrorContext) -> Result<T>;
+
+    /// Like context, but only builds the context on failure
+    fn with_context<F: FnOnce() -> ErrorContext>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(self, context: ErrorContext) -> Result<T> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<F: FnOnce() -> ErrorContext>(self, context: F) -> Result<T> {
+        self.map_err(|err| Error::Context {
+            context: context(),
+            source: Box::new(err.into()),
+        })
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{
+    Chunk, ChunkLocation, Dimension, GlobalLocation, Point3D, VoxelLocation, CHUNK_X_SIZE,
+    CHUNK_Y_SIZE,
+};
+
+/// How chunks are persisted
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PersistenceMode {
+    /// The full contents of each chunk are saved
+    Snapshot,
+    /// Only the generation seed and the edits made since are saved, and chunks are rebuilt
+    /// by regenerating and replaying the edits. Logs longer than compaction_threshold edits
+    /// are collapsed into a snapshot when compacted
+    EventSourced { compaction_threshold: usize },
+}
+
+/// What a chunk's edit log is replayed on top of
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ChunkBase {
+    /// The chunk as the given version of the generator produces it from the seed
+    Generated { seed: u64, generator_version: u32 },
+    /// An encoded payload of the chunk at the time of the last compaction
+    Snapshot(Vec<u8>),
+}
+
+/// The history of a chunk: where it started and every edit made to it since, in order
+#[derive(Clone)]
+pub struct ChunkLog<T> {
+    pub base: ChunkBase,
+    pub edits: Vec<(VoxelLocation, T)>,
+}
+
+/// Builds a chunk from a generation seed, for replaying logs
+pub type SeedGenerator<'a, T> = &'a dyn Fn(u64, ChunkLocation) -> Chunk<T>;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> ChunkLog<T> {
+    /// Rebuilds the chunk by replaying the edits on top of the base
+    pub fn replay(
+        &self,
+        location: ChunkLocation,
+        generate: SeedGenerator<T>,
+    ) -> io::Result<Chunk<T>> {
+        let mut chunk = match &self.base {
+            ChunkBase::Generated { seed, .. } => generate(*seed, location),
+            ChunkBase::Snapshot(payload) => Chunk::decode_payload(payload)?,
+        };
+        for (location, value) in self.edits.iter() {
+            chunk.set(*location, *value);
+        }
+        Ok(chunk)
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match &self.base {
+            // logs of the first generator version are written as before versions were kept
+            ChunkBase::Generated {
+                seed,
+                generator_version: 0,
+            } => {
+                out.write_u8(0)?;
+                out.write_u64::<LittleEndian>(*seed)?;
+            }
+            ChunkBase::Generated {
+                seed,
+                generator_version,
+            } => {
+                out.write_u8(2)?;
+                out.write_u64::<LittleEndian>(*seed)?;
+                out.write_u32::<LittleEndian>(*generator_version)?;
+            }
+            ChunkBase::Snapshot(payload) => {
+                out.write_u8(1)?;
+                out.write_u32::<LittleEndian>(payload.len() as u32)?;
+                out.write_all(payload)?;
+            }
+        }
+        out.write_u32::<LittleEndian>(self.edits.len() as u32)?;
+        for (location, value) in self.edits.iter() {
+            // the index within the chunk is smaller than the three coordinates
+            out.write_u16::<LittleEndian>(Chunk::<T>::get_index(*location) as u16)?;
+            value.encode(out)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<ChunkLog<T>> {
+        let base = match input.read_u8()? {
+            0 => ChunkBase::Generated {
+                seed: input.read_u64::<LittleEndian>()?,
+                generator_version: 0,
+            },
+            2 => ChunkBase::Generated {
+                seed: input.read_u64::<LittleEndian>()?,
+                generator_version: input.read_u32::<LittleEndian>()?,
+            },
+            1 => {
+                let mut payload = vec![0; input.read_u32::<LittleEndian>()? as usize];
+                input.read_exact(&mut payload)?;
+                ChunkBase::Snapshot(payload)
+            }
+            _ => return Err(invalid("bad chunk log base")),
+        };
+        let count = input.read_u32::<LittleEndian>()?;
+        let mut edits = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let index = input.read_u16::<LittleEndian>()? as i32;
+            let location = Point3D::new(
+                index % CHUNK_X_SIZE as i32,
+                (index / CHUNK_X_SIZE as i32) % CHUNK_Y_SIZE as i32,
+                index / (CHUNK_X_SIZE * CHUNK_Y_SIZE) as i32,
+            );
+            if Chunk::<T>::get_index(location) != index as usize {
+                return Err(invalid("edit outside of chunk"));
+            }
+            edits.push((location, T::decode(input)?));
+        }
+        Ok(ChunkLog { base, edits })
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    pub fn set_persistence_mode(&mut self, mode: PersistenceMode) {
+        self.persistence_mode = mode;
+        if mode == PersistenceMode::Snapshot {
+            self.chunk_logs.clear();
+        }
+    }
+
+    pub fn persistence_mode(&self) -> PersistenceMode {
+        self.persistence_mode
+    }
+
+    /// The seed chunks are generated from. Event sourced chunks that haven't been compacted
+    /// are rebuilt from it, and fail to load once it changes
+    pub fn set_generation_seed(&mut self, seed: u64) {
+        self.generation_seed = seed;
+    }
+
+    pub fn generation_seed(&self) -> u64 {
+        self.generation_seed
+    }
+
+    /// Records an edit in its chunk's log when event sourcing. A new log starts from the
+    /// generator if the chunk was just as the generator made it, and from a snapshot of the
+    /// chunk otherwise, since chunks built by hand or fetched from a store can't be
+    /// generated again. Called after the edit is made
+    pub fn log_edit(&mut self, location: GlobalLocation, value: T) {
+        let chunk = Self::get_chunk_location(location);
+        // the chunk isn't as the generator made it anymore
+        let generated = self.generated_chunks.remove(&chunk);
+        if let PersistenceMode::EventSourced { .. } = self.persistence_mode {
+            if !self.chunk_logs.contains_key(&chunk) {
+                let base = if generated {
+                    ChunkBase::Generated {
+                        seed: self.generation_seed,
+                        generator_version: self.generator_version,
+                    }
+                } else {
+                    // writing into a Vec can't fail. The snapshot already holds the edit,
+                    // which replays to the same voxel
+                    ChunkBase::Snapshot(self.loaded_chunks[&chunk].encode_payload().unwrap())
+                };
+                self.chunk_logs.insert(
+                    chunk,
+                    ChunkLog {
+                        base,
+                        edits: Vec::new(),
+                    },
+                );
+            }
+            self.chunk_logs
+                .get_mut(&chunk)
+                .unwrap()
+                .edits
+                .push((Self::get_voxel_location(location), value));
+        }
+    }
+
+    pub fn chunk_log(&self, location: ChunkLocation) -> Option<&ChunkLog<T>> {
+        self.chunk_logs.get(&location)
+    }
+
+    /// Replaces a chunk's log, e.g. with one read back from disk
+    pub fn set_chunk_log(&mut self, location: ChunkLocation, log: ChunkLog<T>) {
+        self.chunk_logs.insert(location, log);
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Collapses every log longer than the compaction threshold into a snapshot of its
+    /// loaded chunk, returning how many logs were compacted
+    pub fn compact_logs(&mut self) -> io::Result<usize> {
+        let threshold = match self.persistence_mode {
+            PersistenceMode::EventSourced {
+                compaction_threshold,
+            } => compaction_threshold,
+            PersistenceMode::Snapshot => return Ok(0),
+        };
+        let mut compacted = 0;
+        for (location, log) in self.chunk_logs.iter_mut() {
+            if log.edits.len() <= threshold {
+                continue;
+            }
+            // a chunk that isn't loaded is rebuilt from the log, so it can wait
+            if let Some(chunk) = self.loaded_chunks.get(location) {
+                log.base = ChunkBase::Snapshot(chunk.encode_payload()?);
+                log.edits.clear();
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+
+    /// Rebuilds a chunk from its log, or None if it has never been edited
+    pub fn replay_chunk_log(
+        &self,
+        location: ChunkLocation,
+        generate: SeedGenerator<T>,
+    ) -> io::Result<Option<Chunk<T>>> {
+        match self.chunk_logs.get(&location) {
+            Some(log) => log.replay(location, generate).map(Some),
+            None => Ok(None),
+        }
+    }
+}
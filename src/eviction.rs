@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::{Chunk, ChunkLocation, Dimension};
+
+/// How much of the world may be resident at once
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkBudget {
+    /// At most this many chunks are loaded
+    Chunks(usize),
+    /// The loaded chunks take at most this many bytes, as counted by Chunk::memory_size
+    Bytes(usize),
+}
+
+/// The order chunks were last used in, so the least recently used can be evicted first
+#[derive(Clone, Default)]
+pub struct ChunkLru {
+    clock: u64,
+    last_used: HashMap<ChunkLocation, u64>,
+    by_age: BTreeMap<u64, ChunkLocation>,
+}
+
+impl ChunkLru {
+    pub fn new() -> ChunkLru {
+        ChunkLru::default()
+    }
+
+    /// Marks a chunk as the most recently used
+    pub fn touch(&mut self, location: ChunkLocation) {
+        self.clock += 1;
+        if let Some(old) = self.last_used.insert(location, self.clock) {
+            self.by_age.remove(&old);
+        }
+        self.by_age.insert(self.clock, location);
+    }
+
+    pub fn forget(&mut self, location: ChunkLocation) {
+        if let Some(old) = self.last_used.remove(&location) {
+            self.by_age.remove(&old);
+        }
+    }
+
+    pub fn contains(&self, location: ChunkLocation) -> bool {
+        self.last_used.contains_key(&location)
+    }
+
+    pub fn newest(&self) -> Option<ChunkLocation> {
+        self.by_age.values().next_back().copied()
+    }
+
+    /// The chunks from least to most recently used
+    pub fn oldest_first(&self) -> impl Iterator<Item = ChunkLocation> + '_ {
+        self.by_age.values().copied()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Keeps the loaded chunks within a budget, evicting the least recently used ones when
+    /// it is exceeded. Chunks around anchors are never evicted. Chunk use is only tracked
+    /// while there is a budget
+    pub fn set_chunk_budget(&mut self, budget: ChunkBudget) {
+        self.chunk_budget = Some(budget);
+        // with writes failing, chunks stay loaded and the budget catches up later
+        let _ = self.evict_over_budget();
+    }
+
+    pub fn clear_chunk_budget(&mut self) {
+        self.chunk_budget = None;
+        self.chunk_lru = ChunkLru::new();
+    }
+
+    /// Marks a chunk as used, if there is a budget to evict chunks for
+    pub fn touch_chunk(&mut self, location: ChunkLocation) {
+        if self.chunk_budget.is_some() {
+            self.chunk_lru.touch(location);
+        }
+    }
+
+    pub fn chunk_budget(&self) -> Option<ChunkBudget> {
+        self.chunk_budget
+    }
+
+    /// If a loaded chunk has changes that aren't on disk yet
+    pub fn chunk_dirty(&self, location: ChunkLocation) -> bool {
+        self.dirty_chunks.contains(&location)
+    }
+
+    /// How many loaded chunks have changes that aren't on disk yet
+    pub fn dirty_chunk_count(&self) -> usize {
+        self.dirty_chunks.len()
+    }
+
+    /// The memory taken by the loaded chunks
+    pub fn loaded_bytes(&self) -> usize {
+        self.loaded_chunks.values().map(Chunk::memory_size).sum()
+    }
+
+    /// Saves a chunk and frees its memory, leaving it defined so it is loaded again when
+    /// needed. Returns false if the chunk stays loaded because there is no disk cache to
+    /// save it in, or its write was dropped by the store failure policy
+    pub fn unload_chunk(&mut self, location: ChunkLocation) -> Result<bool> {
+        if !self.chunk_loaded(location) {
+            return Ok(true);
+        }
+        if self.disk_cache.is_none() {
+            return Ok(false);
+        }
+        self.sync_chunk(location)?;
+        if self.chunk_dirty(location) {
+            return Ok(false);
+        }
+        self.discard_chunk(location);
+        Ok(true)
+    }
+
+    /// If the loaded chunks, taking loaded_bytes, don't fit in the budget
+    fn over_budget(&self, loaded_bytes: usize) -> bool {
+        match self.chunk_budget {
+            Some(ChunkBudget::Chunks(max)) => self.loaded_chunks.len() > max,
+            Some(ChunkBudget::Bytes(max)) => loaded_bytes > max,
+            None => false,
+        }
+    }
+
+    /// Unloads the least recently used chunks until the loaded ones fit in the budget,
+    /// returning how many were unloaded
+    pub fn evict_over_budget(&mut self) -> Result<usize> {
+        let mut loaded_bytes = match self.chunk_budget {
+            Some(ChunkBudget::Bytes(_)) => self.loaded_bytes(),
+            _ => 0,
+        };
+        if !self.over_budget(loaded_bytes) {
+            return Ok(0);
+        }
+        // chunks loaded before the budget was set were never used since, so they go first.
+        // The most recently used chunk is the one being accessed, so it stays
+        let untracked = self
+            .loaded_chunks
+            .keys()
+            .filter(|location| !self.chunk_lru.contains(**location));
+        let tracked = self.chunk_lru.oldest_first();
+        let mut candidates: Vec<ChunkLocation> = untracked
+            .copied()
+            .chain(tracked)
+            .filter(|location| !self.anchors.covers(*location))
+            .collect();
+        if let Some(newest) = self.chunk_lru.newest() {
+            candidates.retain(|location| *location != newest);
+        }
+        let mut evicted = 0;
+        for location in candidates {
+            if !self.over_budget(loaded_bytes) {
+                break;
+            }
+            let bytes = self
+                .loaded_chunks
+                .get(&location)
+                .map_or(0, Chunk::memory_size);
+            if self.unload_chunk(location)? {
+                loaded_bytes = loaded_bytes.saturating_sub(bytes);
+                evicted += 1;
+            }
+        }
+        Ok(evicted)
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+
+use super::registry::VoxelRegistry;
+use super::{GlobalLocation, Point3D, Voxel, VoxelRead};
+
+/// One of the six faces of a voxel, named by the direction it looks in. z is up
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Face {
+    /// +x
+    East,
+    /// -x
+    West,
+    /// +y
+    North,
+    /// -y
+    South,
+    /// +z
+    Up,
+    /// -z
+    Down,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::East,
+        Face::West,
+        Face::North,
+        Face::South,
+        Face::Up,
+        Face::Down,
+    ];
+
+    /// The faces that can connect to neighbors, like fences and glass panes do
+    pub const SIDES: [Face; 4] = [Face::East, Face::West, Face::North, Face::South];
+
+    pub fn opposite(self) -> Face {
+        match self {
+            Face::East => Face::West,
+            Face::West => Face::East,
+            Face::North => Face::South,
+            Face::South => Face::North,
+            Face::Up => Face::Down,
+            Face::Down => Face::Up,
+        }
+    }
+
+    /// The location the face looks at, if it exists
+    pub fn neighbor(self, location: GlobalLocation) -> Option<GlobalLocation> {
+        let Point3D { x, y, z } = location;
+        Some(match self {
+            Face::East => Point3D::new(x.checked_add(1)?, y, z),
+            Face::West => Point3D::new(x.checked_sub(1)?, y, z),
+            Face::North => Point3D::new(x, y.checked_add(1)?, z),
+            Face::South => Point3D::new(x, y.checked_sub(1)?, z),
+            Face::Up => Point3D::new(x, y, z.checked_add(1)?),
+            Face::Down => Point3D::new(x, y, z.checked_sub(1)?),
+        })
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A set of faces of one voxel
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct FaceSet(u8);
+
+impl FaceSet {
+    pub const NONE: FaceSet = FaceSet(0);
+    pub const ALL: FaceSet = FaceSet(0b11_1111);
+
+    pub fn contains(self, face: Face) -> bool {
+        self.0 & face.bit() != 0
+    }
+
+    pub fn insert(&mut self, face: Face) {
+        self.0 |= face.bit();
+    }
+
+    pub fn remove(&mut self, face: Face) {
+        self.0 &= !face.bit();
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Face> {
+        Face::ALL
+            .iter()
+            .copied()
+            .filter(move |face| self.contains(*face))
+    }
+}
+
+impl VoxelRegistry {
+    /// If a face of the type hides whatever is pressed against it. Faces of unknown types
+    /// hide everything
+    pub fn face_occludes(&self, id: u32, face: Face) -> bool {
+        match self.get(id) {
+            Some(voxel_type) => {
+                voxel_type.opacity >= 1.0 && !voxel_type.transparent_faces.contains(&face)
+            }
+            None => true,
+        }
+    }
+
+    /// If the type joins up with a neighbor of the other type, by name or by tag
+    pub fn connects(&self, id: u32, other: u32) -> bool {
+        match (self.get(id), self.get(other)) {
+            (Some(voxel_type), Some(other_type)) => {
+                voxel_type.connects_to.contains(&other_type.name)
+                    || other_type
+                        .tags
+                        .iter()
+                        .any(|tag| voxel_type.connects_to.contains(tag))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// If a face of the voxel should be drawn. Faces are hidden when the neighbor's touching
+/// face occludes them and both shapes fill the face, or when the neighbor is the same
+/// see-through type (so water and glass don't draw their insides). Faces at the edge of
+/// the map are drawn
+pub fn face_visible<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    location: GlobalLocation,
+    face: Face,
+) -> bool {
+    let voxel = map.get(location);
+    if registry.opacity_of(voxel.id) <= 0.0 {
+        return false;
+    }
+    // faces inside the cell, like the top of a bottom slab, can't be hidden by a neighbor
+    if !registry.shapes().covers_face(voxel.shape, face) {
+        return true;
+    }
+    match face.neighbor(location) {
+        Some(neighbor) if map.within_bounds(neighbor) => {
+            let neighbor = map.get(neighbor);
+            let hidden_by_same = neighbor.id == voxel.id && neighbor.shape == voxel.shape;
+            let occluded = registry.face_occludes(neighbor.id, face.opposite())
+                && registry
+                    .shapes()
+                    .covers_face(neighbor.shape, face.opposite());
+            !hidden_by_same && !occluded
+        }
+        _ => true,
+    }
+}
+
+/// Every face of the voxel that should be drawn
+pub fn visible_faces<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    location: GlobalLocation,
+) -> FaceSet {
+    let mut faces = FaceSet::NONE;
+    for face in Face::ALL.iter() {
+        if face_visible(map, registry, location, *face) {
+            faces.insert(*face);
+        }
+    }
+    faces
+}
+
+/// The sides the voxel joins up with its neighbors on, so fences and panes can pick the
+/// right model
+pub fn connections<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    location: GlobalLocation,
+) -> FaceSet {
+    let voxel = map.get(location);
+    let mut faces = FaceSet::NONE;
+    for face in Face::SIDES.iter() {
+        if let Some(neighbor) = face.neighbor(location) {
+            if map.within_bounds(neighbor) && registry.connects(voxel.id, map.get(neighbor).id) {
+                faces.insert(*face);
+            }
+        }
+    }
+    faces
+}
@@ -0,0 +1,97 @@
+use super::{GlobalLocation, Point3D, VoxelRead, VoxelWrite};
+
+/// A volume whose size is known at compile time, so it can live on the stack. Meant for
+/// small windows into the world (3x3x3 neighborhoods and the like) read in inner loops,
+/// where allocating a `Volume` every time would be too slow
+#[derive(Copy, Clone)]
+pub struct FixedVolume<T, const X: usize, const Y: usize, const Z: usize> {
+    start_location: GlobalLocation,
+    voxels: [[[T; X]; Y]; Z],
+}
+
+/// The immediate neighborhood of a voxel
+pub type Stencil3<T> = FixedVolume<T, 3, 3, 3>;
+
+/// The neighborhood of a voxel, two voxels out in every direction
+pub type Stencil5<T> = FixedVolume<T, 5, 5, 5>;
+
+impl<T: Copy + Default, const X: usize, const Y: usize, const Z: usize> FixedVolume<T, X, Y, Z> {
+    /// a new volume initialized to all value
+    pub fn new(start_location: GlobalLocation, value: T) -> FixedVolume<T, X, Y, Z> {
+        FixedVolume {
+            start_location,
+            voxels: [[[value; X]; Y]; Z],
+        }
+    }
+
+    /// Copies the window starting at start_location out of a map. Voxels that the map
+    /// cannot provide are left at their default value
+    pub fn from_read<M: VoxelRead<T>>(
+        map: &M,
+        start_location: GlobalLocation,
+    ) -> FixedVolume<T, X, Y, Z> {
+        let mut volume = FixedVolume::new(start_location, T::default());
+        for z in 0..Z {
+            for y in 0..Y {
+                for x in 0..X {
+                    let location = start_location + Point3D::new(x as i32, y as i32, z as i32);
+                    if map.within_bounds(location) {
+                        volume.voxels[z][y][x] = map.get(location);
+                    }
+                }
+            }
+        }
+        volume
+    }
+
+    /// Moves the window without touching its contents
+    pub fn set_start_location(&mut self, start_location: GlobalLocation) {
+        self.start_location = start_location;
+    }
+
+    pub fn within_bounds(&self, location: GlobalLocation) -> bool {
+        location.x >= self.start_location.x
+            && location.y >= self.start_location.y
+            && location.z >= self.start_location.z
+            && ((location.x - self.start_location.x) as usize) < X
+            && ((location.y - self.start_location.y) as usize) < Y
+            && ((location.z - self.start_location.z) as usize) < Z
+    }
+
+    pub fn get(&self, location: GlobalLocation) -> T {
+        let offset = location - self.start_location;
+        self.voxels[offset.z as usize][offset.y as usize][offset.x as usize]
+    }
+
+    pub fn set(&mut self, location: GlobalLocation, value: T) {
+        let offset = location - self.start_location;
+        self.voxels[offset.z as usize][offset.y as usize][offset.x as usize] = value;
+    }
+}
+
+impl<T: Copy + Default, const X: usize, const Y: usize, const Z: usize> VoxelRead<T>
+    for FixedVolume<T, X, Y, Z>
+{
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation) {
+        (
+            self.start_location,
+            self.start_location + Point3D::new(X as i32, Y as i32, Z as i32),
+        )
+    }
+
+    fn within_bounds(&self, location: GlobalLocation) -> bool {
+        FixedVolume::within_bounds(self, location)
+    }
+
+    fn get(&self, location: GlobalLocation) -> T {
+        FixedVolume::get(self, location)
+    }
+}
+
+impl<T: Copy + Default, const X: usize, const Y: usize, const Z: usize> VoxelWrite<T>
+    for FixedVolume<T, X, Y, Z>
+{
+    fn set(&mut self, location: GlobalLocation, value: T) {
+        FixedVolume::set(self, location, value);
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+/// The version of the on-disk world format this build writes, stamped into the metadata
+/// and every region file. Bumped whenever saves change in a way older builds can't read
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The oldest format version this build still reads. Worlds saved by any release since
+/// it keep loading; fixtures/ holds a world saved in each version to hold us to that
+pub const OLDEST_READABLE_FORMAT_VERSION: u32 = 1;
+
+/// If this build can read worlds saved in a format version
+pub fn can_read(version: u32) -> bool {
+    (OLDEST_READABLE_FORMAT_VERSION..=FORMAT_VERSION).contains(&version)
+}
+
+/// The format version a world folder was saved in, read from its metadata without loading
+/// the world, so games can check a save before opening it. None if the world's metadata
+/// was never saved
+pub fn saved_format_version<P: AsRef<Path>>(folder: P) -> io::Result<Option<u32>> {
+    let path = folder.as_ref().join("metadata");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut input = File::open(path)?;
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    Ok(Some(input.read_u32::<LittleEndian>()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::super::{Chunk, ChunkLocation, Dimension, Point3D, Voxel};
+    use super::*;
+
+    /// The world the fixture of a version holds. Later versions add content exercising
+    /// what they added to the format
+    fn fixture_world(version: u32) -> Dimension<Voxel> {
+        let air = Voxel {
+            id: 1,
+            ..Voxel::default()
+        };
+        let mut dimension = Dimension::new();
+        for location in [
+            ChunkLocation::new(0, 0, 0),
+            ChunkLocation::new(-1, 0, 0),
+            ChunkLocation::new(8, -3, 1),
+        ] {
+            dimension.add_chunk_in_place(location, Chunk::from_value(air));
+        }
+        for x in -16i32..16 {
+            for y in 0..16 {
+                let stone = Voxel {
+                    id: 3,
+                    shape: (x + y).rem_euclid(3) as u8,
+                    ..Voxel::default()
+                };
+                dimension.set_voxel(Point3D::new(x, y, 0), stone);
+            }
+        }
+        dimension.set_voxel(
+            Point3D::new(130, -40, 20),
+            Voxel {
+                id: 2,
+                ..Voxel::default()
+            },
+        );
+        dimension.set_waypoint("spawn", Point3D::new(1, 2, 1));
+        if version >= 2 {
+            // noise, which packs its palette indices, and more kinds of voxel than a palette
+            // holds, in runs
+            let noisy = ChunkLocation::new(0, 0, 1);
+            let varied = ChunkLocation::new(-1, 0, 1);
+            dimension.add_chunk_in_place(noisy, Chunk::from_value(air));
+            dimension.add_chunk_in_place(varied, Chunk::from_value(air));
+            for z in 16..32 {
+                for y in 0..16 {
+                    for x in 0..16i32 {
+                        let id = ((x * 7 + y * 13 + z * 5) % 4) as u32;
+                        dimension.set_voxel(
+                            Point3D::new(x, y, z),
+                            Voxel {
+                                id,
+                                ..Voxel::default()
+                            },
+                        );
+                        let id = 100 + ((z - 16) * 32 + y * 2 + x / 8) as u32;
+                        dimension.set_voxel(
+                            Point3D::new(x - 16, y, z),
+                            Voxel {
+                                id,
+                                ..Voxel::default()
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        dimension
+    }
+
+    fn fixture_folder(version: u32) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("format-v{}", version))
+    }
+
+    /// A copy of a fixture to load from, so loading can't change the fixture itself
+    fn copy_fixture(version: u32) -> PathBuf {
+        let copy = std::env::temp_dir().join(format!(
+            "rust-chunks-fixture-v{}-{}",
+            version,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&copy);
+        fs::create_dir_all(copy.join("regions")).unwrap();
+        fs::copy(
+            fixture_folder(version).join("metadata"),
+            copy.join("metadata"),
+        )
+        .unwrap();
+        for entry in fs::read_dir(fixture_folder(version).join("regions")).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, copy.join("regions").join(path.file_name().unwrap())).unwrap();
+        }
+        copy
+    }
+
+    #[test]
+    fn reads_every_readable_version() {
+        for version in OLDEST_READABLE_FORMAT_VERSION..=FORMAT_VERSION {
+            let expected = fixture_world(version);
+            assert!(can_read(version));
+            let folder = copy_fixture(version);
+            assert_eq!(saved_format_version(&folder).unwrap(), Some(version));
+
+            let mut world: Dimension<Voxel> = Dimension::new();
+            world.set_disk_cache(folder.to_str().unwrap());
+            world.load_metadata().unwrap();
+            let mut chunks: Vec<ChunkLocation> =
+                expected.all_chunk_locations.iter().copied().collect();
+            chunks.sort_by_key(|location| (location.z, location.y, location.x));
+            let mut loaded: Vec<ChunkLocation> =
+                world.all_chunk_locations.iter().copied().collect();
+            loaded.sort_by_key(|location| (location.z, location.y, location.x));
+            assert_eq!(loaded, chunks, "chunks of format v{}", version);
+            for location in chunks {
+                let chunk = world.get_chunk(location).unwrap().content_hash();
+                let expected = expected.loaded_chunks[&location].content_hash();
+                assert_eq!(
+                    chunk, expected,
+                    "chunk {:?} of format v{}",
+                    location, version
+                );
+            }
+            assert_eq!(world.waypoint("spawn"), Some(Point3D::new(1, 2, 1)));
+            fs::remove_dir_all(&folder).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(!can_read(FORMAT_VERSION + 1));
+        assert!(!can_read(OLDEST_READABLE_FORMAT_VERSION - 1));
+    }
+
+    /// Saves the fixture of the current version. Run with --ignored after bumping
+    /// FORMAT_VERSION, and commit the new fixture alongside the older ones
+    #[test]
+    #[ignore]
+    fn write_current_fixture() {
+        let folder = fixture_folder(FORMAT_VERSION);
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let mut world = fixture_world(FORMAT_VERSION);
+        world.set_disk_cache(folder.to_str().unwrap());
+        world.set_canonical_saves(true);
+        world.dirty_chunks.extend(world.all_chunk_locations.clone());
+        world.flush().unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::event_log::{ChunkBase, SeedGenerator};
+use super::occupancy::VoxelClass;
+use super::profiling::ChunkOp;
+use super::{Chunk, ChunkLocation, Dimension};
+
+/// What a generated chunk depends on: the seed, the version of the generator and where
+/// the chunk is
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GenerationKey {
+    pub seed: u64,
+    pub generator_version: u32,
+    pub location: ChunkLocation,
+}
+
+/// Chunks as the generator produced them, compressed, so regenerating a trimmed or
+/// corrupted chunk doesn't have to run the generator again. Holds up to a fixed number of
+/// chunks, forgetting the oldest first
+#[derive(Clone)]
+pub struct GenerationCache {
+    entries: HashMap<GenerationKey, Vec<u8>>,
+    /// keys in the order they were added
+    order: VecDeque<GenerationKey>,
+    max_entries: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl GenerationCache {
+    pub fn new(max_entries: usize) -> GenerationCache {
+        GenerationCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            max_entries,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&self, key: &GenerationKey) -> Option<&[u8]> {
+        self.entries.get(key).map(|payload| payload.as_slice())
+    }
+
+    pub fn insert(&mut self, key: GenerationKey, payload: Vec<u8>) {
+        if self.entries.insert(key, payload).is_none() {
+            self.order.push_back(key);
+        }
+        while self.entries.len() > self.max_entries {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Forgets every cached chunk, keeping the hit counts
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How many lookups found a chunk, and how many didn't
+    pub fn hit_rate(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    /// Moves the cache over to a new generator version. Chunks the upgrade changes are
+    /// dropped, the rest are kept as output of the new version
+    pub fn upgrade(
+        &mut self,
+        old_version: u32,
+        new_version: u32,
+        affected: &dyn Fn(ChunkLocation) -> bool,
+    ) {
+        let keys: Vec<GenerationKey> = self.order.iter().copied().collect();
+        self.order.clear();
+        for key in keys {
+            let payload = match self.entries.remove(&key) {
+                Some(payload) => payload,
+                None => continue,
+            };
+            if key.generator_version != old_version {
+                // output of other versions is left alone
+                self.entries.insert(key, payload);
+                self.order.push_back(key);
+            } else if !affected(key.location) {
+                let key = GenerationKey {
+                    generator_version: new_version,
+                    ..key
+                };
+                self.entries.insert(key, payload);
+                self.order.push_back(key);
+            }
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Keeps up to max_entries generated chunks around for regenerating them
+    pub fn enable_generation_cache(&mut self, max_entries: usize) {
+        self.generation_cache = Some(GenerationCache::new(max_entries));
+    }
+
+    pub fn disable_generation_cache(&mut self) {
+        self.generation_cache = None;
+    }
+
+    pub fn generation_cache(&self) -> Option<&GenerationCache> {
+        self.generation_cache.as_ref()
+    }
+
+    /// The version of the generator chunks are currently generated with
+    pub fn generator_version(&self) -> u32 {
+        self.generator_version
+    }
+
+    /// Switches to a new generator version. Cached chunks the upgrade changes are dropped
+    /// and the others carried over, so only they get generated again. Event sourced chunks
+    /// logged on top of the old version fail to load afterwards, so compact them first
+    pub fn upgrade_generator(&mut self, version: u32, affected: &dyn Fn(ChunkLocation) -> bool) {
+        let old_version = self.generator_version;
+        self.generator_version = version;
+        if let Some(cache) = &mut self.generation_cache {
+            cache.upgrade(old_version, version, affected);
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Generates a chunk from the seed, recording it if it was slow
+    fn timed_generate(&mut self, location: ChunkLocation, generate: SeedGenerator<T>) -> Chunk<T> {
+        let started = Instant::now();
+        let chunk = generate(self.generation_seed, location);
+        self.record_op(ChunkOp::Generate, location, started);
+        chunk
+    }
+
+    /// Generates a chunk from the world seed, reusing the cached output of the current
+    /// generator version if there is one
+    pub fn generate_chunk_cached(
+        &mut self,
+        location: ChunkLocation,
+        generate: SeedGenerator<T>,
+    ) -> Result<Chunk<T>> {
+        let key = GenerationKey {
+            seed: self.generation_seed,
+            generator_version: self.generator_version,
+            location,
+        };
+        let cached = match &self.generation_cache {
+            Some(cache) => cache.get(&key).map(|payload| payload.to_vec()),
+            None => return Ok(self.timed_generate(location, generate)),
+        };
+        if let Some(compressed) = cached {
+            self.generation_cache.as_mut().unwrap().hits += 1;
+            return Ok(Chunk::decode_payload(
+                &self.decompress_payload(&compressed)?,
+            )?);
+        }
+        let chunk = self.timed_generate(location, generate);
+        let compressed = self.compress_payload(&chunk.encode_payload()?)?;
+        let cache = self.generation_cache.as_mut().unwrap();
+        cache.misses += 1;
+        cache.insert(key, compressed);
+        Ok(chunk)
+    }
+
+    /// Rebuilds a chunk from its log like replay_chunk_log, generating its base through
+    /// the generation cache
+    pub fn replay_chunk_log_cached(
+        &mut self,
+        location: ChunkLocation,
+        generate: SeedGenerator<T>,
+    ) -> Result<Option<Chunk<T>>> {
+        let log = match self.chunk_logs.get(&location) {
+            Some(log) => log.clone(),
+            None => return Ok(None),
+        };
+        // the log keeps its own seed and generator version, which only match the cache if
+        // the world's are unchanged
+        let cached = match log.base {
+            ChunkBase::Generated {
+                seed,
+                generator_version,
+            } if seed == self.generation_seed && generator_version == self.generator_version => {
+                Some(self.generate_chunk_cached(location, generate)?)
+            }
+            _ => None,
+        };
+        let chunk = log.replay(location, &|seed, location| match &cached {
+            Some(chunk) => chunk.clone(),
+            None => generate(seed, location),
+        })?;
+        Ok(Some(chunk))
+    }
+}
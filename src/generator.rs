@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::{Chunk, ChunkLocation, Dimension};
+
+/// Builds the chunks of a world as they are first accessed, so infinite worlds don't have
+/// to be defined chunk by chunk up front. Generators must be deterministic, since chunks
+/// that were never edited aren't saved and are generated again when next needed
+pub trait ChunkGenerator<T>: Send + Sync {
+    fn generate(&self, location: ChunkLocation) -> Chunk<T>;
+}
+
+impl<T, F: Fn(ChunkLocation) -> Chunk<T> + Send + Sync> ChunkGenerator<T> for F {
+    fn generate(&self, location: ChunkLocation) -> Chunk<T> {
+        self(location)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Generates chunks that aren't defined when they are accessed, instead of panicking.
+    /// Event sourced chunks based on a generated chunk are rebuilt with it too
+    pub fn set_generator(&mut self, generator: Arc<dyn ChunkGenerator<T>>) {
+        self.generator = Some(generator);
+    }
+
+    pub fn clear_generator(&mut self) {
+        self.generator = None;
+    }
+
+    pub fn has_generator(&self) -> bool {
+        self.generator.is_some()
+    }
+
+    /// If a chunk can be loaded, because it is defined, can be generated, or might be in
+    /// the chunk store
+    pub fn chunk_available(&self, location: ChunkLocation) -> bool {
+        self.generator.is_some() || self.chunk_store.is_some() || self.chunk_defined(location)
+    }
+
+    /// Runs the generator for a chunk, through the generation cache if it is enabled. None
+    /// if there is no generator
+    pub fn generate_chunk(&mut self, location: ChunkLocation) -> Result<Option<Chunk<T>>> {
+        let generator = match &self.generator {
+            Some(generator) => Arc::clone(generator),
+            None => return Ok(None),
+        };
+        self.generate_chunk_cached(location, &|_, location| generator.generate(location))
+            .map(Some)
+    }
+}
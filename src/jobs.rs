@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::mesh::{chunk_mesh_empty, mesh_chunk, Mesh};
+use super::occupancy::EmptyVoxel;
+use super::priority::Priority;
+use super::registry::VoxelRegistry;
+use super::{
+    ChunkLocation, Dimension, GlobalLocation, Point3D, Volume, Voxel, VoxelRead, CHUNK_X_SIZE,
+    CHUNK_Y_SIZE, CHUNK_Z_SIZE,
+};
+
+/// Work to be done off the main thread for a chunk, like meshing or lighting it
+pub type Job<O> = Box<dyn FnOnce() -> O + Send>;
+
+/// What a scheduler is doing, for deciding whether to feed it more work
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct JobStats {
+    /// jobs waiting to be handed to a worker
+    pub pending: usize,
+    /// jobs being worked on
+    pub in_flight: usize,
+    /// results waiting to be taken
+    pub finished: usize,
+    /// if no more jobs are started until results are taken
+    pub backpressured: bool,
+}
+
+/// Runs chunk jobs on worker threads with bounded memory. Jobs are only started while the
+/// jobs in flight plus the results nobody has taken yet stay under a limit, so a slow
+/// consumer like the render thread stalls the scheduler instead of piling up meshes
+pub struct JobScheduler<O: Send + 'static> {
+    /// jobs not started yet, oldest first
+    pending: VecDeque<(ChunkLocation, Job<O>)>,
+    /// the chunks of the pending jobs
+    pending_chunks: HashSet<ChunkLocation>,
+    in_flight: usize,
+    /// results received from the workers but not taken yet
+    finished: VecDeque<(ChunkLocation, O)>,
+    /// the most jobs in flight and results not taken there may be at once
+    max_outstanding: usize,
+    /// picks which pending job starts next, oldest first if absent
+    priority: Option<Arc<dyn Priority>>,
+    jobs: Option<Sender<(ChunkLocation, Job<O>)>>,
+    results: Receiver<(ChunkLocation, O)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<O: Send + 'static> JobScheduler<O> {
+    pub fn new(worker_count: usize, max_outstanding: usize) -> JobScheduler<O> {
+        assert!(worker_count > 0, "a scheduler needs at least one worker");
+        assert!(
+            max_outstanding > 0,
+            "a scheduler must allow at least one job"
+        );
+        let (jobs, job_receiver) = mpsc::channel::<(ChunkLocation, Job<O>)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..worker_count)
+            .map(|_| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                thread::spawn(move || loop {
+                    // the lock is only held while waiting for the next job
+                    let next = job_receiver.lock().unwrap().recv();
+                    match next {
+                        Ok((location, job)) => {
+                            if result_sender.send((location, job())).is_err() {
+                                break;
+                            }
+                        }
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+        JobScheduler {
+            pending: VecDeque::new(),
+            pending_chunks: HashSet::new(),
+            in_flight: 0,
+            finished: VecDeque::new(),
+            max_outstanding,
+            priority: None,
+            jobs: Some(jobs),
+            results,
+            workers,
+        }
+    }
+
+    /// Queues a job for a chunk. A job already waiting for the same chunk is replaced, since
+    /// its result would be out of date anyway
+    pub fn submit(&mut self, location: ChunkLocation, job: Job<O>) {
+        if !self.pending_chunks.insert(location) {
+            self.pending.retain(|(pending, _)| *pending != location);
+        }
+        self.pending.push_back((location, job));
+        self.pump();
+    }
+
+    /// Collects finished results and starts as many pending jobs as the limit allows
+    pub fn pump(&mut self) {
+        while let Ok(result) = self.results.try_recv() {
+            self.in_flight -= 1;
+            self.finished.push_back(result);
+        }
+        while self.in_flight + self.finished.len() < self.max_outstanding {
+            let (location, job) = match self.next_pending() {
+                Some(next) => next,
+                None => break,
+            };
+            self.pending_chunks.remove(&location);
+            self.jobs.as_ref().unwrap().send((location, job)).unwrap();
+            self.in_flight += 1;
+        }
+    }
+
+    /// Removes the pending job to start next, the most urgent by the priority
+    fn next_pending(&mut self) -> Option<(ChunkLocation, Job<O>)> {
+        let index = match &self.priority {
+            Some(priority) => {
+                let mut best: Option<(usize, f32)> = None;
+                for (index, (location, _)) in self.pending.iter().enumerate() {
+                    let score = priority.score(*location);
+                    if best.is_none_or(|(_, best_score)| score > best_score) {
+                        best = Some((index, score));
+                    }
+                }
+                best?.0
+            }
+            None => 0,
+        };
+        self.pending.remove(index)
+    }
+
+    /// Orders pending jobs by a priority instead of oldest first
+    pub fn set_priority(&mut self, priority: Arc<dyn Priority>) {
+        self.priority = Some(priority);
+    }
+
+    /// Takes up to max finished results, oldest first, making room for more jobs
+    pub fn take_finished(&mut self, max: usize) -> Vec<(ChunkLocation, O)> {
+        self.pump();
+        let count = max.min(self.finished.len());
+        let taken = self.finished.drain(..count).collect();
+        self.pump();
+        taken
+    }
+
+    /// If the limit is reached, so no more jobs start until results are taken
+    pub fn is_backpressured(&self) -> bool {
+        self.in_flight + self.finished.len() >= self.max_outstanding
+    }
+
+    pub fn set_max_outstanding(&mut self, max_outstanding: usize) {
+        assert!(
+            max_outstanding > 0,
+            "a scheduler must allow at least one job"
+        );
+        self.max_outstanding = max_outstanding;
+        self.pump();
+    }
+
+    pub fn stats(&self) -> JobStats {
+        JobStats {
+            pending: self.pending.len(),
+            in_flight: self.in_flight,
+            finished: self.finished.len(),
+            backpressured: self.is_backpressured(),
+        }
+    }
+}
+
+impl<O: Send + 'static> Drop for JobScheduler<O> {
+    fn drop(&mut self) {
+        // closing the job channel stops the workers once they finish their current job
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Dimension<Voxel> {
+    /// A job meshing a chunk, working on a copy of the chunk and the voxels around it so
+    /// the world can keep changing while it runs
+    pub fn mesh_job(&self, chunk: ChunkLocation, lod: u32, neighbor_lods: [u32; 6]) -> Job<Mesh> {
+        let border = 1i32 << lod;
+        let size = Point3D::new(
+            CHUNK_X_SIZE as i32,
+            CHUNK_Y_SIZE as i32,
+            CHUNK_Z_SIZE as i32,
+        );
+        let chunk_start = Point3D::new(chunk.x * size.x, chunk.y * size.y, chunk.z * size.z);
+        let registry = match &self.registry {
+            Some(registry) => Arc::clone(registry),
+            None => Arc::new(VoxelRegistry::builtin().clone()),
+        };
+        if chunk_mesh_empty(self, &registry, chunk, lod, neighbor_lods) {
+            return Box::new(Mesh::new);
+        }
+        let border = Point3D::new(border, border, border);
+        let start = chunk_start - border;
+        let end = chunk_start + size + border;
+        // voxels that aren't loaded are copied as empty, so faces facing them are drawn
+        let mut snapshot = Volume::new(start, end, Voxel::empty());
+        for z in start.z..end.z {
+            for y in start.y..end.y {
+                for x in start.x..end.x {
+                    let location: GlobalLocation = Point3D::new(x, y, z);
+                    if VoxelRead::within_bounds(self, location) {
+                        snapshot.set(location, VoxelRead::get(self, location));
+                    }
+                }
+            }
+        }
+        Box::new(move || mesh_chunk(&snapshot, &registry, chunk, lod, neighbor_lods))
+    }
+}
@@ -1,20 +1,163 @@
 #![allow(dead_code, unused_variables)]
 
 extern crate byteorder;
+#[cfg(feature = "encryption")]
+extern crate chacha20poly1305;
+#[cfg(feature = "rand")]
+extern crate rand_core;
+#[cfg(feature = "rapier")]
+extern crate rapier3d;
+extern crate ron;
+extern crate serde;
+extern crate tar;
+extern crate thiserror;
+extern crate toml;
+extern crate twox_hash;
+#[cfg(feature = "remote")]
+extern crate ureq;
+extern crate zstd;
+
+mod anchors;
+mod archive;
+mod bounds;
+mod changes;
+mod chunk_local;
+mod claims;
+mod codec;
+mod cold_storage;
+#[cfg(feature = "rapier")]
+mod colliders;
+mod compression;
+mod conflict;
+#[cfg(feature = "console")]
+mod console;
+mod costmap;
+mod damage;
+mod dirty_regions;
+mod drops;
+mod edit;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod error;
+mod event_log;
+mod eviction;
+mod faces;
+mod fixed_volume;
+mod format;
+mod generation_cache;
+mod generator;
+mod jobs;
+mod lighting;
+mod merge;
+mod mesh;
+mod metadata;
+mod multiblock;
+mod occupancy;
+mod origin;
+mod path_cache;
+mod path_costs;
+mod path_streaming;
+mod pathfinding;
+mod patterns;
+mod point_list;
+mod priority;
+mod procedural;
+mod profiling;
+mod prune;
+mod rate_limit;
+mod raycast;
+mod reachability;
+mod region;
+mod region_load;
+mod registry;
+mod render;
+mod rivers;
+mod rng;
+mod schema;
+mod shapes;
+mod sky;
+mod spatial_index;
+mod stats;
+mod storage;
+mod store;
+mod store_policy;
+mod structures;
+mod tags;
+mod terrain;
+mod tombstones;
+mod traversal;
+mod void;
+mod volume;
+mod waypoints;
+mod weather;
+mod world_hash;
 
 use std::cmp::Ordering;
 
 use std::hash::{Hash, Hasher};
 
 use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
 use std::fs::File;
+use std::io;
 use std::io::BufReader;
 use std::io::BufWriter;
 
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use anchors::Anchors;
+use bounds::OutOfBounds;
+use changes::ChangeFeed;
+use claims::Claims;
+use codec::VoxelCodec;
+use cold_storage::ColdTier;
+use damage::DamageMap;
+use dirty_regions::DirtyRegions;
+use drops::DropTables;
+use edit::Validator;
+#[cfg(feature = "encryption")]
+use encryption::Keyring;
+use error::{Error, Result};
+use event_log::{ChunkLog, PersistenceMode};
+use eviction::{ChunkBudget, ChunkLru};
+use faces::Face;
+use generation_cache::GenerationCache;
+use generator::ChunkGenerator;
+use lighting::LightStore;
+use multiblock::Multiblocks;
+use occupancy::{ChunkSummary, VoxelClass};
+use path_costs::{PathCostOverrides, IMPASSABLE};
+use pathfinding::MovementProfile;
+use priority::Priority;
+use profiling::{AccessProfile, ChunkOp, SlowOpLog};
+use prune::ChunkHistories;
+use rate_limit::EditLimiter;
+use region_load::RegionLoadState;
+use registry::{PropertyValue, VoxelRegistry};
+use rng::{BoxedRng, Pcg32};
+use schema::VoxelSchema;
+use shapes::ShapeId;
+use sky::SkyColumns;
+use stats::WorldStats;
+use storage::ChunkStorage;
+use store::ChunkStore;
+use store_policy::{StoreFailurePolicy, StoreListener};
+use structures::StructureLibrary;
+use tombstones::Tombstones;
+use traversal::Agent;
+use void::Void;
+use waypoints::Waypoints;
+use weather::WeatherLayer;
+
 const CHUNK_X_SIZE: usize = 16;
 const CHUNK_Y_SIZE: usize = 16;
 const CHUNK_Z_SIZE: usize = 16;
@@ -22,17 +165,18 @@ const CHUNK_VOLUME: usize = CHUNK_X_SIZE * CHUNK_Y_SIZE * CHUNK_Z_SIZE;
 const DATA_SEGMENT_SIZE: usize = 256;
 
 /// 256 bytes of data, to be used for any purpose
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 struct DataSegment {
     data: [u8; DATA_SEGMENT_SIZE],
 }
 
-///A point in 3D space
-#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+///A point in 3D space. Coordinates are signed, so worlds extend in every direction from
+/// the origin
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq, Debug)]
 struct Point3D {
-    x: u32,
-    y: u32,
-    z: u32,
+    x: i32,
+    y: i32,
+    z: i32,
 }
 
 /// The location of a Chunk in relation to the world
@@ -44,13 +188,22 @@ type GlobalLocation = Point3D;
 /// The location of a single voxel in relation to its chunk
 type VoxelLocation = Point3D;
 
+/// An axis aligned box of voxels in the world. start is inside the box, end is just past it
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+struct Aabb {
+    start: GlobalLocation,
+    end: GlobalLocation,
+}
+
 /// Represents a collection of voxels that may be loaded and unloaded together
 #[derive(Clone)]
 struct Chunk<T> {
     /// the voxels contained within this chunk, it's a cube
-    voxels: [T; CHUNK_VOLUME],
+    voxels: ChunkStorage<T>,
     /// Extra data
     extra_data: Option<DataSegment>,
+    /// What kinds of voxels the chunk holds
+    summary: ChunkSummary,
 }
 
 /// Represents many chunks that form a world
@@ -62,6 +215,113 @@ struct Dimension<T> {
     all_chunk_locations: HashSet<ChunkLocation>,
     ///the folder where the dimension will be saved
     disk_cache: Option<String>,
+    /// Regions protected from edits
+    claims: Claims,
+    /// The box the world is limited to, if any
+    bounds: Option<Aabb>,
+    /// How accesses outside of bounds are handled
+    out_of_bounds: OutOfBounds,
+    /// What lies below and above the world
+    void: Void<T>,
+    /// The layout of the voxels, if declared
+    schema: Option<VoxelSchema>,
+    /// A zstd dictionary trained on this world's chunks, if any
+    compression_dictionary: Option<Vec<u8>>,
+    /// Dictionaries chunks on disk were compressed with before, by hash
+    retired_dictionaries: BTreeMap<u64, Vec<u8>>,
+    /// The points the world is kept ready around
+    anchors: Anchors,
+    /// Chunk access counts, if profiling is enabled
+    access_profile: Option<AccessProfile>,
+    /// The absolute location that local floating point coordinates are relative to
+    origin: GlobalLocation,
+    /// Pathfinding costs that differ from the default
+    path_cost_overrides: PathCostOverrides,
+    /// Whether chunks are saved whole or as edit logs
+    persistence_mode: PersistenceMode,
+    /// The seed the chunks are generated from
+    generation_seed: u64,
+    /// The edits made to each chunk, when event sourcing
+    chunk_logs: HashMap<ChunkLocation, ChunkLog<T>>,
+    /// The voxel types of the world, if they aren't the builtin ones
+    registry: Option<Arc<VoxelRegistry>>,
+    /// The voxel type names the world was saved with, by id
+    saved_voxel_ids: Option<BTreeMap<u32, String>>,
+    /// The light level of every voxel, once lit
+    light: LightStore,
+    /// The highest opaque voxel of every loaded column
+    sky: SkyColumns,
+    /// The order chunks are loaded in, if not nearest first
+    chunk_priority: Option<Arc<dyn Priority>>,
+    /// Where chunks missing from the disk cache are fetched from
+    chunk_store: Option<Arc<dyn ChunkStore>>,
+    /// Game rules edits are checked against
+    validator: Option<Validator<T>>,
+    /// Rate limits and pending batches of the edit sources
+    edit_limiter: EditLimiter<T>,
+    /// The version of the generator chunks are generated with
+    generator_version: u32,
+    /// Chunks as they were generated, if caching them is enabled
+    generation_cache: Option<GenerationCache>,
+    /// How many voxels of each type were placed and broken
+    stats: WorldStats,
+    /// The regions load_region_async is waiting on
+    region_loads: Vec<Arc<Mutex<RegionLoadState>>>,
+    /// Loaded chunks changed since they were last written to disk
+    dirty_chunks: HashSet<ChunkLocation>,
+    /// Loaded chunks still just as the generator made them
+    generated_chunks: HashSet<ChunkLocation>,
+    /// A stamp for each loaded chunk, replaced whenever its voxels change
+    chunk_stamps: HashMap<ChunkLocation, u64>,
+    /// What happens when the disk cache can't be read or written
+    store_failure_policy: StoreFailurePolicy,
+    /// Told about disk cache failures, if set
+    store_listener: Option<StoreListener>,
+    /// If writes to the disk cache are failing and the world only lives in memory
+    degraded: bool,
+    /// How many chunks may be loaded at once, if limited
+    chunk_budget: Option<ChunkBudget>,
+    /// The order the loaded chunks were last used in, while there is a budget
+    chunk_lru: ChunkLru,
+    /// Chunk operations that took too long, if they are being recorded
+    slow_op_log: Option<SlowOpLog>,
+    /// Structure templates and where copies of them are placed
+    structures: StructureLibrary<T>,
+    /// If voxel reads see placed structures
+    resolve_structures: bool,
+    /// Removed chunks that can still be restored
+    tombstones: Tombstones<T>,
+    /// Named locations saved with the world
+    waypoints: Waypoints,
+    /// Chunks moved out of the disk cache for going unused
+    cold_storage: ColdTier,
+    /// How far along breaking each damaged voxel is
+    damage: DamageMap,
+    /// What broken voxels leave behind
+    drop_tables: DropTables,
+    /// The coarse climate of the world, for farming and biome blending
+    weather: WeatherLayer,
+    /// What each chunk has been through, for pruning the untouched ones
+    histories: ChunkHistories,
+    /// The kinds of multiblocks and the ones standing in the world
+    multiblocks: Multiblocks<T>,
+    /// Where the chance events of the world draw from
+    rng: BoxedRng,
+    /// The world tick, and who is told about voxel changes
+    changes: ChangeFeed<T>,
+    /// The chunks whose surface meshes went stale, if that is tracked
+    stale_meshes: Option<HashSet<ChunkLocation>>,
+    /// The parts of chunks written since they were last taken, if that is tracked
+    dirty_regions: Option<DirtyRegions>,
+    /// If saves are byte for byte the same for the same content
+    canonical_saves: bool,
+    /// How many region files flush writes at once
+    flush_threads: usize,
+    /// Builds chunks that aren't defined when they are accessed, if set
+    generator: Option<Arc<dyn ChunkGenerator<T>>>,
+    /// The keys chunks are encrypted with on disk, if any
+    #[cfg(feature = "encryption")]
+    encryption: Option<Keyring>,
 }
 
 ///Represents a particular section of a dimension
@@ -69,14 +329,42 @@ struct Dimension<T> {
 struct Volume<T> {
     start_location: GlobalLocation,
     end_location: GlobalLocation,
-    x_size: u32,
-    y_size: u32,
-    z_size: u32,
+    x_size: i32,
+    y_size: i32,
+    z_size: i32,
     voxels: Vec<T>,
 }
 
+/// Anything voxels can be read from, so algorithms don't care how the voxels are stored
+trait VoxelRead<T> {
+    /// the first location inside, and the location just past the end
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation);
+
+    /// If the location can be read
+    fn within_bounds(&self, location: GlobalLocation) -> bool;
+
+    fn get(&self, location: GlobalLocation) -> T;
+
+    /// If every voxel stored alongside location is known to be solid, so algorithms can skip
+    /// the whole area without reading it. False means unknown
+    fn known_all_solid(&self, location: GlobalLocation) -> bool {
+        false
+    }
+
+    /// If every voxel stored alongside location is known to be empty. False means unknown
+    fn known_all_empty(&self, location: GlobalLocation) -> bool {
+        false
+    }
+}
+
+/// Anything voxels can be written to
+trait VoxelWrite<T>: VoxelRead<T> {
+    fn set(&mut self, location: GlobalLocation, value: T);
+}
+
 impl Point3D {
-    fn new(x: u32, y: u32, z: u32) -> Point3D {
+    #[allow(clippy::redundant_field_names)]
+    fn new(x: i32, y: i32, z: i32) -> Point3D {
         Point3D { x: x, y: y, z: z }
     }
 }
@@ -105,8 +393,35 @@ impl std::ops::Sub for Point3D {
     }
 }
 
-/// TODO please flesh out the scope struct. It represents an arbitrary 3d
-/// portion of the world that is backed up by chunks, kinda like a world
+impl Aabb {
+    fn new(start: GlobalLocation, end: GlobalLocation) -> Aabb {
+        Aabb { start, end }
+    }
+
+    /// If the box contains no voxels at all
+    fn is_empty(&self) -> bool {
+        self.end.x <= self.start.x || self.end.y <= self.start.y || self.end.z <= self.start.z
+    }
+
+    fn contains(&self, location: GlobalLocation) -> bool {
+        location.x >= self.start.x
+            && location.y >= self.start.y
+            && location.z >= self.start.z
+            && location.x < self.end.x
+            && location.y < self.end.y
+            && location.z < self.end.z
+    }
+
+    /// If the two boxes share at least one voxel
+    fn intersects(&self, other: &Aabb) -> bool {
+        self.start.x < other.end.x
+            && other.start.x < self.end.x
+            && self.start.y < other.end.y
+            && other.start.y < self.end.y
+            && self.start.z < other.end.z
+            && other.start.z < self.end.z
+    }
+}
 
 impl DataSegment {
     fn new() -> DataSegment {
@@ -125,7 +440,7 @@ impl DataSegment {
     }
 }
 
-impl<T: Copy + Default> Chunk<T> {
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Chunk<T> {
     fn new() -> Chunk<T> {
         Chunk::from_value(Default::default())
     }
@@ -135,17 +450,19 @@ impl<T: Copy + Default> Chunk<T> {
         Chunk::from_value_with_extra_data(value, None)
     }
 
+    #[allow(clippy::redundant_field_names)]
     fn from_value_with_extra_data(value: T, extra_data: Option<DataSegment>) -> Chunk<T> {
         Chunk {
-            voxels: [value; CHUNK_VOLUME],
+            voxels: ChunkStorage::Uniform(value),
             extra_data: extra_data,
+            summary: ChunkSummary::uniform(&value),
         }
     }
 
-    fn from_buf_reader(stream: &mut BufReader<File>) -> Chunk<T> {
+    fn from_buf_reader(stream: &mut BufReader<File>) -> io::Result<Chunk<T>> {
         let mut chunk = Chunk::new();
-        chunk.read(stream);
-        chunk
+        chunk.read(stream)?;
+        Ok(chunk)
     }
 
     fn get_index(location: VoxelLocation) -> usize {
@@ -155,53 +472,161 @@ impl<T: Copy + Default> Chunk<T> {
     }
 
     fn get(&self, location: VoxelLocation) -> T {
-        self.voxels[Self::get_index(location)]
+        self.voxels.get(Self::get_index(location))
     }
 
+    #[allow(clippy::unused_unit)]
     fn set(&mut self, location: VoxelLocation, value: T) -> () {
-        self.voxels[Self::get_index(location)] = value;
+        let index = Self::get_index(location);
+        self.summary.replace(&self.voxels.get(index), &value);
+        self.voxels.set(index, value);
+    }
+
+    /// Roughly how many bytes the chunk takes in memory
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Chunk<T>>() + self.voxels.memory_size()
+    }
+
+    /// Switches the voxels to the most compact storage that holds them
+    fn optimize(&mut self) {
+        self.voxels.optimize();
+    }
+
+    /// Stores the voxels the one way they are always stored when saving canonically
+    fn canonicalize(&mut self) {
+        self.voxels.canonicalize();
     }
 
     /// Reads from saved file
-    fn read(&mut self, stream: &mut BufReader<File>) -> () {
-        //TODO implement serde serialization
+    fn read(&mut self, stream: &mut BufReader<File>) -> io::Result<()> {
+        *self = Chunk::read_from(stream)?;
+        Ok(())
     }
 
     /// writes to file
-    fn write(stream: &mut BufWriter<File>, chunk: Chunk<T>) -> () {
-        //TODO implement serde serialization
+    fn write(stream: &mut BufWriter<File>, chunk: Chunk<T>) -> io::Result<()> {
+        chunk.write_to(stream)
     }
 }
 
-impl<T: Copy + Default> Dimension<T> {
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
     fn new() -> Dimension<T> {
         Dimension {
             loaded_chunks: HashMap::new(),
             all_chunk_locations: HashSet::new(),
             disk_cache: None, //TODO please set disk cache and figure this out
+            claims: Claims::new(),
+            bounds: None,
+            out_of_bounds: OutOfBounds::Error,
+            void: Void::new(),
+            schema: None,
+            compression_dictionary: None,
+            retired_dictionaries: BTreeMap::new(),
+            anchors: Anchors::new(),
+            access_profile: None,
+            origin: GlobalLocation::default(),
+            path_cost_overrides: PathCostOverrides::new(),
+            persistence_mode: PersistenceMode::Snapshot,
+            generation_seed: 0,
+            chunk_logs: HashMap::new(),
+            registry: None,
+            saved_voxel_ids: None,
+            light: LightStore::new(),
+            sky: SkyColumns::new(),
+            chunk_priority: None,
+            chunk_store: None,
+            validator: None,
+            edit_limiter: EditLimiter::new(),
+            generator_version: 0,
+            generation_cache: None,
+            stats: WorldStats::new(),
+            region_loads: Vec::new(),
+            dirty_chunks: HashSet::new(),
+            generated_chunks: HashSet::new(),
+            chunk_stamps: HashMap::new(),
+            store_failure_policy: StoreFailurePolicy::FailFast,
+            store_listener: None,
+            degraded: false,
+            chunk_budget: None,
+            chunk_lru: ChunkLru::new(),
+            slow_op_log: None,
+            structures: StructureLibrary::new(),
+            resolve_structures: false,
+            tombstones: Tombstones::new(),
+            waypoints: Waypoints::new(),
+            cold_storage: ColdTier::new(),
+            damage: DamageMap::new(),
+            drop_tables: DropTables::new(),
+            weather: WeatherLayer::new(),
+            histories: ChunkHistories::new(),
+            multiblocks: Multiblocks::new(),
+            rng: Box::new(Pcg32::seeded(0)),
+            changes: ChangeFeed::new(),
+            stale_meshes: None,
+            dirty_regions: None,
+            canonical_saves: false,
+            flush_threads: 4,
+            generator: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
         }
     }
 
+    /// Sets the folder the dimension is saved in
+    fn set_disk_cache(&mut self, folder: &str) {
+        self.disk_cache = Some(String::from(folder));
+    }
+
     /// Adds a chunk to the location
+    #[allow(clippy::unused_unit)]
     fn add_chunk_in_place(&mut self, location: ChunkLocation, chunk: Chunk<T>) -> () {
         self.all_chunk_locations.insert(location);
         self.loaded_chunks.insert(location, chunk);
+        self.dirty_chunks.insert(location);
+        self.generated_chunks.remove(&location);
+        self.chunk_changed(location);
+        // chunks made by hand or fetched from elsewhere can't be generated again
+        self.chunk_edited(location);
+        self.sky_chunk_added(location);
+        self.chunk_mesh_changed(location);
+        self.touch_chunk(location);
+        self.touch_cold_timer(location);
+        // chunks whose writes fail stay loaded, and the failure shows up on the next flush
+        let _ = self.evict_over_budget();
     }
 
-    /// Remove chunk from location, if it exists
-    fn remove_chunk_in_place(&mut self, location: ChunkLocation) -> () {
-        self.all_chunk_locations.remove(&location.clone());
-        self.loaded_chunks.remove(&location.clone());
+    /// Drops a chunk from memory without saving it. It stays defined, so it is loaded
+    /// again when needed
+    fn discard_chunk(&mut self, location: ChunkLocation) {
+        self.dirty_chunks.remove(&location);
+        self.generated_chunks.remove(&location);
+        self.chunk_stamps.remove(&location);
+        self.chunk_lru.forget(location);
+        if self.loaded_chunks.remove(&location).is_some() {
+            self.touch_cold_timer(location);
+            self.sky_chunk_removed(location);
+            self.chunk_mesh_changed(location);
+            self.light.forget_chunk(location);
+        }
     }
 
-    /// Gets a chunk, loading it if unavailable
-    fn get_chunk(&mut self, location: ChunkLocation) -> &Chunk<T> {
-        if !self.chunk_defined(location) {
-            panic!("chunk undefined");
-        } else if !self.chunk_loaded(location) {
-            self.load_chunk(location);
+    /// Gets a chunk, loading it if unavailable. Fails with Error::UndefinedChunk if the
+    /// chunk isn't defined and can't be generated
+    fn get_chunk(&mut self, location: ChunkLocation) -> Result<&Chunk<T>> {
+        self.get_chunk_mut(location).map(|chunk| &*chunk)
+    }
+
+    /// Gets a chunk for modification, loading it if unavailable
+    fn get_chunk_mut(&mut self, location: ChunkLocation) -> Result<&mut Chunk<T>> {
+        if !self.chunk_available(location) {
+            return Err(Error::UndefinedChunk { chunk: location });
+        }
+        self.record_access(location);
+        self.touch_chunk(location);
+        if !self.chunk_loaded(location) {
+            self.load_chunk(location)?;
         }
-        self.loaded_chunks.get(&location).unwrap()
+        Ok(self.loaded_chunks.get_mut(&location).unwrap())
     }
 
     /// If a chunk has been loaded
@@ -214,48 +639,277 @@ impl<T: Copy + Default> Dimension<T> {
         self.all_chunk_locations.contains(&location)
     }
 
-    /// Loads chunk from disk
-    fn load_chunk(&mut self, location: ChunkLocation) -> () {
-        //TODO load chunk from disk cache
+    /// Gives a loaded chunk a new stamp. Stamps come from one counter shared by every
+    /// world, so no two versions of any chunk share one, even across cloned worlds
+    fn chunk_changed(&mut self, location: ChunkLocation) {
+        static NEXT_STAMP: AtomicU64 = AtomicU64::new(0);
+        let stamp = NEXT_STAMP.fetch_add(1, AtomicOrdering::Relaxed);
+        self.chunk_stamps.insert(location, stamp);
+    }
+
+    /// The stamp of a loaded chunk, the same for as long as its voxels don't change. Caches
+    /// built from chunks can compare stamps instead of voxels to tell what went stale
+    fn chunk_stamp(&self, location: ChunkLocation) -> Option<u64> {
+        self.chunk_stamps.get(&location).copied()
+    }
+
+    /// Loads chunk from disk. A chunk that was never saved is fetched from the chunk store
+    /// if it has it, generated if there is a generator, and is the default voxel otherwise.
+    /// Fetched chunks are saved to the disk cache, generated ones become defined, but
+    /// aren't saved until they are edited. Fails with Error::UndefinedChunk if an
+    /// undefined chunk can't be fetched or generated
+    fn load_chunk(&mut self, location: ChunkLocation) -> Result<()> {
+        let started = Instant::now();
+        let saved = if self.chunk_defined(location) {
+            self.store_read(|dimension| dimension.read_chunk_from_disk(location))?
+        } else {
+            None
+        };
+        self.record_op(ChunkOp::Load, location, started);
+        let saved = match saved {
+            Some(chunk) => Some(chunk),
+            None => self.fetch_from_store(location)?.inspect(|_| {
+                self.dirty_chunks.insert(location);
+            }),
+        };
+        let chunk = match saved {
+            Some(chunk) => {
+                self.chunk_loaded_from_disk(location);
+                chunk
+            }
+            None => match self.generate_chunk(location)? {
+                Some(chunk) => {
+                    self.chunk_created(location);
+                    self.generated_chunks.insert(location);
+                    chunk
+                }
+                None if !self.chunk_defined(location) => {
+                    return Err(Error::UndefinedChunk { chunk: location });
+                }
+                None => {
+                    self.chunk_edited(location);
+                    Chunk::new()
+                }
+            },
+        };
+        self.all_chunk_locations.insert(location);
+        self.loaded_chunks.insert(location, chunk);
+        self.chunk_changed(location);
+        self.sky_chunk_added(location);
+        self.chunk_mesh_changed(location);
+        self.touch_chunk(location);
+        // chunks whose writes fail stay loaded, and the failure shows up on the next flush
+        let _ = self.evict_over_budget();
+        Ok(())
     }
 
     ///Syncs the disk version to the version in memory
-    fn sync_chunk(&mut self, location: ChunkLocation) -> () {
-        //TODO write chunk to disk
+    fn sync_chunk(&mut self, location: ChunkLocation) -> Result<()> {
+        // a chunk whose write was dropped stays dirty, to be written once the disk works
+        if !self.dirty_chunks.contains(&location) {
+            return Ok(());
+        }
+        let started = Instant::now();
+        self.prepare_chunk_for_save(location);
+        if self.store_write(|dimension| dimension.write_chunk_to_disk(location))? {
+            self.dirty_chunks.remove(&location);
+        }
+        self.record_op(ChunkOp::Save, location, started);
+        Ok(())
+    }
+
+    /// Switches a chunk about to be saved to the storage it is saved in
+    fn prepare_chunk_for_save(&mut self, location: ChunkLocation) {
+        // chunks that were diverse once may fit a smaller storage by now
+        let canonical = self.canonical_saves;
+        if let Some(chunk) = self.loaded_chunks.get_mut(&location) {
+            if canonical {
+                chunk.canonicalize();
+            } else {
+                chunk.optimize();
+            }
+        }
     }
 
     /// writes out all chunks to disk (sync all)
-    fn flush(&mut self) -> () {
-        //TODO implement flush, write out all chunks to disk
+    fn flush(&mut self) -> Result<()> {
+        // a retried write only writes the chunks that are still dirty
+        self.store_write(|dimension| dimension.write_dirty_chunks())?;
+        // with writes being dropped, the metadata waits until the chunks are on disk too
+        if !self.dirty_chunks.is_empty() {
+            return Ok(());
+        }
+        self.store_write(|dimension| dimension.purge_expired_tombstones().map(|_| ()))?;
+        self.store_write(|dimension| dimension.migrate_cold_chunks().map(|_| ()))?;
+        self.store_write(|dimension| dimension.compact_regions().map(|_| ()))?;
+        self.store_write(|dimension| dimension.save_metadata())?;
+        Ok(())
     }
 
-    /// Gets the location of the chunk where this voxel lies
+    /// Gets the location of the chunk where this voxel lies. Rounds down, so the voxels
+    /// just below 0 lie in the chunk at -1
     fn get_chunk_location(location: GlobalLocation) -> ChunkLocation {
         ChunkLocation {
-            x: location.x / (CHUNK_X_SIZE as u32),
-            y: location.y / (CHUNK_Y_SIZE as u32),
-            z: location.z / (CHUNK_Z_SIZE as u32),
+            x: location.x.div_euclid(CHUNK_X_SIZE as i32),
+            y: location.y.div_euclid(CHUNK_Y_SIZE as i32),
+            z: location.z.div_euclid(CHUNK_Z_SIZE as i32),
         }
     }
 
     /// Gets the location of the voxel in the chunk where this global location lies
     fn get_voxel_location(location: GlobalLocation) -> VoxelLocation {
         VoxelLocation {
-            x: location.x % (CHUNK_X_SIZE as u32),
-            y: location.y % (CHUNK_Y_SIZE as u32),
-            z: location.z % (CHUNK_Z_SIZE as u32),
+            x: location.x.rem_euclid(CHUNK_X_SIZE as i32),
+            y: location.y.rem_euclid(CHUNK_Y_SIZE as i32),
+            z: location.z.rem_euclid(CHUNK_Z_SIZE as i32),
         }
     }
 
-    /// gets voxel at location if available. It is preffered to use get_Volume for better
+    /// Gets the voxels the chunk at this location covers
+    fn get_chunk_bounds(location: ChunkLocation) -> Aabb {
+        let size = Point3D::new(
+            CHUNK_X_SIZE as i32,
+            CHUNK_Y_SIZE as i32,
+            CHUNK_Z_SIZE as i32,
+        );
+        let start = Point3D::new(
+            location.x * size.x,
+            location.y * size.y,
+            location.z * size.z,
+        );
+        Aabb::new(start, start + size)
+    }
+
+    /// gets voxel at location if available. It is preffered to use get_volume for better
     /// performance
     fn get_voxel(&mut self, location: GlobalLocation) -> T {
-        let chunk = self.get_chunk(Self::get_chunk_location(location));
-        chunk.get(Self::get_voxel_location(location))
+        match self.try_get_voxel(location) {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// sets voxel at location, loading its chunk if necessary. Panics if the write fails,
+    /// write_voxel reports the failure instead
+    fn set_voxel(&mut self, location: GlobalLocation, value: T) {
+        if let Err(err) = self.write_voxel(location, value) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Sets the voxel at location, loading its chunk if necessary. Fails if the location is
+    /// out of bounds or its chunk can't be loaded, but unlike try_set_voxel doesn't check
+    /// claims or the validator
+    fn write_voxel(&mut self, location: GlobalLocation, value: T) -> Result<()> {
+        let location = match self.resolve_location(location)? {
+            Some(location) if !self.void.covers(location.z) => location,
+            _ => return Ok(()),
+        };
+        let chunk = self.get_chunk_mut(Self::get_chunk_location(location))?;
+        let voxel_location = Self::get_voxel_location(location);
+        let old = chunk.get(voxel_location);
+        chunk.set(voxel_location, value);
+        self.stats.record(&old, &value);
+        self.dirty_chunks.insert(Self::get_chunk_location(location));
+        self.chunk_changed(Self::get_chunk_location(location));
+        self.chunk_edited(Self::get_chunk_location(location));
+        self.sky_voxel_written(location, &value);
+        self.log_edit(location, value);
+        self.changes.record(location, old, value);
+        self.voxel_mesh_changed(location);
+        self.voxel_region_dirtied(location);
+        self.multiblock_voxel_written(location, old, value);
+        Ok(())
+    }
+}
+
+/// The space covered by the defined chunks. Only loaded chunks can be read through this
+/// interface, since reading cannot page chunks in from disk
+impl<T: Copy + Default + VoxelClass + VoxelCodec> VoxelRead<T> for Dimension<T> {
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation) {
+        let mut locations = self.all_chunk_locations.iter();
+        let first = match locations.next() {
+            Some(location) => *location,
+            None => return (GlobalLocation::default(), GlobalLocation::default()),
+        };
+        let (min, max) = locations.fold((first, first), |(min, max), location| {
+            (
+                ChunkLocation::new(
+                    min.x.min(location.x),
+                    min.y.min(location.y),
+                    min.z.min(location.z),
+                ),
+                ChunkLocation::new(
+                    max.x.max(location.x),
+                    max.y.max(location.y),
+                    max.z.max(location.z),
+                ),
+            )
+        });
+        (
+            Self::get_chunk_bounds(min).start,
+            Self::get_chunk_bounds(max).end,
+        )
+    }
+
+    fn within_bounds(&self, location: GlobalLocation) -> bool {
+        self.bounds.is_none_or(|bounds| bounds.contains(location))
+            && (self.void.covers(location.z)
+                || self.chunk_loaded(Self::get_chunk_location(location)))
+    }
+
+    fn get(&self, location: GlobalLocation) -> T {
+        if let Some(value) = self.void.voxel(location) {
+            return value;
+        }
+        self.loaded_chunks
+            .get(&Self::get_chunk_location(location))
+            .expect("chunk not loaded")
+            .get(Self::get_voxel_location(location))
+    }
+
+    fn known_all_solid(&self, location: GlobalLocation) -> bool {
+        let chunk = Self::get_chunk_location(location);
+        !self.void.touches_chunk(chunk) && self.chunk_all_solid(chunk)
+    }
+
+    fn known_all_empty(&self, location: GlobalLocation) -> bool {
+        let chunk = Self::get_chunk_location(location);
+        !self.void.touches_chunk(chunk) && self.chunk_all_empty(chunk)
+    }
+}
+
+/// A chunk on its own, as the voxels from the origin to the chunk size
+impl<T: Copy + Default + VoxelClass + VoxelCodec> VoxelRead<T> for Chunk<T> {
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation) {
+        (
+            GlobalLocation::default(),
+            GlobalLocation::new(
+                CHUNK_X_SIZE as i32,
+                CHUNK_Y_SIZE as i32,
+                CHUNK_Z_SIZE as i32,
+            ),
+        )
+    }
+
+    fn within_bounds(&self, location: GlobalLocation) -> bool {
+        let (start, end) = VoxelRead::bounds(self);
+        Aabb::new(start, end).contains(location)
+    }
+
+    fn get(&self, location: GlobalLocation) -> T {
+        Chunk::get(self, location)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> VoxelWrite<T> for Dimension<T> {
+    fn set(&mut self, location: GlobalLocation, value: T) {
+        self.set_voxel(location, value);
     }
 }
 
 impl<T: Copy + Default> Volume<T> {
+    #[allow(clippy::redundant_field_names)]
     fn new(start_location: GlobalLocation, end_location: GlobalLocation, value: T) -> Volume<T> {
         let x_size = end_location.x - start_location.x;
         let y_size = end_location.y - start_location.y;
@@ -271,28 +925,82 @@ impl<T: Copy + Default> Volume<T> {
     }
 
     fn get_index(&self, location: GlobalLocation) -> usize {
-        (location.z * self.x_size * self.y_size + location.y * self.x_size + location.x) as usize
+        let offset = location - self.start_location;
+        (offset.z * self.x_size * self.y_size + offset.y * self.x_size + offset.x) as usize
     }
 
     fn get_location(&self, index: usize) -> GlobalLocation {
-        Point3D {
-            z: (index as u32) / (self.x_size * self.y_size),
-            y: (index as u32) % (self.x_size * self.y_size),
-            x: (index as u32) % (self.y_size),
-        }
+        let index = index as i32;
+        self.start_location
+            + Point3D {
+                z: index / (self.x_size * self.y_size),
+                y: (index / self.x_size) % self.y_size,
+                x: index % self.x_size,
+            }
     }
 
     fn within_bounds(&self, location: GlobalLocation) -> bool {
-        self.get_index(location) < self.voxels.len()
+        location.x >= self.start_location.x
+            && location.y >= self.start_location.y
+            && location.z >= self.start_location.z
+            && location.x < self.end_location.x
+            && location.y < self.end_location.y
+            && location.z < self.end_location.z
     }
 
+    /// Panics if location is outside of the volume
     fn get(&self, location: GlobalLocation) -> T {
-        self.voxels[self.get_index(location)]
+        match self.try_get(location) {
+            Some(value) => value,
+            None => panic!("{}", Error::OutOfBounds { location }),
+        }
     }
 
+    /// Panics if location is outside of the volume
+    #[allow(clippy::unused_unit)]
     fn set(&mut self, location: GlobalLocation, value: T) -> () {
+        if let Err(err) = self.try_set(location, value) {
+            panic!("{}", err);
+        }
+    }
+
+    /// The voxel at location, None if it is outside of the volume
+    fn try_get(&self, location: GlobalLocation) -> Option<T> {
+        if !self.within_bounds(location) {
+            return None;
+        }
+        Some(self.voxels[self.get_index(location)])
+    }
+
+    /// Sets the voxel at location, failing with Error::OutOfBounds if it is outside of the
+    /// volume
+    fn try_set(&mut self, location: GlobalLocation, value: T) -> Result<()> {
+        if !self.within_bounds(location) {
+            return Err(Error::OutOfBounds { location });
+        }
         let loc = self.get_index(location);
         self.voxels[loc] = value;
+        Ok(())
+    }
+}
+
+impl<T: Copy + Default> VoxelRead<T> for Volume<T> {
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation) {
+        (self.start_location, self.end_location)
+    }
+
+    fn within_bounds(&self, location: GlobalLocation) -> bool {
+        Volume::within_bounds(self, location)
+    }
+
+    fn get(&self, location: GlobalLocation) -> T {
+        Volume::get(self, location)
+    }
+}
+
+impl<T: Copy + Default> VoxelWrite<T> for Volume<T> {
+    fn set(&mut self, location: GlobalLocation, value: T) {
+        Volume::set(self, location, value);
     }
 }
 
@@ -300,43 +1008,73 @@ impl<T: Copy + Default> Volume<T> {
 //////////////////////////////////implementation///////////////////////////////////////////
 ///////////////////////////////////////////////////////////////////////////////////////////
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct VoxelType {
     id: u32,
     name: String,
+    #[serde(default)]
     solid: bool,
+    /// the color the type is drawn with, as rgb
+    #[serde(default)]
+    color: Option<[u8; 3]>,
+    /// how much of what is behind the voxel it hides, from 0 to 1
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    /// how much light the voxel gives off, 0 for none
+    #[serde(default)]
+    emissive: u8,
+    /// faces that don't hide their neighbors even if the type is opaque, like the top of
+    /// a path block
+    #[serde(default)]
+    transparent_faces: BTreeSet<Face>,
+    /// names or tags of the types the voxel joins up with on its sides, like fences
+    #[serde(default)]
+    connects_to: BTreeSet<String>,
+    /// labels like "ore" or "liquid" that group types for queries
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    /// game specific data
+    #[serde(default)]
+    properties: BTreeMap<String, PropertyValue>,
+}
+
+impl VoxelType {
+    fn new(id: u32, name: &str, solid: bool) -> VoxelType {
+        VoxelType {
+            id,
+            name: String::from(name),
+            solid,
+            color: None,
+            opacity: if solid { 1.0 } else { 0.0 },
+            emissive: 0,
+            transparent_faces: BTreeSet::new(),
+            connects_to: BTreeSet::new(),
+            tags: BTreeSet::new(),
+            properties: BTreeMap::new(),
+        }
+    }
 }
 
-#[derive(Clone, Copy, Default)]
+fn default_opacity() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
 struct Voxel {
     id: u32,
+    /// the shape the voxel fills its cell with, 0 for the full cube
+    shape: ShapeId,
     extra_data: Option<DataSegment>,
 }
 
 impl Voxel {
     fn get_type(&self) -> VoxelType {
-        match self.id {
-            0 => VoxelType {
-                id: 0,
-                name: String::from("unknown"),
-                solid: true,
-            },
-            1 => VoxelType {
-                id: 1,
-                name: String::from("air"),
-                solid: false,
-            },
-            2 => VoxelType {
-                id: 2,
-                name: String::from("water"),
-                solid: false,
-            },
-            3 => VoxelType {
-                id: 3,
-                name: String::from("stone"),
-                solid: true,
-            },
-            _ => panic!("material id undefined"),
-        }
+        self.get_type_in(VoxelRegistry::builtin()).clone()
+    }
+
+    /// The type of the voxel according to a registry
+    fn get_type_in<'a>(&self, registry: &'a VoxelRegistry) -> &'a VoxelType {
+        registry.get(self.id).expect("material id undefined")
     }
 }
 
@@ -375,65 +1113,95 @@ impl PartialOrd for Node {
     }
 }
 
-/// If the current location can be travelled by a droid
-fn is_traversable(map: &Volume<Voxel>, location: GlobalLocation) -> bool {
-    let location_underneath = GlobalLocation::new(location.x, location.y, location.z - 1);
+/// If the current location can be travelled by a droid with the agent's abilities
+fn is_traversable<M: VoxelRead<Voxel> + ?Sized>(
+    map: &M,
+    location: GlobalLocation,
+    agent: &Agent,
+) -> bool {
+    // nothing can stand inside a chunk of solid rock, so don't bother reading it, unless
+    // some solid voxels might be passable
+    if agent.rules.is_empty() && map.known_all_solid(location) {
+        return false;
+    }
+    let location_underneath = match location.z.checked_sub(1) {
+        Some(z) => GlobalLocation::new(location.x, location.y, z),
+        None => return false,
+    };
     //check that the current location and the location underneath are defined
-    (map.within_bounds(location) && map.within_bounds(location_underneath)
-     //check that current location is not solid
-     && (!map.get(location).get_type().solid)
-     //check that location down one must be solid
-     && (map.get(location_underneath).get_type().solid))
+    if !map.within_bounds(location) || !map.within_bounds(location_underneath) {
+        return false;
+    }
+    //check that current location can be occupied
+    match agent.can_occupy(&map.get(location)) {
+        //check that location down one must be solid
+        Some(needs_floor) => {
+            !needs_floor || map.get(location_underneath).is_solid_in(agent.registry)
+        }
+        None => false,
+    }
 }
 
-fn get_djikstra_map(map: &Volume<Voxel>, weights: Vec<(GlobalLocation, u32)>) -> Volume<u32> {
+fn get_djikstra_map<T, M: VoxelRead<T>, P: MovementProfile<T> + ?Sized>(
+    map: &M,
+    weights: Vec<(GlobalLocation, u32)>,
+    overrides: &PathCostOverrides,
+    profile: &P,
+) -> Volume<u32> {
     // The nodes that are on the exploring front of the djikstra map
     let mut frontier: BinaryHeap<Node> = BinaryHeap::new();
-    // The nodes that used to be on the exploring front
-    let mut visited: HashSet<Node> = HashSet::new();
+    // The cost of every location that used to be on the exploring front
+    let mut visited: HashMap<GlobalLocation, u32> = HashMap::new();
 
     // insert original weights into node tree
     for (location, weight) in weights.iter() {
         frontier.push(Node {
-            location: location.clone(),
-            cost: weight.clone(),
+            location: *location,
+            cost: *weight,
         });
     }
 
     //while there are still pending nodes
-    while frontier.len() > 0 {
-        let current_node = frontier.pop().unwrap();
-        visited.insert(current_node);
-        for location in [
-            current_node.location - GlobalLocation::new(1, 0, 0),
-            current_node.location + GlobalLocation::new(1, 0, 0),
-            current_node.location - GlobalLocation::new(0, 1, 0),
-            current_node.location + GlobalLocation::new(0, 1, 0),
-            current_node.location - GlobalLocation::new(0, 0, 1),
-            current_node.location + GlobalLocation::new(0, 0, 1),
-        ]
-        .iter()
-        {
-            //if it can be traversed,
-            if is_traversable(map, location.clone())
-                    //if it has not been visited
-                    && !visited.iter().find(|x| &x.location == location).is_some()
+    while let Some(current_node) = frontier.pop() {
+        // a location may be queued several times, only the cheapest one counts
+        if visited.contains_key(&current_node.location) {
+            continue;
+        }
+        visited.insert(current_node.location, current_node.cost);
+        // costs are of getting from a location to the seeds, so they spread backwards, to
+        // the locations a step into the current one can be taken from
+        for location in profile.predecessors(current_node.location) {
+            //if it can be traversed and has not been visited
+            if !map.within_bounds(location)
+                || !profile.can_enter(map, location)
+                || overrides.get(location) == Some(IMPASSABLE)
+                || visited.contains_key(&location)
             {
-                // add it to the priority queue
+                continue;
+            }
+            // overridden costs replace the profile's, unless they block the location off
+            let step_cost = match overrides.get(current_node.location) {
+                Some(IMPASSABLE) => continue,
+                Some(cost) => Some(cost),
+                None => profile.step_cost(map, location, current_node.location),
+            };
+            // add it to the priority queue, unless the step can't be taken
+            if let Some(step_cost) = step_cost {
                 frontier.push(Node {
-                    location: location.clone(),
-                    cost: current_node.cost + 1,
+                    location,
+                    cost: current_node.cost.saturating_add(step_cost),
                 });
             }
         }
     }
 
     // Create djikstra map
-    let mut potential_map: Volume<u32> =
-        Volume::new(map.start_location, map.end_location, u32::max_value());
+    let (start_location, end_location) = map.bounds();
+    let mut potential_map: Volume<u32> = Volume::new(start_location, end_location, u32::MAX);
     //overwrite map with nodes
-    for node in visited.iter() {
-        potential_map.set(node.location, node.cost);
+    for (location, cost) in visited.iter() {
+        // seeds outside of the map have nowhere to go in it
+        let _ = potential_map.try_set(*location, *cost);
     }
     potential_map
 }
@@ -0,0 +1,378 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use super::faces::Face;
+use super::registry::VoxelRegistry;
+use super::{
+    Aabb, Chunk, ChunkLocation, Dimension, GlobalLocation, Point3D, Voxel, VoxelRead, CHUNK_VOLUME,
+    CHUNK_Z_SIZE,
+};
+
+/// The brightest light a voxel can hold. Light fades by one level per voxel travelled
+pub const MAX_LIGHT: u8 = 15;
+
+/// Relighting regions up to this many voxels updates voxel by voxel, bigger ones are
+/// cleared and flooded again in one go
+const INCREMENTAL_LIMIT: usize = 512;
+
+/// How bright the sky is at a time of day, from 0 to 1. Times of day go from 0 to 1,
+/// starting at midnight
+pub type DaylightCurve = Arc<dyn Fn(f32) -> f32 + Send + Sync>;
+
+/// How a region was relit
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RelightStrategy {
+    /// every voxel was updated on its own, cheap for a few scattered edits
+    Incremental,
+    /// the light around the region was cleared and flooded again, cheap for big edits
+    Rebuild,
+}
+
+/// Where light comes from. Each source is stored and spread on its own, so sky light can
+/// be dimmed at night without touching torches
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LightSource {
+    /// light given off by emissive voxels
+    Block,
+    /// light from the sky, shining into every voxel open to it
+    Sky,
+}
+
+/// The light level of every voxel of the lit chunks, for one source
+#[derive(Clone, Default)]
+struct LightChannel {
+    levels: HashMap<ChunkLocation, Vec<u8>>,
+}
+
+impl LightChannel {
+    fn get(&self, location: GlobalLocation) -> u8 {
+        self.levels
+            .get(&Dimension::<Voxel>::get_chunk_location(location))
+            .map_or(0, |levels| levels[light_index(location)])
+    }
+
+    fn set(&mut self, location: GlobalLocation, level: u8) {
+        let chunk = Dimension::<Voxel>::get_chunk_location(location);
+        if level == 0 && !self.levels.contains_key(&chunk) {
+            return;
+        }
+        self.levels
+            .entry(chunk)
+            .or_insert_with(|| vec![0; CHUNK_VOLUME])[light_index(location)] = level;
+    }
+}
+
+fn light_index(location: GlobalLocation) -> usize {
+    Chunk::<Voxel>::get_index(Dimension::<Voxel>::get_voxel_location(location))
+}
+
+/// The light of a dimension, kept separately for each source
+#[derive(Clone)]
+pub struct LightStore {
+    block: LightChannel,
+    sky: LightChannel,
+    daylight: DaylightCurve,
+}
+
+impl LightStore {
+    pub fn new() -> LightStore {
+        LightStore {
+            block: LightChannel::default(),
+            sky: LightChannel::default(),
+            daylight: Arc::new(default_daylight),
+        }
+    }
+
+    fn channel(&self, source: LightSource) -> &LightChannel {
+        match source {
+            LightSource::Block => &self.block,
+            LightSource::Sky => &self.sky,
+        }
+    }
+
+    fn channel_mut(&mut self, source: LightSource) -> &mut LightChannel {
+        match source {
+            LightSource::Block => &mut self.block,
+            LightSource::Sky => &mut self.sky,
+        }
+    }
+
+    pub fn get(&self, source: LightSource, location: GlobalLocation) -> u8 {
+        self.channel(source).get(location)
+    }
+
+    pub fn set(&mut self, source: LightSource, location: GlobalLocation, level: u8) {
+        self.channel_mut(source).set(location, level);
+    }
+
+    /// Drops the light of a chunk, e.g. when it is unloaded
+    pub fn forget_chunk(&mut self, location: ChunkLocation) {
+        self.block.levels.remove(&location);
+        self.sky.levels.remove(&location);
+    }
+
+    /// Reads the light at a fixed daylight, from 0 to 1, for many lookups in a row
+    pub fn sampler(&self, daylight: f32) -> LightSampler<'_> {
+        LightSampler {
+            store: self,
+            daylight: daylight.clamp(0.0, 1.0),
+            last: Cell::new(None),
+        }
+    }
+}
+
+/// The light levels of one chunk, for each source
+type ChunkLight<'a> = (ChunkLocation, Option<&'a [u8]>, Option<&'a [u8]>);
+
+/// Reads light levels the way sampled_light does, remembering the last chunk it read so
+/// lookups that stay in one chunk, like a search moving step by step, skip the chunk
+/// lookup
+pub struct LightSampler<'a> {
+    store: &'a LightStore,
+    daylight: f32,
+    last: Cell<Option<ChunkLight<'a>>>,
+}
+
+impl<'a> LightSampler<'a> {
+    /// The brighter of the block light and the dimmed sky light at location
+    pub fn get(&self, location: GlobalLocation) -> u8 {
+        let chunk = Dimension::<Voxel>::get_chunk_location(location);
+        let (_, block, sky) = match self.last.get() {
+            Some(last) if last.0 == chunk => last,
+            _ => {
+                let levels = (
+                    chunk,
+                    self.store.block.levels.get(&chunk).map(Vec::as_slice),
+                    self.store.sky.levels.get(&chunk).map(Vec::as_slice),
+                );
+                self.last.set(Some(levels));
+                levels
+            }
+        };
+        let index = light_index(location);
+        let block = block.map_or(0, |levels| levels[index]);
+        let sky = sky.map_or(0, |levels| levels[index]);
+        block.max((sky as f32 * self.daylight).round() as u8)
+    }
+}
+
+/// Full brightness during the day, a tenth of it at night, with smooth dawns and dusks
+fn default_daylight(time_of_day: f32) -> f32 {
+    let sun = (0.5 - 0.5 * (2.0 * PI * time_of_day).cos()).clamp(0.0, 1.0);
+    0.1 + 0.9 * sun
+}
+
+impl Dimension<Voxel> {
+    fn lighting_registry(&self) -> &VoxelRegistry {
+        self.registry().unwrap_or(VoxelRegistry::builtin())
+    }
+
+    /// If light can pass through the voxel at the location. Unloaded voxels stop light
+    fn passes_light(&self, location: GlobalLocation) -> bool {
+        VoxelRead::within_bounds(self, location)
+            && self
+                .lighting_registry()
+                .opacity_of(VoxelRead::get(self, location).id)
+                < 1.0
+    }
+
+    /// The light a source puts into the voxel at the location before any spreading
+    fn emitted_light(&self, source: LightSource, location: GlobalLocation) -> u8 {
+        if !VoxelRead::within_bounds(self, location) {
+            return 0;
+        }
+        match source {
+            LightSource::Block => {
+                let id = VoxelRead::get(self, location).id;
+                self.lighting_registry().emissive_of(id).min(MAX_LIGHT)
+            }
+            LightSource::Sky if self.passes_light(location) && self.is_exposed_to_sky(location) => {
+                MAX_LIGHT
+            }
+            LightSource::Sky => 0,
+        }
+    }
+
+    /// The light emissive voxels give a location, 0 where nothing has been lit
+    pub fn block_light_at(&self, location: GlobalLocation) -> u8 {
+        self.light.get(LightSource::Block, location)
+    }
+
+    /// The light the sky gives a location at full daylight, 0 where nothing has been lit
+    pub fn sky_light_at(&self, location: GlobalLocation) -> u8 {
+        self.light.get(LightSource::Sky, location)
+    }
+
+    /// Changes how bright the sky is over the day
+    pub fn set_daylight_curve(&mut self, curve: DaylightCurve) {
+        self.light.daylight = curve;
+    }
+
+    /// The light at a location at a time of day, the brighter of the block light and the
+    /// sky light dimmed by the daylight curve. Time of day goes from 0 to 1 starting at
+    /// midnight. This is what renderers and spawn rules should agree on
+    pub fn sampled_light(&self, location: GlobalLocation, time_of_day: f32) -> u8 {
+        let daylight = (self.light.daylight)(time_of_day.rem_euclid(1.0)).clamp(0.0, 1.0);
+        let sky = (self.sky_light_at(location) as f32 * daylight).round() as u8;
+        sky.max(self.block_light_at(location))
+    }
+
+    /// Samples the light at a time of day without looking up the chunk of every voxel
+    /// again, e.g. for movement profiles that care about light
+    pub fn light_sampler(&self, time_of_day: f32) -> LightSampler<'_> {
+        self.light
+            .sampler((self.light.daylight)(time_of_day.rem_euclid(1.0)))
+    }
+
+    /// Relights the region after bulk edits like explosions or pastes. Small regions are
+    /// updated voxel by voxel, big ones are cleared and flooded again, whichever is cheaper
+    pub fn recompute_light(&mut self, region: Aabb) -> RelightStrategy {
+        let size = region.end - region.start;
+        let volume = size.x as usize * size.y as usize * size.z as usize;
+        if volume <= INCREMENTAL_LIMIT {
+            for z in region.start.z..region.end.z {
+                for y in region.start.y..region.end.y {
+                    for x in region.start.x..region.end.x {
+                        self.update_light(Point3D::new(x, y, z));
+                    }
+                }
+            }
+            RelightStrategy::Incremental
+        } else {
+            self.rebuild_light(LightSource::Block, region);
+            self.rebuild_light(LightSource::Sky, region);
+            RelightStrategy::Rebuild
+        }
+    }
+
+    /// Relights around one changed voxel. Changing a voxel also changes which voxels below
+    /// it are open to the sky, so those are relit too
+    pub fn update_light(&mut self, location: GlobalLocation) {
+        self.update_light_from(LightSource::Block, location);
+        self.update_light_from(LightSource::Sky, location);
+        let mut below = location;
+        while let Some(next) = Face::Down.neighbor(below) {
+            if !self.passes_light(next) {
+                break;
+            }
+            self.update_light_from(LightSource::Sky, next);
+            below = next;
+        }
+    }
+
+    /// Removes the light of one source that a voxel no longer lets through or gives off,
+    /// then spreads whatever light now reaches it
+    fn update_light_from(&mut self, source: LightSource, location: GlobalLocation) {
+        let mut darkened = VecDeque::new();
+        let mut lit = VecDeque::new();
+        darkened.push_back((location, self.light.get(source, location)));
+        self.light.set(source, location, 0);
+        while let Some((current, level)) = darkened.pop_front() {
+            for face in Face::ALL.iter() {
+                let neighbor = match face.neighbor(current) {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+                let neighbor_level = self.light.get(source, neighbor);
+                if neighbor_level != 0 && neighbor_level < level {
+                    // this light may have come from the removed light, so it is taken
+                    // away too and refilled from whatever still reaches it
+                    self.light.set(source, neighbor, 0);
+                    darkened.push_back((neighbor, neighbor_level));
+                } else if neighbor_level != 0 {
+                    lit.push_back(neighbor);
+                }
+            }
+        }
+        let emitted = self.emitted_light(source, location);
+        if emitted > 0 {
+            self.light.set(source, location, emitted);
+            lit.push_back(location);
+        }
+        self.spread_light(source, lit, None);
+    }
+
+    /// Clears the light of one source around the region and floods it again from the
+    /// emitters inside and the light coming in from outside
+    fn rebuild_light(&mut self, source: LightSource, region: Aabb) {
+        // light can't change further than this from the edits
+        let margin = Point3D::new(MAX_LIGHT as i32, MAX_LIGHT as i32, MAX_LIGHT as i32);
+        let mut start = region.start - margin;
+        if source == LightSource::Sky {
+            // except for sky light, which reaches all the way down the columns
+            let lowest = self.loaded_chunks.keys().map(|chunk| chunk.z).min();
+            start.z = start.z.min(lowest.unwrap_or(0) * CHUNK_Z_SIZE as i32);
+        }
+        let end = region.end + margin;
+        let area = Aabb::new(start, end);
+        let mut lit = VecDeque::new();
+        for z in area.start.z..area.end.z {
+            for y in area.start.y..area.end.y {
+                for x in area.start.x..area.end.x {
+                    let location = Point3D::new(x, y, z);
+                    let emitted = self.emitted_light(source, location);
+                    self.light.set(source, location, emitted);
+                    if emitted > 0 {
+                        lit.push_back(location);
+                    }
+                }
+            }
+        }
+        // the voxels just outside the area keep their light and shine back in
+        for z in area.start.z..area.end.z {
+            for y in area.start.y..area.end.y {
+                for x in area.start.x..area.end.x {
+                    let on_edge = x == area.start.x
+                        || y == area.start.y
+                        || z == area.start.z
+                        || x + 1 == area.end.x
+                        || y + 1 == area.end.y
+                        || z + 1 == area.end.z;
+                    if !on_edge {
+                        continue;
+                    }
+                    for face in Face::ALL.iter() {
+                        if let Some(outside) = face.neighbor(Point3D::new(x, y, z)) {
+                            if !area.contains(outside) && self.light.get(source, outside) > 1 {
+                                lit.push_back(outside);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.spread_light(source, lit, Some(area));
+    }
+
+    /// Floods light of one source outwards from the queued voxels, through voxels that
+    /// let it pass and optionally only within an area
+    fn spread_light(
+        &mut self,
+        source: LightSource,
+        mut lit: VecDeque<GlobalLocation>,
+        area: Option<Aabb>,
+    ) {
+        while let Some(current) = lit.pop_front() {
+            let level = self.light.get(source, current);
+            if level <= 1 {
+                continue;
+            }
+            for face in Face::ALL.iter() {
+                let neighbor = match face.neighbor(current) {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+                if area.is_some_and(|area| !area.contains(neighbor)) {
+                    continue;
+                }
+                if self.light.get(source, neighbor) < level - 1 && self.passes_light(neighbor) {
+                    self.light.set(source, neighbor, level - 1);
+                    lit.push_back(neighbor);
+                }
+            }
+        }
+    }
+}
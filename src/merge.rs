@@ -0,0 +1,108 @@
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::{ChunkLocation, Dimension, Point3D, VoxelLocation};
+
+/// How the voxels of one world are placed in another when merging. Each voxel is mirrored
+/// first, then turned counterclockwise around the z axis, then scaled up into a cube of
+/// scale voxels on a side, then moved by translation. Mirroring and turning keep voxels
+/// on the grid: the voxel at x mirrors to -x - 1, and a quarter turn takes (x, y) to
+/// (-y - 1, x)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MergeTransform {
+    pub translation: Point3D,
+    /// counterclockwise quarter turns, 0 to 3
+    pub quarter_turns: u8,
+    /// which of the x, y and z axes are mirrored
+    pub mirror: [bool; 3],
+    /// how many voxels on a side each voxel becomes, at least 1
+    pub scale: u32,
+}
+
+impl Default for MergeTransform {
+    fn default() -> MergeTransform {
+        MergeTransform {
+            translation: Point3D::new(0, 0, 0),
+            quarter_turns: 0,
+            mirror: [false; 3],
+            scale: 1,
+        }
+    }
+}
+
+impl MergeTransform {
+    /// Places every voxel where it was
+    pub fn identity() -> MergeTransform {
+        MergeTransform::default()
+    }
+
+    /// Moves every voxel by translation
+    pub fn translated(translation: Point3D) -> MergeTransform {
+        MergeTransform {
+            translation,
+            ..MergeTransform::default()
+        }
+    }
+
+    /// The lowest corner of the cube of voxels a voxel of the source ends up as
+    pub fn apply(self, location: Point3D) -> Point3D {
+        let mirror = |value: i32, mirrored: bool| if mirrored { -value - 1 } else { value };
+        let (x, y, z) = (
+            mirror(location.x, self.mirror[0]),
+            mirror(location.y, self.mirror[1]),
+            mirror(location.z, self.mirror[2]),
+        );
+        let (x, y) = match self.quarter_turns % 4 {
+            0 => (x, y),
+            1 => (-y - 1, x),
+            2 => (-x - 1, -y - 1),
+            _ => (y, -x - 1),
+        };
+        let scale = self.scale.max(1) as i32;
+        Point3D::new(x * scale, y * scale, z * scale) + self.translation
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Copies every voxel of source that isn't empty into this world, placed as transform
+    /// says, returning how many voxels were written. Empty voxels leave what is here, the
+    /// way placed structures do. Source is streamed a chunk at a time: chunks it had to
+    /// load are dropped again once copied, so prefab worlds of any size merge without
+    /// being copied out whole first. Writes go through write_voxel, without claims
+    pub fn merge_from(
+        &mut self,
+        source: &mut Dimension<T>,
+        transform: MergeTransform,
+    ) -> Result<usize> {
+        let mut chunks: Vec<ChunkLocation> = source.all_chunk_locations.iter().copied().collect();
+        chunks.sort_by_key(|location| (location.z, location.y, location.x));
+        let scale = transform.scale.max(1) as i32;
+        let mut written = 0;
+        for location in chunks {
+            let was_loaded = source.chunk_loaded(location);
+            let origin = Self::get_chunk_bounds(location).start;
+            let mut voxels = Vec::new();
+            let chunk = source.get_chunk(location)?;
+            for local in VoxelLocation::all() {
+                let value = chunk.get(local);
+                if !value.is_empty() {
+                    voxels.push((transform.apply(origin + local), value));
+                }
+            }
+            if !was_loaded && !source.chunk_dirty(location) {
+                source.discard_chunk(location);
+            }
+            for (corner, value) in voxels {
+                for z in 0..scale {
+                    for y in 0..scale {
+                        for x in 0..scale {
+                            self.write_voxel(corner + Point3D::new(x, y, z), value)?;
+                            written += 1;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(written)
+    }
+}
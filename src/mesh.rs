@@ -0,0 +1,536 @@
+use std::collections::{HashMap, HashSet};
+
+use super::chunk_local::classify;
+use super::codec::VoxelCodec;
+use super::faces::{face_visible, Face};
+use super::occupancy::{EmptyVoxel, VoxelClass};
+use super::registry::VoxelRegistry;
+use super::{
+    ChunkLocation, Dimension, GlobalLocation, Point3D, Voxel, VoxelRead, CHUNK_X_SIZE,
+    CHUNK_Y_SIZE, CHUNK_Z_SIZE,
+};
+
+/// Triangles ready to hand to a renderer, with a color per vertex
+#[derive(Clone, Default, Debug)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// rgb from the registry, with the opacity as alpha
+    pub colors: Vec<[u8; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Adds the face of the box from start to end that looks in the direction of face
+    pub fn push_face(&mut self, start: [f32; 3], end: [f32; 3], face: Face, color: [u8; 4]) {
+        let [x0, y0, z0] = start;
+        let [x1, y1, z1] = end;
+        // corners are listed counter clockwise as seen from outside the box
+        let corners = match face {
+            Face::East => [[x1, y0, z0], [x1, y1, z0], [x1, y1, z1], [x1, y0, z1]],
+            Face::West => [[x0, y1, z0], [x0, y0, z0], [x0, y0, z1], [x0, y1, z1]],
+            Face::North => [[x1, y1, z0], [x0, y1, z0], [x0, y1, z1], [x1, y1, z1]],
+            Face::South => [[x0, y0, z0], [x1, y0, z0], [x1, y0, z1], [x0, y0, z1]],
+            Face::Up => [[x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1]],
+            Face::Down => [[x0, y1, z0], [x1, y1, z0], [x1, y0, z0], [x0, y0, z0]],
+        };
+        let first = self.positions.len() as u32;
+        for corner in corners.iter() {
+            self.positions.push(*corner);
+            self.normals.push(face_normal(face));
+            self.colors.push(color);
+        }
+        self.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+fn face_normal(face: Face) -> [f32; 3] {
+    match face {
+        Face::East => [1.0, 0.0, 0.0],
+        Face::West => [-1.0, 0.0, 0.0],
+        Face::North => [0.0, 1.0, 0.0],
+        Face::South => [0.0, -1.0, 0.0],
+        Face::Up => [0.0, 0.0, 1.0],
+        Face::Down => [0.0, 0.0, -1.0],
+    }
+}
+
+/// The voxel standing in for a cube of step voxels on a side at a coarser level of detail:
+/// the most common visible voxel, if at least half the cube is visible, else empty
+pub fn lod_sample<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    start: GlobalLocation,
+    step: i32,
+) -> Voxel {
+    if step == 1 {
+        return if map.within_bounds(start) {
+            map.get(start)
+        } else {
+            Voxel::default()
+        };
+    }
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let mut visible = 0;
+    for z in 0..step {
+        for y in 0..step {
+            for x in 0..step {
+                let location = start + Point3D::new(x, y, z);
+                if !map.within_bounds(location) {
+                    continue;
+                }
+                let voxel = map.get(location);
+                if registry.opacity_of(voxel.id) > 0.0 {
+                    visible += 1;
+                    *counts.entry(voxel.id).or_default() += 1;
+                }
+            }
+        }
+    }
+    if visible * 2 < (step * step * step) as usize {
+        return Voxel::empty();
+    }
+    // ties go to the lowest id so every chunk picks the same stand-in
+    let id = counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(id, _)| id)
+        .unwrap();
+    Voxel {
+        id,
+        ..Voxel::default()
+    }
+}
+
+/// If a chunk and the six chunks around it are known to be all solid, so none of its faces
+/// show as long as solid voxels hide what is behind them
+fn chunk_enclosed<T: Copy + Default + VoxelClass + VoxelCodec, M: VoxelRead<T> + ?Sized>(
+    map: &M,
+    chunk: ChunkLocation,
+) -> bool {
+    let origin = |chunk| Dimension::<T>::get_chunk_bounds(chunk).start;
+    map.known_all_solid(origin(chunk))
+        && Face::ALL.iter().all(|face| {
+            face.neighbor(chunk)
+                .is_some_and(|neighbor| map.known_all_solid(origin(neighbor)))
+        })
+}
+
+/// If mesh_chunk would draw nothing for a chunk, as the chunk summaries tell without
+/// meshing it: the chunk is all empty space, or it is buried in solid chunks of types
+/// that hide what is behind them. Summaries don't count shapes, so at full detail a
+/// buried chunk and the layer around it are also checked for voxels that aren't full
+/// cubes, whose faces can show
+pub fn chunk_mesh_empty<M: VoxelRead<Voxel> + ?Sized>(
+    map: &M,
+    registry: &VoxelRegistry,
+    chunk: ChunkLocation,
+    lod: u32,
+    neighbor_lods: [u32; 6],
+) -> bool {
+    let origin = Dimension::<Voxel>::get_chunk_bounds(chunk).start;
+    if map.known_all_empty(origin) {
+        return registry.opacity_of(Voxel::empty().id) <= 0.0;
+    }
+    // faces facing chunks drawn at another level of detail are drawn however hidden
+    if neighbor_lods
+        .iter()
+        .any(|neighbor_lod| *neighbor_lod != lod)
+        || !chunk_enclosed(map, chunk)
+    {
+        return false;
+    }
+    let solid_types_hide = registry.iter().all(|voxel_type| {
+        !voxel_type.solid
+            || Face::ALL
+                .iter()
+                .all(|face| registry.face_occludes(voxel_type.id, *face))
+    });
+    if !solid_types_hide {
+        return false;
+    }
+    if lod > 0 {
+        return true;
+    }
+    let one = Point3D::new(1, 1, 1);
+    let end = origin
+        + Point3D::new(
+            CHUNK_X_SIZE as i32,
+            CHUNK_Y_SIZE as i32,
+            CHUNK_Z_SIZE as i32,
+        );
+    let (start, end) = (origin - one, end + one);
+    (start.z..end.z).all(|z| {
+        (start.y..end.y).all(|y| {
+            (start.x..end.x).all(|x| {
+                let location = Point3D::new(x, y, z);
+                !map.within_bounds(location)
+                    || registry
+                        .shapes()
+                        .get(map.get(location).shape)
+                        .is_none_or(|shape| shape.is_full())
+            })
+        })
+    })
+}
+
+/// Meshes a chunk as cubes, at a level of detail where every cube stands in for 2^lod
+/// voxels on a side. neighbor_lods holds the level of detail each neighboring chunk is
+/// drawn at, indexed by the face it touches. Where it differs from lod, faces on that
+/// side of the chunk are never culled, which closes the cracks between the two
+/// resolutions like a skirt would
+pub fn mesh_chunk<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    chunk: ChunkLocation,
+    lod: u32,
+    neighbor_lods: [u32; 6],
+) -> Mesh {
+    let step = 1i32 << lod;
+    assert!(
+        step as usize <= CHUNK_X_SIZE.min(CHUNK_Y_SIZE).min(CHUNK_Z_SIZE),
+        "level of detail coarser than a chunk"
+    );
+    if chunk_mesh_empty(map, registry, chunk, lod, neighbor_lods) {
+        return Mesh::new();
+    }
+    let size = [
+        CHUNK_X_SIZE as i32,
+        CHUNK_Y_SIZE as i32,
+        CHUNK_Z_SIZE as i32,
+    ];
+    let origin = Point3D::new(chunk.x * size[0], chunk.y * size[1], chunk.z * size[2]);
+    let mut mesh = Mesh::new();
+    for z in (0..size[2]).step_by(step as usize) {
+        for y in (0..size[1]).step_by(step as usize) {
+            for x in (0..size[0]).step_by(step as usize) {
+                let location = origin + Point3D::new(x, y, z);
+                if step == 1 && !map.within_bounds(location) {
+                    continue;
+                }
+                let voxel = lod_sample(map, registry, location, step);
+                let opacity = registry.opacity_of(voxel.id);
+                if opacity <= 0.0 {
+                    continue;
+                }
+                for face in Face::ALL.iter().copied() {
+                    let on_edge = match face {
+                        Face::East => x + step >= size[0],
+                        Face::West => x == 0,
+                        Face::North => y + step >= size[1],
+                        Face::South => y == 0,
+                        Face::Up => z + step >= size[2],
+                        Face::Down => z == 0,
+                    };
+                    let stitched = on_edge && neighbor_lods[face as usize] != lod;
+                    if !stitched && !cell_face_visible(map, registry, location, voxel, step, face) {
+                        continue;
+                    }
+                    let start = [location.x as f32, location.y as f32, location.z as f32];
+                    let end = [
+                        start[0] + step as f32,
+                        start[1] + step as f32,
+                        start[2] + step as f32,
+                    ];
+                    let [r, g, b] = registry.color_of(voxel.id);
+                    let alpha = (opacity * 255.0).round() as u8;
+                    mesh.push_face(start, end, face, [r, g, b, alpha]);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+/// If a face of a level of detail cell is uncovered. Full resolution cells use the
+/// registry's face rules, coarser ones compare the stand-in voxels
+fn cell_face_visible<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    location: GlobalLocation,
+    voxel: Voxel,
+    step: i32,
+    face: Face,
+) -> bool {
+    if step == 1 {
+        return face_visible(map, registry, location, face);
+    }
+    let neighbor = match face {
+        Face::East => location
+            .x
+            .checked_add(step)
+            .map(|x| Point3D::new(x, location.y, location.z)),
+        Face::West => location
+            .x
+            .checked_sub(step)
+            .map(|x| Point3D::new(x, location.y, location.z)),
+        Face::North => location
+            .y
+            .checked_add(step)
+            .map(|y| Point3D::new(location.x, y, location.z)),
+        Face::South => location
+            .y
+            .checked_sub(step)
+            .map(|y| Point3D::new(location.x, y, location.z)),
+        Face::Up => location
+            .z
+            .checked_add(step)
+            .map(|z| Point3D::new(location.x, location.y, z)),
+        Face::Down => location
+            .z
+            .checked_sub(step)
+            .map(|z| Point3D::new(location.x, location.y, z)),
+    };
+    match neighbor {
+        Some(neighbor) if map.within_bounds(neighbor) => {
+            let neighbor = lod_sample(map, registry, neighbor, step);
+            neighbor.id != voxel.id && !registry.face_occludes(neighbor.id, face.opposite())
+        }
+        _ => true,
+    }
+}
+
+/// Decides which voxels the surface mesher draws and how. Closures deciding if a voxel is
+/// opaque work as rules, drawing every opaque voxel white
+pub trait MeshRules<T> {
+    /// If the voxel is drawn and hides the faces of the voxels behind it
+    fn is_opaque(&self, voxel: &T) -> bool;
+
+    /// If every solid voxel is opaque by these rules, so chunks buried in solid chunks can
+    /// be skipped without looking at them. False unless overridden
+    fn solid_is_opaque(&self) -> bool {
+        false
+    }
+
+    /// The color of the voxel's faces
+    fn color(&self, voxel: &T) -> [u8; 4] {
+        [255; 4]
+    }
+}
+
+impl<T, F: Fn(&T) -> bool> MeshRules<T> for F {
+    fn is_opaque(&self, voxel: &T) -> bool {
+        self(voxel)
+    }
+}
+
+/// Draws the voxels VoxelClass says are opaque. Chunks buried in solid chunks are skipped,
+/// so voxel types should keep their solid voxels opaque, as VoxelClass does by default
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ByVoxelClass;
+
+impl<T: VoxelClass> MeshRules<T> for ByVoxelClass {
+    fn is_opaque(&self, voxel: &T) -> bool {
+        voxel.is_opaque()
+    }
+
+    fn solid_is_opaque(&self) -> bool {
+        true
+    }
+}
+
+/// The axis a face looks along, then the two axes across it
+fn face_axes(face: Face) -> (usize, usize, usize) {
+    match face {
+        Face::East | Face::West => (0, 1, 2),
+        Face::North | Face::South => (1, 0, 2),
+        Face::Up | Face::Down => (2, 0, 1),
+    }
+}
+
+fn axis(point: Point3D, axis: usize) -> i32 {
+    [point.x, point.y, point.z][axis]
+}
+
+/// Meshes the opaque surface of the box from start to end. A face is drawn where the voxel
+/// it looks at isn't opaque, or can't be read, so the map should hold the voxels around
+/// the box for its outer faces to be culled. Touching faces of equal voxels are merged
+/// into as few rectangles as greedily possible
+pub fn mesh_surface<T: PartialEq, M: VoxelRead<T>, R: MeshRules<T> + ?Sized>(
+    map: &M,
+    rules: &R,
+    start: GlobalLocation,
+    end: GlobalLocation,
+) -> Mesh {
+    let mut mesh = Mesh::new();
+    let opaque = |location: GlobalLocation| -> Option<T> {
+        if !map.within_bounds(location) {
+            return None;
+        }
+        let voxel = map.get(location);
+        if rules.is_opaque(&voxel) {
+            Some(voxel)
+        } else {
+            None
+        }
+    };
+    for face in Face::ALL.iter().copied() {
+        let (d, u, v) = face_axes(face);
+        let (u_size, v_size) = (
+            (axis(end, u) - axis(start, u)).max(0) as usize,
+            (axis(end, v) - axis(start, v)).max(0) as usize,
+        );
+        for layer in axis(start, d)..axis(end, d) {
+            let at = |i: usize, j: usize| {
+                let mut point = [0; 3];
+                point[d] = layer;
+                point[u] = axis(start, u) + i as i32;
+                point[v] = axis(start, v) + j as i32;
+                Point3D::new(point[0], point[1], point[2])
+            };
+            // the voxels of the layer whose face is drawn, rows along u
+            let mut mask: Vec<Option<T>> = Vec::with_capacity(u_size * v_size);
+            for j in 0..v_size {
+                for i in 0..u_size {
+                    let location = at(i, j);
+                    mask.push(opaque(location).filter(|_| {
+                        face.neighbor(location)
+                            .is_none_or(|neighbor| opaque(neighbor).is_none())
+                    }));
+                }
+            }
+            for j in 0..v_size {
+                let mut i = 0;
+                while i < u_size {
+                    let voxel = match mask[j * u_size + i].take() {
+                        Some(voxel) => voxel,
+                        None => {
+                            i += 1;
+                            continue;
+                        }
+                    };
+                    let mut width = 1;
+                    while i + width < u_size
+                        && mask[j * u_size + i + width].as_ref() == Some(&voxel)
+                    {
+                        mask[j * u_size + i + width] = None;
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    'grow: while j + height < v_size {
+                        let row = (j + height) * u_size;
+                        for cell in mask[row + i..row + i + width].iter() {
+                            if cell.as_ref() != Some(&voxel) {
+                                break 'grow;
+                            }
+                        }
+                        for cell in mask[row + i..row + i + width].iter_mut() {
+                            *cell = None;
+                        }
+                        height += 1;
+                    }
+                    let low = at(i, j);
+                    let high = at(i + width, j + height) + {
+                        let mut one = [0; 3];
+                        one[d] = 1;
+                        Point3D::new(one[0], one[1], one[2])
+                    };
+                    mesh.push_face(
+                        [low.x as f32, low.y as f32, low.z as f32],
+                        [high.x as f32, high.y as f32, high.z as f32],
+                        face,
+                        rules.color(&voxel),
+                    );
+                    i += width;
+                }
+            }
+        }
+    }
+    mesh
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Meshes the surface of a loaded chunk, culling its border faces against the loaded
+    /// neighboring chunks. None if the chunk isn't loaded. Chunks of nothing but empty
+    /// space, or buried in solid chunks when the rules draw solid voxels opaque, are known
+    /// from their summaries to have an empty mesh
+    pub fn mesh_chunk_surface<R: MeshRules<T> + ?Sized>(
+        &self,
+        location: ChunkLocation,
+        rules: &R,
+    ) -> Option<Mesh> {
+        if !self.chunk_loaded(location) {
+            return None;
+        }
+        let bounds = Self::get_chunk_bounds(location);
+        if (self.known_all_empty(bounds.start) && !rules.is_opaque(&T::empty()))
+            || (rules.solid_is_opaque() && chunk_enclosed(self, location))
+        {
+            return Some(Mesh::new());
+        }
+        Some(mesh_surface(self, rules, bounds.start, bounds.end))
+    }
+
+    /// Starts remembering which chunks' surface meshes went stale, for remesh_changed
+    pub fn track_mesh_changes(&mut self) {
+        if self.stale_meshes.is_none() {
+            self.stale_meshes = Some(HashSet::new());
+        }
+    }
+
+    pub fn untrack_mesh_changes(&mut self) {
+        self.stale_meshes = None;
+    }
+
+    /// Marks the meshes a voxel shows up in as stale: its chunk's, and the neighboring
+    /// chunk's if the voxel lies on the border between them
+    pub fn voxel_mesh_changed(&mut self, location: GlobalLocation) {
+        let stale = match &mut self.stale_meshes {
+            Some(stale) => stale,
+            None => return,
+        };
+        let chunk = Self::get_chunk_location(location);
+        stale.insert(chunk);
+        for face in classify(Self::get_voxel_location(location)).faces() {
+            if let Some(neighbor) = face.neighbor(chunk) {
+                stale.insert(neighbor);
+            }
+        }
+    }
+
+    /// Marks the meshes of a chunk and its neighbors as stale, e.g. once it is loaded and
+    /// hides the border faces of its neighbors
+    pub fn chunk_mesh_changed(&mut self, location: ChunkLocation) {
+        let stale = match &mut self.stale_meshes {
+            Some(stale) => stale,
+            None => return,
+        };
+        stale.insert(location);
+        for face in Face::ALL.iter() {
+            if let Some(neighbor) = face.neighbor(location) {
+                stale.insert(neighbor);
+            }
+        }
+    }
+
+    /// Meshes again the loaded chunks whose meshes went stale since the last call, so a
+    /// game loop only pays for what changed. Stale chunks that aren't loaded are dropped,
+    /// their meshes are made when they are loaded again. Empty unless changes are tracked
+    pub fn remesh_changed<R: MeshRules<T> + ?Sized>(
+        &mut self,
+        rules: &R,
+    ) -> Vec<(ChunkLocation, Mesh)> {
+        let stale = match &mut self.stale_meshes {
+            Some(stale) => std::mem::take(stale),
+            None => return Vec::new(),
+        };
+        let mut stale: Vec<ChunkLocation> = stale.into_iter().collect();
+        stale.sort_by_key(|location| (location.z, location.y, location.x));
+        stale
+            .into_iter()
+            .filter_map(|location| {
+                self.mesh_chunk_surface(location, rules)
+                    .map(|mesh| (location, mesh))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::claims::Claims;
+use super::codec::VoxelCodec;
+use super::compression::{hash_dictionary, validate_dictionary};
+use super::dirty_regions::DirtyRegions;
+use super::error::{Error, ErrorContext, Operation, Result, ResultExt};
+use super::format::{can_read, FORMAT_VERSION};
+use super::occupancy::VoxelClass;
+use super::prune::ChunkHistories;
+use super::schema::VoxelSchema;
+use super::stats::WorldStats;
+use super::structures::StructureLibrary;
+use super::tombstones::Tombstones;
+use super::waypoints::Waypoints;
+use super::{ChunkLocation, Dimension, Point3D};
+
+/// Marks the start of a metadata file
+const METADATA_MAGIC: &[u8; 4] = b"RCMT";
+const METADATA_VERSION: u32 = FORMAT_VERSION;
+
+/// Tags identifying each section of the metadata file
+const CLAIMS_SECTION: &[u8; 4] = b"CLMS";
+const SCHEMA_SECTION: &[u8; 4] = b"SCHM";
+const DICTIONARY_SECTION: &[u8; 4] = b"DICT";
+const RETIRED_DICTIONARIES_SECTION: &[u8; 4] = b"ODCT";
+const VOXEL_IDS_SECTION: &[u8; 4] = b"VIDS";
+const STATS_SECTION: &[u8; 4] = b"STAT";
+const STRUCTURES_SECTION: &[u8; 4] = b"STRC";
+const TOMBSTONES_SECTION: &[u8; 4] = b"TOMB";
+const WAYPOINTS_SECTION: &[u8; 4] = b"WAYP";
+const COLD_STORAGE_SECTION: &[u8; 4] = b"COLD";
+const DAMAGE_SECTION: &[u8; 4] = b"DMGE";
+const WEATHER_SECTION: &[u8; 4] = b"WTHR";
+const HISTORY_SECTION: &[u8; 4] = b"HIST";
+const DIRTY_REGIONS_SECTION: &[u8; 4] = b"DIRT";
+
+/// The world metadata is a list of tagged sections, each prefixed by its length, so readers
+/// can skip sections they don't know about
+fn write_section<W: Write>(out: &mut W, tag: &[u8; 4], payload: &[u8]) -> io::Result<()> {
+    out.write_all(tag)?;
+    out.write_u32::<LittleEndian>(payload.len() as u32)?;
+    out.write_all(payload)
+}
+
+/// Reads the next section, or None at the end of the file
+fn read_section<R: Read>(input: &mut R) -> io::Result<Option<([u8; 4], Vec<u8>)>> {
+    let mut tag = [0; 4];
+    match input.read_exact(&mut tag) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut payload = vec![0; input.read_u32::<LittleEndian>()? as usize];
+    input.read_exact(&mut payload)?;
+    Ok(Some((tag, payload)))
+}
+
+pub fn write_point<W: Write>(out: &mut W, point: Point3D) -> io::Result<()> {
+    out.write_i32::<LittleEndian>(point.x)?;
+    out.write_i32::<LittleEndian>(point.y)?;
+    out.write_i32::<LittleEndian>(point.z)
+}
+
+pub fn read_point<R: Read>(input: &mut R) -> io::Result<Point3D> {
+    Ok(Point3D::new(
+        input.read_i32::<LittleEndian>()?,
+        input.read_i32::<LittleEndian>()?,
+        input.read_i32::<LittleEndian>()?,
+    ))
+}
+
+/// Writes a string prefixed by its length, failing if it is longer than u16::MAX bytes
+pub fn write_string<W: Write>(out: &mut W, string: &str) -> io::Result<()> {
+    if string.len() > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "string is too long to be saved",
+        ));
+    }
+    out.write_u16::<LittleEndian>(string.len() as u16)?;
+    out.write_all(string.as_bytes())
+}
+
+pub fn read_string<R: Read>(input: &mut R) -> io::Result<String> {
+    let mut bytes = vec![0; input.read_u16::<LittleEndian>()? as usize];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Where the world metadata lives inside the disk cache, if there is one
+    fn metadata_path(&self) -> Option<PathBuf> {
+        self.disk_cache
+            .as_ref()
+            .map(|folder| Path::new(folder).join("metadata"))
+    }
+
+    /// Writes the world metadata (schema, claims and the like) into the disk cache
+    pub fn save_metadata(&self) -> Result<()> {
+        let path = match self.metadata_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // written under another name first so a failed save leaves the old metadata in place
+        let partial = path.with_extension("partial");
+        self.write_metadata(&partial)
+            .and_then(|()| fs::rename(&partial, &path))
+            .with_context(|| ErrorContext::new(Operation::SaveMetadata).path(&path))
+    }
+
+    fn write_metadata(&self, path: &Path) -> io::Result<()> {
+        let mut claims = Vec::new();
+        self.claims.write_to(&mut claims)?;
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(METADATA_MAGIC)?;
+        out.write_u32::<LittleEndian>(METADATA_VERSION)?;
+        if let Some(schema) = &self.schema {
+            let mut payload = Vec::new();
+            schema.write_to(&mut payload)?;
+            write_section(&mut out, SCHEMA_SECTION, &payload)?;
+        }
+        write_section(&mut out, CLAIMS_SECTION, &claims)?;
+        if let Some(ids) = &self.saved_voxel_ids {
+            let mut payload = Vec::new();
+            payload.write_u32::<LittleEndian>(ids.len() as u32)?;
+            for (id, name) in ids.iter() {
+                payload.write_u32::<LittleEndian>(*id)?;
+                write_string(&mut payload, name)?;
+            }
+            write_section(&mut out, VOXEL_IDS_SECTION, &payload)?;
+        }
+        if let Some(dictionary) = &self.compression_dictionary {
+            write_section(&mut out, DICTIONARY_SECTION, dictionary)?;
+        }
+        if !self.retired_dictionaries.is_empty() {
+            let mut payload = Vec::new();
+            payload.write_u32::<LittleEndian>(self.retired_dictionaries.len() as u32)?;
+            for dictionary in self.retired_dictionaries.values() {
+                payload.write_u32::<LittleEndian>(dictionary.len() as u32)?;
+                payload.write_all(dictionary)?;
+            }
+            write_section(&mut out, RETIRED_DICTIONARIES_SECTION, &payload)?;
+        }
+        // the stats count edits, so worlds with the same content can have different ones
+        if !self.canonical_saves {
+            let mut stats = Vec::new();
+            self.stats.write_to(&mut stats)?;
+            write_section(&mut out, STATS_SECTION, &stats)?;
+        }
+        if !self.structures.is_empty() {
+            let mut payload = Vec::new();
+            self.structures.write_to(&mut payload)?;
+            write_section(&mut out, STRUCTURES_SECTION, &payload)?;
+        }
+        if !self.tombstones.is_empty() {
+            let mut payload = Vec::new();
+            self.tombstones.write_to(&mut payload)?;
+            write_section(&mut out, TOMBSTONES_SECTION, &payload)?;
+        }
+        if !self.waypoints.is_empty() {
+            let mut payload = Vec::new();
+            self.waypoints.write_to(&mut payload)?;
+            write_section(&mut out, WAYPOINTS_SECTION, &payload)?;
+        }
+        if !self.cold_storage.is_empty() {
+            let mut payload = Vec::new();
+            // when chunks were last used has nothing to do with their content
+            self.cold_storage
+                .write_to(&mut payload, !self.canonical_saves)?;
+            write_section(&mut out, COLD_STORAGE_SECTION, &payload)?;
+        }
+        // when chunks were first seen has nothing to do with their content
+        if !self.histories.is_empty() && !self.canonical_saves {
+            let mut payload = Vec::new();
+            self.histories.write_to(&mut payload)?;
+            write_section(&mut out, HISTORY_SECTION, &payload)?;
+        }
+        // which regions consumers haven't caught up on is bookkeeping, not content
+        if let Some(regions) = &self.dirty_regions {
+            if !regions.is_empty() && !self.canonical_saves {
+                let mut payload = Vec::new();
+                regions.write_to(&mut payload)?;
+                write_section(&mut out, DIRTY_REGIONS_SECTION, &payload)?;
+            }
+        }
+        if !self.weather.is_empty() {
+            let mut payload = Vec::new();
+            self.weather.write_to(&mut payload)?;
+            write_section(&mut out, WEATHER_SECTION, &payload)?;
+        }
+        // damage is play state rather than content, so canonical saves leave it out
+        if self.damage.persists() && !self.damage.is_empty() && !self.canonical_saves {
+            let mut payload = Vec::new();
+            self.damage.write_to(&mut payload, self.current_tick())?;
+            write_section(&mut out, DAMAGE_SECTION, &payload)?;
+        }
+        out.flush()
+    }
+
+    /// Reads the world metadata back from the disk cache, and defines every chunk saved in
+    /// the region files. Does nothing if the world was never saved. Fails with
+    /// Error::SchemaMismatch at the root of the error if the world was saved with a
+    /// different voxel schema than the declared one
+    pub fn load_metadata(&mut self) -> Result<()> {
+        let path = match self.metadata_path() {
+            Some(path) if path.exists() => path,
+            _ => return self.scan_regions(),
+        };
+        self.read_metadata(&path)
+            .with_context(|| ErrorContext::new(Operation::LoadMetadata).path(&path))?;
+        self.scan_regions()
+    }
+
+    fn read_metadata(&mut self, path: &Path) -> Result<()> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != METADATA_MAGIC || !can_read(input.read_u32::<LittleEndian>()?) {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "not a world metadata file").into(),
+            );
+        }
+        while let Some((tag, payload)) = read_section(&mut input)? {
+            if &tag == SCHEMA_SECTION {
+                let found = VoxelSchema::read_from(&mut &payload[..])?;
+                match &self.schema {
+                    Some(expected) if *expected != found => {
+                        return Err(Error::SchemaMismatch {
+                            expected: expected.clone(),
+                            found,
+                        })
+                    }
+                    _ => self.schema = Some(found),
+                }
+            } else if &tag == CLAIMS_SECTION {
+                self.claims = Claims::read_from(&mut &payload[..])?;
+            } else if &tag == VOXEL_IDS_SECTION {
+                let mut payload = &payload[..];
+                let mut ids = BTreeMap::new();
+                for _ in 0..payload.read_u32::<LittleEndian>()? {
+                    let id = payload.read_u32::<LittleEndian>()?;
+                    ids.insert(id, read_string(&mut payload)?);
+                }
+                // the registry in use may have added types since the world was saved
+                self.saved_voxel_ids = match &self.registry {
+                    Some(registry) => {
+                        registry.check_stable_ids(&ids)?;
+                        Some(registry.id_names())
+                    }
+                    None => Some(ids),
+                };
+            } else if &tag == DICTIONARY_SECTION {
+                validate_dictionary(&payload)?;
+                self.compression_dictionary = Some(payload);
+            } else if &tag == RETIRED_DICTIONARIES_SECTION {
+                let mut payload = &payload[..];
+                self.retired_dictionaries.clear();
+                for _ in 0..payload.read_u32::<LittleEndian>()? {
+                    let mut dictionary = vec![0; payload.read_u32::<LittleEndian>()? as usize];
+                    payload.read_exact(&mut dictionary)?;
+                    validate_dictionary(&dictionary)?;
+                    self.retired_dictionaries
+                        .insert(hash_dictionary(Some(&dictionary)), dictionary);
+                }
+            } else if &tag == STATS_SECTION {
+                self.stats = WorldStats::read_from(&mut &payload[..])?;
+            } else if &tag == STRUCTURES_SECTION {
+                self.structures = StructureLibrary::read_from(&mut &payload[..])?;
+            } else if &tag == TOMBSTONES_SECTION {
+                let retention = self.tombstones.retention();
+                self.tombstones = Tombstones::read_from(&mut &payload[..])?;
+                self.tombstones.set_retention(retention);
+            } else if &tag == WAYPOINTS_SECTION {
+                self.waypoints = Waypoints::read_from(&mut &payload[..])?;
+            } else if &tag == COLD_STORAGE_SECTION {
+                self.cold_storage.read_from(&mut &payload[..])?;
+                // cold chunks aren't in the region files scanned for chunks
+                let cold: Vec<ChunkLocation> = self.cold_storage.cold_chunks().copied().collect();
+                self.all_chunk_locations.extend(cold);
+            } else if &tag == HISTORY_SECTION {
+                self.histories = ChunkHistories::read_from(&mut &payload[..])?;
+            } else if &tag == DIRTY_REGIONS_SECTION {
+                self.dirty_regions = Some(DirtyRegions::read_from(&mut &payload[..])?);
+            } else if &tag == WEATHER_SECTION {
+                self.weather.read_from(&mut &payload[..])?;
+            } else if &tag == DAMAGE_SECTION {
+                let tick = self.current_tick();
+                self.damage.read_from(&mut &payload[..], tick)?;
+            }
+        }
+        Ok(())
+    }
+}
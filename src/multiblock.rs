@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::patterns::find_pattern;
+use super::structures::Transform;
+use super::{Aabb, ChunkLocation, Dimension, GlobalLocation, Point3D, Volume};
+
+pub type MultiblockId = u32;
+
+/// A kind of multiblock structure, like a furnace built out of several voxels. It forms
+/// wherever the world matches its pattern exactly, empty voxels included
+#[derive(Clone)]
+pub struct MultiblockDefinition<T> {
+    pub name: String,
+    pub pattern: Volume<T>,
+    /// if the pattern also counts turned around the z axis
+    pub rotations: bool,
+}
+
+impl<T> MultiblockDefinition<T> {
+    pub fn new(name: &str, pattern: Volume<T>, rotations: bool) -> MultiblockDefinition<T> {
+        MultiblockDefinition {
+            name: String::from(name),
+            pattern,
+            rotations,
+        }
+    }
+}
+
+/// A multiblock standing in the world
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FormedMultiblock {
+    pub definition: MultiblockId,
+    /// the voxels the multiblock is made of
+    pub bounds: Aabb,
+    pub transform: Transform,
+}
+
+/// If a multiblock was just completed or just broken
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MultiblockChange {
+    Formed,
+    Broken,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct MultiblockEvent {
+    pub change: MultiblockChange,
+    pub multiblock: FormedMultiblock,
+}
+
+/// Called when an edit completes or breaks a multiblock
+pub type MultiblockListener = Arc<dyn Fn(&MultiblockEvent) + Send + Sync>;
+
+/// The registered kinds of multiblocks and the ones that are formed
+#[derive(Clone)]
+pub struct Multiblocks<T> {
+    next_id: MultiblockId,
+    definitions: BTreeMap<MultiblockId, MultiblockDefinition<T>>,
+    /// the formed multiblocks, keyed in the order they formed
+    formed: BTreeMap<u64, FormedMultiblock>,
+    next_formed: u64,
+    /// the keys of the formed multiblocks reaching into each chunk
+    by_chunk: HashMap<ChunkLocation, Vec<u64>>,
+    listener: Option<MultiblockListener>,
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Multiblocks<T> {
+    pub fn new() -> Multiblocks<T> {
+        Multiblocks {
+            next_id: 0,
+            definitions: BTreeMap::new(),
+            formed: BTreeMap::new(),
+            next_formed: 0,
+            by_chunk: HashMap::new(),
+            listener: None,
+        }
+    }
+
+    /// The chunks a box of voxels reaches into. Empty boxes are treated as a point at their
+    /// start
+    fn chunks_of(bounds: Aabb) -> Vec<ChunkLocation> {
+        let first = Dimension::<T>::get_chunk_location(bounds.start);
+        let last = if bounds.is_empty() {
+            first
+        } else {
+            Dimension::<T>::get_chunk_location(bounds.end - Point3D::new(1, 1, 1))
+        };
+        let mut chunks = Vec::new();
+        for z in first.z..=last.z {
+            for y in first.y..=last.y {
+                for x in first.x..=last.x {
+                    chunks.push(Point3D::new(x, y, z));
+                }
+            }
+        }
+        chunks
+    }
+
+    /// Adds a formed multiblock, returning false if it was formed already
+    fn insert(&mut self, multiblock: FormedMultiblock) -> bool {
+        let chunks = Self::chunks_of(multiblock.bounds);
+        let formed = &self.formed;
+        let known = self
+            .by_chunk
+            .get(&chunks[0])
+            .is_some_and(|keys| keys.iter().any(|key| formed[key] == multiblock));
+        if known {
+            return false;
+        }
+        let key = self.next_formed;
+        self.next_formed += 1;
+        self.formed.insert(key, multiblock);
+        for chunk in chunks {
+            self.by_chunk.entry(chunk).or_default().push(key);
+        }
+        true
+    }
+
+    fn remove(&mut self, key: u64) -> Option<FormedMultiblock> {
+        let multiblock = self.formed.remove(&key)?;
+        for chunk in Self::chunks_of(multiblock.bounds) {
+            if let Some(keys) = self.by_chunk.get_mut(&chunk) {
+                keys.retain(|other| *other != key);
+                if keys.is_empty() {
+                    self.by_chunk.remove(&chunk);
+                }
+            }
+        }
+        Some(multiblock)
+    }
+
+    /// The keys of the formed multiblocks holding location, oldest first
+    fn covering(&self, location: GlobalLocation) -> Vec<u64> {
+        let mut keys: Vec<u64> = self
+            .by_chunk
+            .get(&Dimension::<T>::get_chunk_location(location))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|key| self.formed[key].bounds.contains(location))
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn emit(&self, change: MultiblockChange, multiblock: FormedMultiblock) {
+        if let Some(listener) = &self.listener {
+            listener(&MultiblockEvent { change, multiblock });
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Registers a kind of multiblock, returning the id its events refer to it by. Only
+    /// edits made afterwards form it; detect_multiblocks finds the ones already built
+    pub fn register_multiblock(&mut self, definition: MultiblockDefinition<T>) -> MultiblockId {
+        let id = self.multiblocks.next_id;
+        self.multiblocks.next_id += 1;
+        self.multiblocks.definitions.insert(id, definition);
+        id
+    }
+
+    /// Unregisters a kind of multiblock. The formed ones are dropped without events
+    pub fn unregister_multiblock(&mut self, id: MultiblockId) -> Option<MultiblockDefinition<T>> {
+        let keys: Vec<u64> = self
+            .multiblocks
+            .formed
+            .iter()
+            .filter(|(_, formed)| formed.definition == id)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.multiblocks.remove(key);
+        }
+        self.multiblocks.definitions.remove(&id)
+    }
+
+    pub fn multiblock_definition(&self, id: MultiblockId) -> Option<&MultiblockDefinition<T>> {
+        self.multiblocks.definitions.get(&id)
+    }
+
+    /// The multiblocks formed so far, oldest first. They aren't saved with the world
+    pub fn formed_multiblocks(&self) -> Vec<FormedMultiblock> {
+        self.multiblocks.formed.values().copied().collect()
+    }
+
+    pub fn set_multiblock_listener(&mut self, listener: MultiblockListener) {
+        self.multiblocks.listener = Some(listener);
+    }
+
+    pub fn clear_multiblock_listener(&mut self) {
+        self.multiblocks.listener = None;
+    }
+
+    /// Forms every registered multiblock built in the loaded part of region that isn't
+    /// formed yet, e.g. after loading a world, returning how many formed
+    pub fn detect_multiblocks(&mut self, region: Aabb) -> usize {
+        let ids: Vec<MultiblockId> = self.multiblocks.definitions.keys().copied().collect();
+        ids.into_iter()
+            .map(|id| self.form_multiblocks(id, region))
+            .sum()
+    }
+
+    /// Forms the multiblocks of a definition found in region
+    fn form_multiblocks(&mut self, id: MultiblockId, region: Aabb) -> usize {
+        let definition = &self.multiblocks.definitions[&id];
+        let size = definition.pattern.end_location - definition.pattern.start_location;
+        let found = find_pattern(self, region, &definition.pattern, 0, definition.rotations);
+        let mut formed = 0;
+        for found in found {
+            let multiblock = FormedMultiblock {
+                definition: id,
+                bounds: Aabb::new(
+                    found.origin,
+                    found.origin + found.transform.placed_size(size),
+                ),
+                transform: found.transform,
+            };
+            if self.multiblocks.insert(multiblock) {
+                self.multiblocks.emit(MultiblockChange::Formed, multiblock);
+                formed += 1;
+            }
+        }
+        formed
+    }
+
+    /// Breaks the multiblocks a write of value to location doesn't fit, then forms the
+    /// ones it completes. Formed multiblocks are indexed by the chunks they reach into,
+    /// and only the placements that cover location are searched for new ones, so edits
+    /// stay cheap however many multiblocks there are. Called on every voxel write
+    pub fn multiblock_voxel_written(&mut self, location: GlobalLocation, old: T, value: T) {
+        if self.multiblocks.definitions.is_empty() || old == value {
+            return;
+        }
+        for key in self.multiblocks.covering(location) {
+            let multiblock = self.multiblocks.formed[&key];
+            let pattern = &self.multiblocks.definitions[&multiblock.definition].pattern;
+            let size = pattern.end_location - pattern.start_location;
+            let local = multiblock
+                .transform
+                .to_template(location - multiblock.bounds.start, size);
+            if pattern.get(pattern.start_location + local) != value {
+                self.multiblocks.remove(key);
+                self.multiblocks.emit(MultiblockChange::Broken, multiblock);
+            }
+        }
+        let candidates: Vec<(MultiblockId, Point3D)> = self
+            .multiblocks
+            .definitions
+            .iter()
+            .filter(|(_, definition)| definition.pattern.voxels.contains(&value))
+            .map(|(id, definition)| {
+                let pattern = &definition.pattern;
+                let size = pattern.end_location - pattern.start_location;
+                // turned patterns swap x and y, so the reach covers both
+                let reach = if definition.rotations {
+                    let side = size.x.max(size.y);
+                    Point3D::new(side, side, size.z)
+                } else {
+                    size
+                };
+                (*id, reach)
+            })
+            .collect();
+        for (id, reach) in candidates {
+            let region = Aabb::new(location - reach + Point3D::new(1, 1, 1), location + reach);
+            self.form_multiblocks(id, region);
+        }
+    }
+}
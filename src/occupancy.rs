@@ -0,0 +1,288 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::registry::VoxelRegistry;
+use super::{ChunkLocation, Dimension, Voxel, CHUNK_VOLUME};
+
+/// Voxel types with a value for empty space, like air. Occupancy summaries, meshing,
+/// point lists and structure overlays all go by it to tell what's there from what isn't
+pub trait EmptyVoxel {
+    /// If the voxel is empty space
+    fn is_empty(&self) -> bool;
+
+    /// The voxel of empty space
+    fn empty() -> Self;
+}
+
+/// Voxel types that can say what kind of space they fill. Equal voxels share storage in
+/// chunks
+pub trait VoxelClass: PartialEq + EmptyVoxel {
+    /// If nothing can pass through the voxel
+    fn is_solid(&self) -> bool;
+
+    /// Which kind of voxel this is, for counting voxels by type
+    fn type_id(&self) -> u32;
+
+    /// If the voxel blocks the view and sunlight. Solid voxels are opaque unless they
+    /// say otherwise
+    fn is_opaque(&self) -> bool {
+        self.is_solid()
+    }
+
+    /// If the voxel is solid according to registry, the voxel types loaded for the world.
+    /// Voxels whose types don't come from a registry ignore it
+    fn is_solid_in(&self, registry: Option<&VoxelRegistry>) -> bool {
+        self.is_solid()
+    }
+
+    /// If the voxel is opaque according to registry, like is_solid_in
+    fn is_opaque_in(&self, registry: Option<&VoxelRegistry>) -> bool {
+        self.is_opaque()
+    }
+
+    /// How much light the voxel holds
+    fn light(&self) -> u8 {
+        0
+    }
+}
+
+impl EmptyVoxel for Voxel {
+    fn is_empty(&self) -> bool {
+        self.id == 1
+    }
+
+    fn empty() -> Voxel {
+        Voxel {
+            id: 1,
+            ..Voxel::default()
+        }
+    }
+}
+
+impl VoxelClass for Voxel {
+    fn is_solid(&self) -> bool {
+        self.is_solid_in(None)
+    }
+
+    fn type_id(&self) -> u32 {
+        self.id
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.is_opaque_in(None)
+    }
+
+    fn is_solid_in(&self, registry: Option<&VoxelRegistry>) -> bool {
+        // without a registry the builtin types are used. Ids the registry doesn't know
+        // count as solid, like the unknown type
+        registry
+            .unwrap_or_else(|| VoxelRegistry::builtin())
+            .get(self.id)
+            .is_none_or(|voxel_type| voxel_type.solid)
+    }
+
+    fn is_opaque_in(&self, registry: Option<&VoxelRegistry>) -> bool {
+        registry
+            .unwrap_or_else(|| VoxelRegistry::builtin())
+            .get(self.id)
+            .is_none_or(|voxel_type| voxel_type.opacity >= 1.0)
+    }
+}
+
+impl EmptyVoxel for u8 {
+    fn is_empty(&self) -> bool {
+        *self == 0
+    }
+
+    fn empty() -> u8 {
+        0
+    }
+}
+
+impl VoxelClass for u8 {
+    fn is_solid(&self) -> bool {
+        *self != 0
+    }
+
+    fn type_id(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl EmptyVoxel for u16 {
+    fn is_empty(&self) -> bool {
+        *self == 0
+    }
+
+    fn empty() -> u16 {
+        0
+    }
+}
+
+impl VoxelClass for u16 {
+    fn is_solid(&self) -> bool {
+        *self != 0
+    }
+
+    fn type_id(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl EmptyVoxel for u32 {
+    fn is_empty(&self) -> bool {
+        *self == 0
+    }
+
+    fn empty() -> u32 {
+        0
+    }
+}
+
+impl VoxelClass for u32 {
+    fn is_solid(&self) -> bool {
+        *self != 0
+    }
+
+    fn type_id(&self) -> u32 {
+        *self
+    }
+}
+
+/// Counts of the kinds of voxels in a chunk, kept up to date as the chunk is written so
+/// questions like "is this chunk all air" can be answered without looking at the voxels
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChunkSummary {
+    solid: u16,
+    empty: u16,
+    /// how many voxels hold each light level
+    light_levels: [u16; 256],
+}
+
+impl ChunkSummary {
+    /// The summary of a chunk made entirely of value
+    pub fn uniform<T: VoxelClass>(value: &T) -> ChunkSummary {
+        let mut summary = ChunkSummary {
+            solid: 0,
+            empty: 0,
+            light_levels: [0; 256],
+        };
+        if value.is_solid() {
+            summary.solid = CHUNK_VOLUME as u16;
+        }
+        if value.is_empty() {
+            summary.empty = CHUNK_VOLUME as u16;
+        }
+        summary.light_levels[value.light() as usize] = CHUNK_VOLUME as u16;
+        summary
+    }
+
+    /// Accounts for old being overwritten by new
+    pub fn replace<T: VoxelClass>(&mut self, old: &T, new: &T) {
+        self.solid = self.solid - old.is_solid() as u16 + new.is_solid() as u16;
+        self.empty = self.empty - old.is_empty() as u16 + new.is_empty() as u16;
+        self.light_levels[old.light() as usize] -= 1;
+        self.light_levels[new.light() as usize] += 1;
+    }
+
+    /// If every voxel of the chunk is empty, so it has nothing to draw or tick
+    pub fn is_all_empty(&self) -> bool {
+        self.empty as usize == CHUNK_VOLUME
+    }
+
+    /// If every voxel of the chunk is solid, so nothing can move through it
+    pub fn is_all_solid(&self) -> bool {
+        self.solid as usize == CHUNK_VOLUME
+    }
+
+    pub fn solid_count(&self) -> usize {
+        self.solid as usize
+    }
+
+    pub fn empty_count(&self) -> usize {
+        self.empty as usize
+    }
+
+    pub fn min_light(&self) -> u8 {
+        self.light_levels
+            .iter()
+            .position(|count| *count > 0)
+            .unwrap() as u8
+    }
+
+    pub fn max_light(&self) -> u8 {
+        self.light_levels
+            .iter()
+            .rposition(|count| *count > 0)
+            .unwrap() as u8
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u16::<LittleEndian>(self.solid)?;
+        out.write_u16::<LittleEndian>(self.empty)?;
+        // most chunks only hold a few light levels, so only the used ones are written
+        let used = self.light_levels.iter().filter(|count| **count > 0).count();
+        out.write_u16::<LittleEndian>(used as u16)?;
+        for (level, count) in self.light_levels.iter().enumerate() {
+            if *count > 0 {
+                out.write_u8(level as u8)?;
+                out.write_u16::<LittleEndian>(*count)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<ChunkSummary> {
+        let mut summary = ChunkSummary {
+            solid: input.read_u16::<LittleEndian>()?,
+            empty: input.read_u16::<LittleEndian>()?,
+            light_levels: [0; 256],
+        };
+        for _ in 0..input.read_u16::<LittleEndian>()? {
+            let level = input.read_u8()?;
+            summary.light_levels[level as usize] = input.read_u16::<LittleEndian>()?;
+        }
+        let total: usize = summary
+            .light_levels
+            .iter()
+            .map(|count| *count as usize)
+            .sum();
+        if total != CHUNK_VOLUME
+            || summary.solid as usize > CHUNK_VOLUME
+            || summary.empty as usize > CHUNK_VOLUME
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk summary doesn't add up",
+            ));
+        }
+        Ok(summary)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The summary of a loaded chunk
+    pub fn chunk_summary(&self, location: ChunkLocation) -> Option<&ChunkSummary> {
+        self.loaded_chunks
+            .get(&location)
+            .map(|chunk| &chunk.summary)
+    }
+
+    /// If the chunk is loaded and holds nothing but empty voxels
+    pub fn chunk_all_empty(&self, location: ChunkLocation) -> bool {
+        self.chunk_summary(location)
+            .is_some_and(|summary| summary.is_all_empty())
+    }
+
+    /// If the chunk is loaded and holds nothing but solid voxels. Summaries count solid
+    /// voxels by the builtin types, so with a registry loaded this is always false rather
+    /// than possibly wrong
+    pub fn chunk_all_solid(&self, location: ChunkLocation) -> bool {
+        self.registry.is_none()
+            && self
+                .chunk_summary(location)
+                .is_some_and(|summary| summary.is_all_solid())
+    }
+}
@@ -0,0 +1,74 @@
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Dimension, GlobalLocation, Point3D};
+
+/// Converts between absolute voxel coordinates and coordinates relative to a floating
+/// origin. Physics engines and renderers working in f32 lose precision far from zero, so
+/// they work relative to an origin near the camera or player, which is moved along as they
+/// travel. The voxel data itself always stays in absolute coordinates
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The absolute location local coordinates are measured from
+    pub fn origin(&self) -> GlobalLocation {
+        self.origin
+    }
+
+    /// Moves the origin to a new absolute location, returning the shift every local
+    /// coordinate held by the caller has to be moved by (new local = old local + shift)
+    pub fn rebase_origin(&mut self, origin: GlobalLocation) -> [f64; 3] {
+        let shift = [
+            self.origin.x as f64 - origin.x as f64,
+            self.origin.y as f64 - origin.y as f64,
+            self.origin.z as f64 - origin.z as f64,
+        ];
+        self.origin = origin;
+        shift
+    }
+
+    /// Moves the origin to the chunk containing location if location has drifted more than
+    /// threshold voxels away from it, returning the shift applied if it moved
+    pub fn rebase_origin_if_far(
+        &mut self,
+        location: GlobalLocation,
+        threshold: u32,
+    ) -> Option<[f64; 3]> {
+        let far = location.x.abs_diff(self.origin.x) > threshold
+            || location.y.abs_diff(self.origin.y) > threshold
+            || location.z.abs_diff(self.origin.z) > threshold;
+        if far {
+            // the corner of the chunk, so local coordinates of voxels stay whole numbers
+            let origin = location - Self::get_voxel_location(location);
+            Some(self.rebase_origin(origin))
+        } else {
+            None
+        }
+    }
+
+    /// The position of a voxel's minimum corner relative to the origin, in double precision
+    pub fn to_local_f64(&self, location: GlobalLocation) -> [f64; 3] {
+        [
+            location.x as f64 - self.origin.x as f64,
+            location.y as f64 - self.origin.y as f64,
+            location.z as f64 - self.origin.z as f64,
+        ]
+    }
+
+    /// The position of a voxel's minimum corner relative to the origin, for f32 engines
+    pub fn to_local(&self, location: GlobalLocation) -> [f32; 3] {
+        let local = self.to_local_f64(location);
+        [local[0] as f32, local[1] as f32, local[2] as f32]
+    }
+
+    /// The voxel containing a position relative to the origin
+    pub fn to_global_f64(&self, local: [f64; 3]) -> GlobalLocation {
+        Point3D::new(
+            (self.origin.x as f64 + local[0]).floor() as i32,
+            (self.origin.y as f64 + local[1]).floor() as i32,
+            (self.origin.z as f64 + local[2]).floor() as i32,
+        )
+    }
+
+    /// The voxel containing a position relative to the origin, for f32 engines
+    pub fn to_global(&self, local: [f32; 3]) -> GlobalLocation {
+        self.to_global_f64([local[0] as f64, local[1] as f64, local[2] as f64])
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::codec::VoxelCodec;
+use super::faces::Face;
+use super::occupancy::VoxelClass;
+use super::pathfinding::{find_path, MovementProfile};
+use super::{Chunk, ChunkLocation, Dimension, GlobalLocation};
+
+/// What a cached path is looked up by. Profiles can't be compared, so callers give each
+/// profile they search with a key of their own
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PathKey {
+    pub start: GlobalLocation,
+    pub goal: GlobalLocation,
+    pub profile: u64,
+}
+
+/// A chunk a cached path passes through or touches, as it was when the path was found
+#[derive(Clone)]
+struct CorridorChunk {
+    location: ChunkLocation,
+    /// the chunk's stamp, None if it wasn't loaded
+    stamp: Option<u64>,
+    /// the chunk's content hash, for when its stamp changed but its voxels might not have
+    hash: Option<u64>,
+}
+
+#[derive(Clone)]
+struct CachedPath {
+    path: Vec<GlobalLocation>,
+    corridor: Vec<CorridorChunk>,
+    /// the generation of the path cost overrides the path was found under
+    overrides: u64,
+    last_used: u64,
+}
+
+/// The most recently used paths, so agents asking for the same path again don't search
+/// for it again. A path is only reused while the chunks along it and the path cost
+/// overrides are unchanged
+#[derive(Clone)]
+pub struct PathCache {
+    capacity: usize,
+    clock: u64,
+    paths: HashMap<PathKey, CachedPath>,
+    by_age: BTreeMap<u64, PathKey>,
+    hits: u64,
+    misses: u64,
+}
+
+impl PathCache {
+    /// A cache keeping up to capacity paths
+    pub fn new(capacity: usize) -> PathCache {
+        PathCache {
+            capacity,
+            clock: 0,
+            paths: HashMap::new(),
+            by_age: BTreeMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.by_age.clear();
+    }
+
+    /// How many lookups found a valid path
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// How many lookups had to search
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn remove(&mut self, key: &PathKey) {
+        if let Some(cached) = self.paths.remove(key) {
+            self.by_age.remove(&cached.last_used);
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: PathKey,
+        path: Vec<GlobalLocation>,
+        corridor: Vec<CorridorChunk>,
+        overrides: u64,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.paths.len() >= self.capacity {
+            let oldest = match self.by_age.values().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            self.remove(&oldest);
+        }
+        self.clock += 1;
+        self.by_age.insert(self.clock, key);
+        self.paths.insert(
+            key,
+            CachedPath {
+                path,
+                corridor,
+                overrides,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    fn touch(&mut self, key: &PathKey) {
+        if let Some(cached) = self.paths.get_mut(key) {
+            self.by_age.remove(&cached.last_used);
+            self.clock += 1;
+            cached.last_used = self.clock;
+            self.by_age.insert(self.clock, *key);
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The chunks a path passes through or touches, with their stamps and content hashes
+    fn path_corridor(&self, path: &[GlobalLocation]) -> Vec<CorridorChunk> {
+        let mut chunks: Vec<ChunkLocation> = Vec::new();
+        for location in path.iter() {
+            let neighbors = Face::ALL.iter().filter_map(|face| face.neighbor(*location));
+            for touched in std::iter::once(*location).chain(neighbors) {
+                let chunk = Self::get_chunk_location(touched);
+                if !chunks.contains(&chunk) {
+                    chunks.push(chunk);
+                }
+            }
+        }
+        chunks
+            .into_iter()
+            .map(|location| CorridorChunk {
+                location,
+                stamp: self.chunk_stamp(location),
+                hash: self.loaded_chunks.get(&location).map(Chunk::content_hash),
+            })
+            .collect()
+    }
+
+    /// If the chunk looks the same as when the corridor was built. Chunks whose stamp
+    /// changed are hashed, since reloading a chunk stamps it anew without changing it, and
+    /// their new stamp is kept when they turn out unchanged
+    fn corridor_chunk_unchanged(&self, chunk: &mut CorridorChunk) -> bool {
+        let stamp = self.chunk_stamp(chunk.location);
+        if stamp == chunk.stamp {
+            return true;
+        }
+        if self
+            .loaded_chunks
+            .get(&chunk.location)
+            .map(Chunk::content_hash)
+            == chunk.hash
+        {
+            chunk.stamp = stamp;
+            return true;
+        }
+        false
+    }
+
+    /// find_path through the loaded chunks, reusing the path cached for the same query if
+    /// none of the chunks along it or the path cost overrides changed since. Paths that
+    /// aren't found aren't cached
+    pub fn find_path_cached<P: MovementProfile<T> + ?Sized>(
+        &self,
+        cache: &mut PathCache,
+        key: PathKey,
+        profile: &P,
+    ) -> Option<Vec<GlobalLocation>> {
+        let overrides = self.path_cost_overrides.generation();
+        if let Some(cached) = cache.paths.get_mut(&key) {
+            let unchanged = cached.overrides == overrides
+                && cached
+                    .corridor
+                    .iter_mut()
+                    .all(|chunk| self.corridor_chunk_unchanged(chunk));
+            if unchanged {
+                let path = cached.path.clone();
+                cache.touch(&key);
+                cache.hits += 1;
+                return Some(path);
+            }
+            cache.remove(&key);
+        }
+        cache.misses += 1;
+        let path = find_path(self, key.start, key.goal, profile)?;
+        let corridor = self.path_corridor(&path);
+        cache.insert(key, path.clone(), corridor, overrides);
+        Some(path)
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Dimension, GlobalLocation};
+
+/// The cost of stepping into a voxel nobody has overridden
+pub const DEFAULT_STEP_COST: u32 = 1;
+
+/// A cost that makes a voxel impossible to step into
+pub const IMPASSABLE: u32 = u32::MAX;
+
+/// Sparse per-voxel costs consulted by pathfinding before the default step cost, for
+/// effects like fire or temporary blockades that shouldn't require editing voxels
+#[derive(Clone, Default)]
+pub struct PathCostOverrides {
+    costs: HashMap<GlobalLocation, u32>,
+    /// bumped on every change, so paths found under older costs can tell they are stale
+    generation: u64,
+}
+
+impl PathCostOverrides {
+    pub fn new() -> PathCostOverrides {
+        PathCostOverrides::default()
+    }
+
+    pub fn set(&mut self, location: GlobalLocation, cost: u32) {
+        self.costs.insert(location, cost);
+        self.generation += 1;
+    }
+
+    /// Goes back to the default cost for location
+    pub fn clear(&mut self, location: GlobalLocation) {
+        self.costs.remove(&location);
+        self.generation += 1;
+    }
+
+    pub fn clear_all(&mut self) {
+        self.costs.clear();
+        self.generation += 1;
+    }
+
+    /// Changes every time an override is set or cleared
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn get(&self, location: GlobalLocation) -> Option<u32> {
+        self.costs.get(&location).copied()
+    }
+
+    /// The cost of stepping into location, or None if it can't be entered
+    pub fn step_cost(&self, location: GlobalLocation) -> Option<u32> {
+        match self.get(location).unwrap_or(DEFAULT_STEP_COST) {
+            IMPASSABLE => None,
+            cost => Some(cost),
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Makes stepping into location cost cost instead of the default, IMPASSABLE blocks it
+    pub fn set_path_cost_override(&mut self, location: GlobalLocation, cost: u32) {
+        self.path_cost_overrides.set(location, cost);
+    }
+
+    pub fn clear_path_cost_override(&mut self, location: GlobalLocation) {
+        self.path_cost_overrides.clear(location);
+    }
+
+    pub fn path_cost_overrides(&self) -> &PathCostOverrides {
+        &self.path_cost_overrides
+    }
+}
@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+use super::anchors::AnchorId;
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{ChunkLocation, Dimension, GlobalLocation};
+
+/// A stretch of a path that stays inside one chunk
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PathSegment {
+    pub chunk: ChunkLocation,
+    /// the indices of the path steps inside the chunk
+    pub steps: Range<usize>,
+}
+
+/// Keeps the chunks an agent is about to walk through loaded, by holding an anchor on each
+#[derive(Clone, Default)]
+pub struct PathTicket {
+    anchors: Vec<AnchorId>,
+}
+
+impl PathTicket {
+    pub fn new() -> PathTicket {
+        PathTicket::default()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Splits a path into consecutive runs of steps that lie in the same chunk
+    pub fn split_path_by_chunk(path: &[GlobalLocation]) -> Vec<PathSegment> {
+        let mut segments: Vec<PathSegment> = Vec::new();
+        for (i, location) in path.iter().enumerate() {
+            let chunk = Self::get_chunk_location(*location);
+            match segments.last_mut() {
+                Some(segment) if segment.chunk == chunk => segment.steps.end = i + 1,
+                _ => segments.push(PathSegment {
+                    chunk,
+                    steps: i..i + 1,
+                }),
+            }
+        }
+        segments
+    }
+
+    /// The chunks the next steps of a path pass through, starting from the step at position,
+    /// in the order they are entered
+    pub fn chunks_ahead(
+        path: &[GlobalLocation],
+        position: usize,
+        steps: usize,
+    ) -> Vec<ChunkLocation> {
+        let end = std::cmp::min(path.len(), position.saturating_add(steps));
+        let mut chunks: Vec<ChunkLocation> = Vec::new();
+        for location in path[std::cmp::min(position, end)..end].iter() {
+            let chunk = Self::get_chunk_location(*location);
+            if !chunks.contains(&chunk) {
+                chunks.push(chunk);
+            }
+        }
+        chunks
+    }
+
+    /// Points the ticket's anchors at the chunks the agent passes through in its next steps,
+    /// so they are kept loaded. Call again as the agent advances along the path
+    pub fn update_path_ticket(
+        &mut self,
+        ticket: &mut PathTicket,
+        path: &[GlobalLocation],
+        position: usize,
+        steps: usize,
+    ) {
+        let chunks = Self::chunks_ahead(path, position, steps);
+        while ticket.anchors.len() > chunks.len() {
+            self.remove_anchor(ticket.anchors.pop().unwrap());
+        }
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            match ticket.anchors.get(i) {
+                Some(id) => {
+                    self.move_anchor(*id, chunk);
+                }
+                None => ticket.anchors.push(self.add_anchor(chunk, 0)),
+            }
+        }
+    }
+
+    /// Lets go of every chunk held by the ticket
+    pub fn release_path_ticket(&mut self, ticket: &mut PathTicket) {
+        for id in ticket.anchors.drain(..) {
+            self.remove_anchor(id);
+        }
+    }
+}
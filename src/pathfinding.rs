@@ -0,0 +1,556 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::faces::Face;
+use super::lighting::LightSampler;
+use super::occupancy::VoxelClass;
+use super::path_costs::{PathCostOverrides, DEFAULT_STEP_COST, IMPASSABLE};
+use super::traversal::Agent;
+use super::{is_traversable, GlobalLocation, Node, Volume, Voxel, VoxelRead};
+
+/// The location offset by (x, y, z), if that doesn't leave the coordinate space
+fn offset(location: GlobalLocation, x: i32, y: i32, z: i32) -> Option<GlobalLocation> {
+    Some(GlobalLocation::new(
+        location.x.checked_add(x)?,
+        location.y.checked_add(y)?,
+        location.z.checked_add(z)?,
+    ))
+}
+
+/// The six locations sharing a face with location
+pub fn face_neighbors(location: GlobalLocation) -> Vec<GlobalLocation> {
+    [
+        (-1, 0, 0),
+        (1, 0, 0),
+        (0, -1, 0),
+        (0, 1, 0),
+        (0, 0, -1),
+        (0, 0, 1),
+    ]
+    .iter()
+    .filter_map(|(x, y, z)| offset(location, *x, *y, *z))
+    .collect()
+}
+
+/// How something moves through the world: where it can be, what each step costs and which
+/// steps it can take
+pub trait MovementProfile<T> {
+    /// If the mover can be at location
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool;
+
+    /// What stepping from one location to a neighboring one costs, None if the step can't
+    /// be taken even though both ends can be entered
+    fn step_cost(
+        &self,
+        map: &dyn VoxelRead<T>,
+        from: GlobalLocation,
+        to: GlobalLocation,
+    ) -> Option<u32> {
+        Some(DEFAULT_STEP_COST)
+    }
+
+    /// The locations one step away. The face neighbors unless overridden
+    fn neighbors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        face_neighbors(location)
+    }
+
+    /// The locations one step can come from, for searches going backwards. The same as
+    /// neighbors unless overridden, which is right for profiles whose steps go both ways
+    fn predecessors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.neighbors(location)
+    }
+
+    /// A guess at the cost from one location to another, which must never be more than the
+    /// real cost for paths to come out shortest. The manhattan distance unless overridden
+    fn heuristic(&self, from: GlobalLocation, to: GlobalLocation) -> u32 {
+        from.x.abs_diff(to.x) + from.y.abs_diff(to.y) + from.z.abs_diff(to.z)
+    }
+}
+
+/// Walking agents need solid ground underneath, unless their abilities let them swim or
+/// climb through the voxel they're in
+impl<'a> MovementProfile<Voxel> for Agent<'a> {
+    fn can_enter(&self, map: &dyn VoxelRead<Voxel>, location: GlobalLocation) -> bool {
+        is_traversable(map, location, self)
+    }
+}
+
+/// Flies through anything that isn't solid
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Flyer;
+
+impl<T: VoxelClass> MovementProfile<T> for Flyer {
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool {
+        map.within_bounds(location) && !map.get(location).is_solid()
+    }
+}
+
+/// Adds horizontal diagonal steps to another profile. A diagonal step costs half again as
+/// much as the inner profile's step, and can't cut the corner of something the inner
+/// profile couldn't enter
+#[derive(Copy, Clone, Debug)]
+pub struct Diagonal<P>(pub P);
+
+impl<T, P: MovementProfile<T>> MovementProfile<T> for Diagonal<P> {
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool {
+        self.0.can_enter(map, location)
+    }
+
+    fn step_cost(
+        &self,
+        map: &dyn VoxelRead<T>,
+        from: GlobalLocation,
+        to: GlobalLocation,
+    ) -> Option<u32> {
+        if from.x == to.x || from.y == to.y {
+            return self.0.step_cost(map, from, to);
+        }
+        let corners = [
+            GlobalLocation::new(to.x, from.y, from.z),
+            GlobalLocation::new(from.x, to.y, from.z),
+        ];
+        if !corners.iter().all(|corner| self.0.can_enter(map, *corner)) {
+            return None;
+        }
+        let cost = self.0.step_cost(map, from, to)?;
+        Some(cost.saturating_add(cost.div_ceil(2)))
+    }
+
+    fn neighbors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        let mut neighbors = self.0.neighbors(location);
+        for (x, y) in [(-1, -1), (-1, 1), (1, -1), (1, 1)].iter() {
+            neighbors.extend(offset(location, *x, *y, 0));
+        }
+        neighbors
+    }
+
+    fn predecessors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        let mut predecessors = self.0.predecessors(location);
+        for (x, y) in [(-1, -1), (-1, 1), (1, -1), (1, 1)].iter() {
+            predecessors.extend(offset(location, *x, *y, 0));
+        }
+        predecessors
+    }
+
+    /// Diagonal steps cover two axes for at least the cost of one straight step
+    fn heuristic(&self, from: GlobalLocation, to: GlobalLocation) -> u32 {
+        std::cmp::max(from.x.abs_diff(to.x), from.y.abs_diff(to.y)) + from.z.abs_diff(to.z)
+    }
+}
+
+/// Applies path cost overrides on top of another profile: overridden locations cost their
+/// override to step into instead, or can't be entered at all if IMPASSABLE
+pub struct WithOverrides<'a, P> {
+    pub profile: P,
+    pub overrides: &'a PathCostOverrides,
+}
+
+impl<'a, T, P: MovementProfile<T>> MovementProfile<T> for WithOverrides<'a, P> {
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool {
+        self.overrides.get(location) != Some(IMPASSABLE) && self.profile.can_enter(map, location)
+    }
+
+    fn step_cost(
+        &self,
+        map: &dyn VoxelRead<T>,
+        from: GlobalLocation,
+        to: GlobalLocation,
+    ) -> Option<u32> {
+        let cost = self.profile.step_cost(map, from, to)?;
+        match self.overrides.get(to) {
+            Some(IMPASSABLE) => None,
+            Some(cost) => Some(cost),
+            None => Some(cost),
+        }
+    }
+
+    fn neighbors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.profile.neighbors(location)
+    }
+
+    fn predecessors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.profile.predecessors(location)
+    }
+
+    /// Overrides can make steps cheaper than the profile's, even free, so no guess is safe
+    /// and searches fall back to Dijkstra
+    fn heuristic(&self, from: GlobalLocation, to: GlobalLocation) -> u32 {
+        0
+    }
+}
+
+/// Adds a cost to steps depending on how lit the voxel stepped into is, like monsters
+/// preferring the dark or villagers avoiding it. extra_cost maps a light level to what it
+/// adds to the inner profile's step, None makes voxels that lit impassable
+pub struct LightCost<'a, P, F> {
+    pub profile: P,
+    pub light: LightSampler<'a>,
+    pub extra_cost: F,
+}
+
+impl<'a, T, P: MovementProfile<T>, F: Fn(u8) -> Option<u32>> MovementProfile<T>
+    for LightCost<'a, P, F>
+{
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool {
+        self.profile.can_enter(map, location)
+    }
+
+    fn step_cost(
+        &self,
+        map: &dyn VoxelRead<T>,
+        from: GlobalLocation,
+        to: GlobalLocation,
+    ) -> Option<u32> {
+        let cost = self.profile.step_cost(map, from, to)?;
+        Some(cost.saturating_add((self.extra_cost)(self.light.get(to))?))
+    }
+
+    fn neighbors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.profile.neighbors(location)
+    }
+
+    fn predecessors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.profile.predecessors(location)
+    }
+
+    /// Light only ever adds to the cost, so the inner guess still holds
+    fn heuristic(&self, from: GlobalLocation, to: GlobalLocation) -> u32 {
+        self.profile.heuristic(from, to)
+    }
+}
+
+/// Overlays weighted danger maps onto another profile, like turret coverage or fire, so
+/// agents path around danger without a search of their own. Stepping into a voxel costs
+/// the weighted sum of the dangers there on top of the inner step, rounded up. Negative
+/// sums add nothing, infinite ones make the voxel impassable. Voxels outside of a map
+/// have no danger from it
+pub struct DangerCost<'a, P> {
+    pub profile: P,
+    pub maps: Vec<(&'a Volume<f32>, f32)>,
+}
+
+impl<'a, P> DangerCost<'a, P> {
+    pub fn new(profile: P) -> DangerCost<'a, P> {
+        DangerCost {
+            profile,
+            maps: Vec::new(),
+        }
+    }
+
+    /// Adds a danger map, its values multiplied by weight
+    pub fn with_map(mut self, map: &'a Volume<f32>, weight: f32) -> DangerCost<'a, P> {
+        self.maps.push((map, weight));
+        self
+    }
+
+    /// The weighted danger at location
+    pub fn danger(&self, location: GlobalLocation) -> f32 {
+        self.maps
+            .iter()
+            .filter(|(map, _)| map.within_bounds(location))
+            .map(|(map, weight)| map.get(location) * weight)
+            .sum()
+    }
+}
+
+impl<'a, T, P: MovementProfile<T>> MovementProfile<T> for DangerCost<'a, P> {
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool {
+        self.profile.can_enter(map, location)
+    }
+
+    fn step_cost(
+        &self,
+        map: &dyn VoxelRead<T>,
+        from: GlobalLocation,
+        to: GlobalLocation,
+    ) -> Option<u32> {
+        let cost = self.profile.step_cost(map, from, to)?;
+        let danger = self.danger(to);
+        if danger.is_nan() || danger == f32::INFINITY {
+            return None;
+        }
+        // the float to int cast saturates, so huge dangers cost u32::MAX
+        Some(cost.saturating_add(danger.max(0.0).ceil() as u32))
+    }
+
+    fn neighbors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.profile.neighbors(location)
+    }
+
+    fn predecessors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        self.profile.predecessors(location)
+    }
+
+    /// Danger only ever adds to the cost, so the inner guess still holds
+    fn heuristic(&self, from: GlobalLocation, to: GlobalLocation) -> u32 {
+        self.profile.heuristic(from, to)
+    }
+}
+
+/// How a conveyor voxel pushes what stands on it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Conveyor {
+    /// the way the conveyor moves, one of the Face::SIDES
+    pub direction: Face,
+    /// added to steps off the conveyor going against its direction
+    pub against_cost: u32,
+}
+
+/// Steps that only go one way on top of another profile: dropping off ledges, which can't
+/// be climbed back up, and conveyors that make going against them cost more. Searches
+/// going forwards follow neighbors and ones going backwards predecessors, so both honor
+/// the rules
+#[derive(Clone, Debug)]
+pub struct MovementRules<P> {
+    pub profile: P,
+    /// how many voxels the mover may drop down off a ledge in one step, 0 for none
+    pub max_drop: u32,
+    /// conveyor voxel types by their VoxelClass::type_id. The conveyor a step is taken on
+    /// is the voxel underneath where it starts
+    pub conveyors: HashMap<u32, Conveyor>,
+}
+
+impl<P> MovementRules<P> {
+    pub fn new(profile: P) -> MovementRules<P> {
+        MovementRules {
+            profile,
+            max_drop: 0,
+            conveyors: HashMap::new(),
+        }
+    }
+
+    pub fn with_max_drop(mut self, max_drop: u32) -> MovementRules<P> {
+        self.max_drop = max_drop;
+        self
+    }
+
+    pub fn with_conveyor(mut self, type_id: u32, conveyor: Conveyor) -> MovementRules<P> {
+        self.conveyors.insert(type_id, conveyor);
+        self
+    }
+
+    /// The locations dropping off of location can land on, or that can drop onto location
+    /// when going up
+    fn drops(&self, location: GlobalLocation, up: bool) -> Vec<GlobalLocation> {
+        let mut drops = Vec::new();
+        for (x, y) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            for height in 1..=self.max_drop.min(i32::MAX as u32) as i32 {
+                let z = if up { height } else { -height };
+                drops.extend(offset(location, *x, *y, z));
+            }
+        }
+        drops
+    }
+}
+
+impl<T: VoxelClass, P: MovementProfile<T>> MovementProfile<T> for MovementRules<P> {
+    fn can_enter(&self, map: &dyn VoxelRead<T>, location: GlobalLocation) -> bool {
+        self.profile.can_enter(map, location)
+    }
+
+    fn step_cost(
+        &self,
+        map: &dyn VoxelRead<T>,
+        from: GlobalLocation,
+        to: GlobalLocation,
+    ) -> Option<u32> {
+        let mut cost = self.profile.step_cost(map, from, to)?;
+        let horizontal = from.x != to.x || from.y != to.y;
+        if horizontal && to.z < from.z {
+            // the mover falls down the column next to it, which has to be open
+            for z in to.z + 1..=from.z {
+                let open = GlobalLocation::new(to.x, to.y, z);
+                if !map.within_bounds(open) || map.get(open).is_solid() {
+                    return None;
+                }
+            }
+            cost = cost.saturating_add(from.z.abs_diff(to.z));
+        }
+        let underneath = offset(from, 0, 0, -1).filter(|underneath| map.within_bounds(*underneath));
+        if let Some(underneath) = underneath {
+            if let Some(conveyor) = self.conveyors.get(&map.get(underneath).type_id()) {
+                let push = conveyor
+                    .direction
+                    .neighbor(GlobalLocation::default())
+                    .unwrap();
+                let against = (to.x - from.x) * push.x + (to.y - from.y) * push.y < 0;
+                if against {
+                    cost = cost.saturating_add(conveyor.against_cost);
+                }
+            }
+        }
+        Some(cost)
+    }
+
+    fn neighbors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        let mut neighbors = self.profile.neighbors(location);
+        neighbors.extend(self.drops(location, false));
+        neighbors
+    }
+
+    fn predecessors(&self, location: GlobalLocation) -> Vec<GlobalLocation> {
+        let mut predecessors = self.profile.predecessors(location);
+        predecessors.extend(self.drops(location, true));
+        predecessors
+    }
+
+    /// Drops cost a step plus their height and conveyors only add, so the inner guess
+    /// still holds
+    fn heuristic(&self, from: GlobalLocation, to: GlobalLocation) -> u32 {
+        self.profile.heuristic(from, to)
+    }
+}
+
+/// The cheapest path from start to goal for the profile, both ends included, found with A*.
+/// None if the goal can't be reached
+pub fn find_path<T, M: VoxelRead<T>, P: MovementProfile<T> + ?Sized>(
+    map: &M,
+    start: GlobalLocation,
+    goal: GlobalLocation,
+    profile: &P,
+) -> Option<Vec<GlobalLocation>> {
+    if !profile.can_enter(map, start) || !profile.can_enter(map, goal) {
+        return None;
+    }
+    // the nodes on the exploring front, ordered by their cost so far plus the heuristic
+    let mut frontier: BinaryHeap<Node> = BinaryHeap::new();
+    // the cheapest known cost of getting to each location
+    let mut costs: HashMap<GlobalLocation, u32> = HashMap::new();
+    // where the cheapest known path to each location came from
+    let mut parents: HashMap<GlobalLocation, GlobalLocation> = HashMap::new();
+
+    costs.insert(start, 0);
+    frontier.push(Node {
+        location: start,
+        cost: profile.heuristic(start, goal),
+    });
+    while let Some(current) = frontier.pop() {
+        let location = current.location;
+        if location == goal {
+            let mut path = vec![goal];
+            while let Some(parent) = parents.get(path.last().unwrap()) {
+                path.push(*parent);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let cost = costs[&location];
+        // a location may be queued several times, only its cheapest entry is expanded
+        if current.cost > cost.saturating_add(profile.heuristic(location, goal)) {
+            continue;
+        }
+        for neighbor in profile.neighbors(location) {
+            if !map.within_bounds(neighbor) || !profile.can_enter(map, neighbor) {
+                continue;
+            }
+            let step = match profile.step_cost(map, location, neighbor) {
+                Some(step) => step,
+                None => continue,
+            };
+            let neighbor_cost = cost.saturating_add(step);
+            if costs
+                .get(&neighbor)
+                .is_some_and(|known| *known <= neighbor_cost)
+            {
+                continue;
+            }
+            costs.insert(neighbor, neighbor_cost);
+            parents.insert(neighbor, location);
+            frontier.push(Node {
+                location: neighbor,
+                cost: neighbor_cost.saturating_add(profile.heuristic(neighbor, goal)),
+            });
+        }
+    }
+    None
+}
+
+/// The goal nearest to an agent and how to get there
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GoalAssignment {
+    /// the index of the goal in the goals searched for
+    pub goal: usize,
+    /// from the agent to the goal, both ends included
+    pub path: Vec<GlobalLocation>,
+    pub cost: u32,
+}
+
+/// The cheapest goal to reach for each agent, and the path to it, found with one Dijkstra
+/// search spreading from every goal at once instead of a search per agent. The search
+/// follows steps backwards, so profiles with one way steps are honored. None for agents
+/// that can't reach any goal
+pub fn nearest_goals<T, M: VoxelRead<T>, P: MovementProfile<T> + ?Sized>(
+    map: &M,
+    agents: &[GlobalLocation],
+    goals: &[GlobalLocation],
+    profile: &P,
+) -> Vec<Option<GoalAssignment>> {
+    let mut frontier: BinaryHeap<Node> = BinaryHeap::new();
+    let mut costs: HashMap<GlobalLocation, u32> = HashMap::new();
+    // the goal each location is cheapest to reach, and the next step towards it
+    let mut nearest: HashMap<GlobalLocation, usize> = HashMap::new();
+    let mut next_step: HashMap<GlobalLocation, GlobalLocation> = HashMap::new();
+    for (i, goal) in goals.iter().enumerate() {
+        if !profile.can_enter(map, *goal) || costs.contains_key(goal) {
+            continue;
+        }
+        costs.insert(*goal, 0);
+        nearest.insert(*goal, i);
+        frontier.push(Node {
+            location: *goal,
+            cost: 0,
+        });
+    }
+    let mut waiting: HashSet<GlobalLocation> = agents.iter().copied().collect();
+    while let Some(current) = frontier.pop() {
+        if waiting.is_empty() {
+            break;
+        }
+        let location = current.location;
+        if current.cost > costs[&location] {
+            continue;
+        }
+        waiting.remove(&location);
+        for previous in profile.predecessors(location) {
+            if !map.within_bounds(previous) || !profile.can_enter(map, previous) {
+                continue;
+            }
+            let step = match profile.step_cost(map, previous, location) {
+                Some(step) => step,
+                None => continue,
+            };
+            let previous_cost = current.cost.saturating_add(step);
+            if costs
+                .get(&previous)
+                .is_some_and(|known| *known <= previous_cost)
+            {
+                continue;
+            }
+            costs.insert(previous, previous_cost);
+            nearest.insert(previous, nearest[&location]);
+            next_step.insert(previous, location);
+            frontier.push(Node {
+                location: previous,
+                cost: previous_cost,
+            });
+        }
+    }
+    agents
+        .iter()
+        .map(|agent| {
+            // locations still waiting were never settled, their costs may not be final
+            if waiting.contains(agent) {
+                return None;
+            }
+            let goal = *nearest.get(agent)?;
+            let mut path = vec![*agent];
+            while let Some(next) = next_step.get(path.last().unwrap()) {
+                path.push(*next);
+            }
+            Some(GoalAssignment {
+                goal,
+                path,
+                cost: costs[agent],
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,217 @@
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::structures::Transform;
+use super::{
+    Aabb, Dimension, GlobalLocation, Point3D, Volume, VoxelRead, CHUNK_X_SIZE, CHUNK_Y_SIZE,
+    CHUNK_Z_SIZE,
+};
+
+/// Where a pattern was found
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PatternMatch {
+    /// the lowest corner of the placement
+    pub origin: GlobalLocation,
+    /// how the pattern was turned to match, as for structures
+    pub transform: Transform,
+    /// how many voxels of the placement differ from the pattern
+    pub mismatches: usize,
+}
+
+/// The pattern turned one way, laid out x first like a volume
+struct Orientation<T> {
+    transform: Transform,
+    size: Point3D,
+    voxels: Vec<T>,
+}
+
+impl<T: Copy + Default> Orientation<T> {
+    fn new(pattern: &Volume<T>, transform: Transform) -> Orientation<T> {
+        let template_size = pattern.end_location - pattern.start_location;
+        let size = transform.placed_size(template_size);
+        let mut voxels = Vec::with_capacity((size.x * size.y * size.z) as usize);
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let local = transform.to_template(Point3D::new(x, y, z), template_size);
+                    voxels.push(pattern.get(pattern.start_location + local));
+                }
+            }
+        }
+        Orientation {
+            transform,
+            size,
+            voxels,
+        }
+    }
+
+    fn local(&self, index: usize) -> Point3D {
+        let index = index as i32;
+        Point3D::new(
+            index % self.size.x,
+            (index / self.size.x) % self.size.y,
+            index / (self.size.x * self.size.y),
+        )
+    }
+}
+
+/// Every placement of pattern lying wholly inside region that differs from it in at most
+/// tolerance voxels, ordered by origin. With rotations, the pattern is also tried turned
+/// around the z axis, each distinct turn once. Voxels that can't be read count as
+/// differing. Whole chunks of the map known to be all empty or all solid are skipped
+/// without reading them when the pattern can't match there, so searching wide open or
+/// buried areas for a multiblock machine is cheap
+pub fn find_pattern<T: Copy + Default + VoxelClass, M: VoxelRead<T> + ?Sized>(
+    map: &M,
+    region: Aabb,
+    pattern: &Volume<T>,
+    tolerance: usize,
+    rotations: bool,
+) -> Vec<PatternMatch> {
+    let turns = if rotations { 4 } else { 1 };
+    let mut orientations: Vec<Orientation<T>> = Vec::new();
+    for quarter_turns in 0..turns {
+        let orientation = Orientation::new(
+            pattern,
+            Transform {
+                quarter_turns,
+                mirror_x: false,
+            },
+        );
+        // symmetric patterns would otherwise be found more than once in the same place
+        if !orientations
+            .iter()
+            .any(|other| other.size == orientation.size && other.voxels == orientation.voxels)
+        {
+            orientations.push(orientation);
+        }
+    }
+    // how many voxels of the pattern differ from all empty and all solid space
+    let not_empty = pattern
+        .voxels
+        .iter()
+        .filter(|voxel| !voxel.is_empty())
+        .count();
+    let not_solid = pattern
+        .voxels
+        .iter()
+        .filter(|voxel| !voxel.is_solid())
+        .count();
+    let chunk_size = Point3D::new(
+        CHUNK_X_SIZE as i32,
+        CHUNK_Y_SIZE as i32,
+        CHUNK_Z_SIZE as i32,
+    );
+    let chunk_of = |location: Point3D| {
+        Point3D::new(
+            location.x.div_euclid(chunk_size.x),
+            location.y.div_euclid(chunk_size.y),
+            location.z.div_euclid(chunk_size.z),
+        )
+    };
+
+    let mut matches = Vec::new();
+    for orientation in orientations.iter() {
+        if orientation.voxels.is_empty() {
+            continue;
+        }
+        // the origins of the placements that fit in region
+        let start = region.start;
+        let end = region.end - orientation.size + Point3D::new(1, 1, 1);
+        if end.x <= start.x || end.y <= start.y || end.z <= start.z {
+            continue;
+        }
+        let (first, last) = (chunk_of(start), chunk_of(end - Point3D::new(1, 1, 1)));
+        for chunk_z in first.z..=last.z {
+            for chunk_y in first.y..=last.y {
+                for chunk_x in first.x..=last.x {
+                    let corner = Point3D::new(
+                        chunk_x * chunk_size.x,
+                        chunk_y * chunk_size.y,
+                        chunk_z * chunk_size.z,
+                    );
+                    let block_start = Point3D::new(
+                        corner.x.max(start.x),
+                        corner.y.max(start.y),
+                        corner.z.max(start.z),
+                    );
+                    let block_end = Point3D::new(
+                        (corner.x + chunk_size.x).min(end.x),
+                        (corner.y + chunk_size.y).min(end.y),
+                        (corner.z + chunk_size.z).min(end.z),
+                    );
+                    // the chunks every placement from this block reaches into
+                    let reach_first = chunk_of(block_start);
+                    let reach_last = chunk_of(block_end + orientation.size - Point3D::new(2, 2, 2));
+                    let mut all_empty = true;
+                    let mut all_solid = true;
+                    for z in reach_first.z..=reach_last.z {
+                        for y in reach_first.y..=reach_last.y {
+                            for x in reach_first.x..=reach_last.x {
+                                let location = Point3D::new(
+                                    x * chunk_size.x,
+                                    y * chunk_size.y,
+                                    z * chunk_size.z,
+                                );
+                                all_empty &= map.known_all_empty(location);
+                                all_solid &= map.known_all_solid(location);
+                            }
+                        }
+                    }
+                    if (all_empty && not_empty > tolerance) || (all_solid && not_solid > tolerance)
+                    {
+                        continue;
+                    }
+                    for z in block_start.z..block_end.z {
+                        for y in block_start.y..block_end.y {
+                            for x in block_start.x..block_end.x {
+                                let origin = Point3D::new(x, y, z);
+                                let mut mismatches = 0;
+                                for (index, expected) in orientation.voxels.iter().enumerate() {
+                                    let location = origin + orientation.local(index);
+                                    if !map.within_bounds(location)
+                                        || map.get(location) != *expected
+                                    {
+                                        mismatches += 1;
+                                        if mismatches > tolerance {
+                                            break;
+                                        }
+                                    }
+                                }
+                                if mismatches <= tolerance {
+                                    matches.push(PatternMatch {
+                                        origin,
+                                        transform: orientation.transform,
+                                        mismatches,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    matches.sort_by_key(|found| {
+        (
+            found.origin.z,
+            found.origin.y,
+            found.origin.x,
+            found.transform.quarter_turns,
+        )
+    });
+    matches
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The placements of pattern in the loaded part of region, like find_pattern. Chunk
+    /// occupancy summaries rule out chunks of plain air or rock without reading them
+    pub fn find_pattern(
+        &self,
+        region: Aabb,
+        pattern: &Volume<T>,
+        tolerance: usize,
+        rotations: bool,
+    ) -> Vec<PatternMatch> {
+        find_pattern(self, region, pattern, tolerance, rotations)
+    }
+}
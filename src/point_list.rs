@@ -0,0 +1,104 @@
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+
+use super::occupancy::VoxelClass;
+use super::{Point3D, Volume};
+
+/// A voxel given by its coordinates and value, the way spreadsheets, scanners and point
+/// clouds list them
+pub type VoxelPoint<T> = (i32, i32, i32, T);
+
+impl<T: Copy + Default + VoxelClass> Volume<T> {
+    /// The smallest volume holding every point, with the voxels no point sets left empty,
+    /// the way to_point_list skips them. Where points repeat a location the last one wins.
+    /// An empty list gives an empty volume at the origin
+    pub fn from_point_list<I: IntoIterator<Item = VoxelPoint<T>>>(points: I) -> Volume<T> {
+        let points: Vec<VoxelPoint<T>> = points.into_iter().collect();
+        let (first_x, first_y, first_z, _) = match points.first() {
+            Some(first) => *first,
+            None => return Volume::new(Point3D::new(0, 0, 0), Point3D::new(0, 0, 0), T::empty()),
+        };
+        let mut start = Point3D::new(first_x, first_y, first_z);
+        let mut end = start;
+        for (x, y, z, _) in points.iter() {
+            start = Point3D::new(start.x.min(*x), start.y.min(*y), start.z.min(*z));
+            end = Point3D::new(end.x.max(*x), end.y.max(*y), end.z.max(*z));
+        }
+        let mut volume = Volume::new(start, end + Point3D::new(1, 1, 1), T::empty());
+        for (x, y, z, value) in points {
+            volume.set(Point3D::new(x, y, z), value);
+        }
+        volume
+    }
+
+    /// Every voxel of the volume that isn't empty, in storage order, so sparse volumes
+    /// come out as short lists
+    pub fn to_point_list(&self) -> Vec<VoxelPoint<T>> {
+        self.voxels
+            .iter()
+            .enumerate()
+            .filter(|(_, voxel)| !voxel.is_empty())
+            .map(|(index, voxel)| {
+                let location = self.get_location(index);
+                (location.x, location.y, location.z, *voxel)
+            })
+            .collect()
+    }
+}
+
+/// Reads a point list from text with one "x, y, z, value" line per voxel. Fields may be
+/// separated by commas, semicolons or whitespace. Blank lines, lines starting with # and a
+/// header line before the first point are skipped, so most csv exports read as they are
+pub fn read_point_list<T, R: BufRead, F: Fn(&str) -> Option<T>>(
+    input: R,
+    parse_value: F,
+) -> io::Result<Vec<VoxelPoint<T>>> {
+    let mut points = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+        let point = match fields.as_slice() {
+            [x, y, z, value] => match (x.parse(), y.parse(), z.parse(), parse_value(value)) {
+                (Ok(x), Ok(y), Ok(z), Some(value)) => Some((x, y, z, value)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match point {
+            Some(point) => points.push(point),
+            None if points.is_empty()
+                && fields.first().is_some_and(|x| x.parse::<i32>().is_err()) =>
+            {
+                // the header
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {} is not a point: {:?}", number + 1, line),
+                ))
+            }
+        }
+    }
+    Ok(points)
+}
+
+/// Writes a point list as csv, one "x,y,z,value" line per voxel under an "x,y,z,value"
+/// header
+pub fn write_point_list<T, W: Write, V: Display, F: Fn(&T) -> V>(
+    out: &mut W,
+    points: &[VoxelPoint<T>],
+    format_value: F,
+) -> io::Result<()> {
+    writeln!(out, "x,y,z,value")?;
+    for (x, y, z, value) in points.iter() {
+        writeln!(out, "{},{},{},{}", x, y, z, format_value(value))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{ChunkLocation, Dimension};
+
+/// Decides which chunks are worked on first by the loader and the job queues. Games plug
+/// in their own to tune streaming without touching the schedulers
+pub trait Priority: Send + Sync {
+    /// How urgently the chunk should be worked on, higher first
+    fn score(&self, chunk: ChunkLocation) -> f32;
+}
+
+impl<F: Fn(ChunkLocation) -> f32 + Send + Sync> Priority for F {
+    fn score(&self, chunk: ChunkLocation) -> f32 {
+        self(chunk)
+    }
+}
+
+fn chunk_center(chunk: ChunkLocation) -> [f32; 3] {
+    [
+        chunk.x as f32 + 0.5,
+        chunk.y as f32 + 0.5,
+        chunk.z as f32 + 0.5,
+    ]
+}
+
+/// Nearer chunks first. Positions are in chunks
+#[derive(Copy, Clone, Debug)]
+pub struct DistancePriority {
+    pub focus: [f32; 3],
+    /// how much a chunk of distance costs
+    pub weight: f32,
+}
+
+impl Priority for DistancePriority {
+    fn score(&self, chunk: ChunkLocation) -> f32 {
+        let center = chunk_center(chunk);
+        let distance = (0..3)
+            .map(|axis| (center[axis] - self.focus[axis]).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        -self.weight * distance
+    }
+}
+
+/// A bonus for chunks in front of the camera. Positions are in chunks
+#[derive(Copy, Clone, Debug)]
+pub struct FrustumPriority {
+    pub eye: [f32; 3],
+    /// the way the camera looks, needn't be normalized
+    pub forward: [f32; 3],
+    /// half the opening angle of the view cone, in radians
+    pub half_angle: f32,
+    pub bonus: f32,
+}
+
+impl Priority for FrustumPriority {
+    fn score(&self, chunk: ChunkLocation) -> f32 {
+        let center = chunk_center(chunk);
+        let to_chunk = [
+            center[0] - self.eye[0],
+            center[1] - self.eye[1],
+            center[2] - self.eye[2],
+        ];
+        let length = |v: [f32; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        let (distance, forward_length) = (length(to_chunk), length(self.forward));
+        // the chunk the camera is in is always visible
+        if distance < 1.0 {
+            return self.bonus;
+        }
+        if forward_length == 0.0 {
+            return 0.0;
+        }
+        let cos = (to_chunk[0] * self.forward[0]
+            + to_chunk[1] * self.forward[1]
+            + to_chunk[2] * self.forward[2])
+            / (distance * forward_length);
+        if cos >= self.half_angle.cos() {
+            self.bonus
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A bonus for chunks edited a short while ago, fading out over the window, so the
+/// results of edits show up quickly
+pub struct RecentlyEditedPriority {
+    edited: Mutex<HashMap<ChunkLocation, Instant>>,
+    pub window: Duration,
+    pub bonus: f32,
+}
+
+impl RecentlyEditedPriority {
+    pub fn new(window: Duration, bonus: f32) -> RecentlyEditedPriority {
+        RecentlyEditedPriority {
+            edited: Mutex::new(HashMap::new()),
+            window,
+            bonus,
+        }
+    }
+
+    pub fn mark_edited(&self, chunk: ChunkLocation) {
+        let mut edited = self.edited.lock().unwrap();
+        let now = Instant::now();
+        edited.retain(|_, at| now.duration_since(*at) < self.window);
+        edited.insert(chunk, now);
+    }
+}
+
+impl Priority for RecentlyEditedPriority {
+    fn score(&self, chunk: ChunkLocation) -> f32 {
+        match self.edited.lock().unwrap().get(&chunk) {
+            Some(at) => {
+                let age = at.elapsed().as_secs_f32() / self.window.as_secs_f32();
+                self.bonus * (1.0 - age).max(0.0)
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// The sum of several heuristics
+#[derive(Clone, Default)]
+pub struct CombinedPriority {
+    parts: Vec<Arc<dyn Priority>>,
+}
+
+impl CombinedPriority {
+    pub fn new() -> CombinedPriority {
+        CombinedPriority::default()
+    }
+
+    pub fn with(mut self, part: Arc<dyn Priority>) -> CombinedPriority {
+        self.parts.push(part);
+        self
+    }
+}
+
+impl Priority for CombinedPriority {
+    fn score(&self, chunk: ChunkLocation) -> f32 {
+        self.parts.iter().map(|part| part.score(chunk)).sum()
+    }
+}
+
+/// Sorts chunks most urgent first. Chunks scoring the same keep their order
+pub fn sort_by_priority(chunks: &mut [ChunkLocation], priority: &dyn Priority) {
+    let mut scored: Vec<(f32, ChunkLocation)> = chunks
+        .iter()
+        .map(|chunk| (priority.score(*chunk), *chunk))
+        .collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    for (slot, (_, chunk)) in chunks.iter_mut().zip(scored) {
+        *slot = chunk;
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Orders the chunks the loader works on, instead of nearest to an anchor first
+    pub fn set_chunk_priority(&mut self, priority: Arc<dyn Priority>) {
+        self.chunk_priority = Some(priority);
+    }
+
+    pub fn clear_chunk_priority(&mut self) {
+        self.chunk_priority = None;
+    }
+}
@@ -0,0 +1,89 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::{GlobalLocation, VoxelRead};
+
+/// A read only section of the world whose voxels are computed on demand from a closure
+/// instead of being stored, so algorithms can run on procedural content without
+/// materializing it first
+pub struct ProceduralVolume<T, F>
+where
+    F: Fn(GlobalLocation) -> T,
+{
+    start_location: GlobalLocation,
+    end_location: GlobalLocation,
+    /// computes the value of the voxel at a location
+    generator: F,
+    /// voxels that have already been computed, if memoization is enabled
+    cache: Option<RefCell<HashMap<GlobalLocation, T>>>,
+}
+
+impl<T: Copy + Default, F: Fn(GlobalLocation) -> T> ProceduralVolume<T, F> {
+    /// a volume that calls the generator on every read
+    pub fn new(
+        start_location: GlobalLocation,
+        end_location: GlobalLocation,
+        generator: F,
+    ) -> ProceduralVolume<T, F> {
+        ProceduralVolume {
+            start_location,
+            end_location,
+            generator,
+            cache: None,
+        }
+    }
+
+    /// a volume that remembers every voxel it has computed, for expensive generators
+    pub fn memoized(
+        start_location: GlobalLocation,
+        end_location: GlobalLocation,
+        generator: F,
+    ) -> ProceduralVolume<T, F> {
+        ProceduralVolume {
+            start_location,
+            end_location,
+            generator,
+            cache: Some(RefCell::new(HashMap::new())),
+        }
+    }
+
+    pub fn within_bounds(&self, location: GlobalLocation) -> bool {
+        location.x >= self.start_location.x
+            && location.y >= self.start_location.y
+            && location.z >= self.start_location.z
+            && location.x < self.end_location.x
+            && location.y < self.end_location.y
+            && location.z < self.end_location.z
+    }
+
+    pub fn get(&self, location: GlobalLocation) -> T {
+        match &self.cache {
+            Some(cache) => *cache
+                .borrow_mut()
+                .entry(location)
+                .or_insert_with(|| (self.generator)(location)),
+            None => (self.generator)(location),
+        }
+    }
+
+    /// Forgets all memoized voxels, so they are recomputed on the next read
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.borrow_mut().clear();
+        }
+    }
+}
+
+impl<T: Copy + Default, F: Fn(GlobalLocation) -> T> VoxelRead<T> for ProceduralVolume<T, F> {
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation) {
+        (self.start_location, self.end_location)
+    }
+
+    fn within_bounds(&self, location: GlobalLocation) -> bool {
+        ProceduralVolume::within_bounds(self, location)
+    }
+
+    fn get(&self, location: GlobalLocation) -> T {
+        ProceduralVolume::get(self, location)
+    }
+}
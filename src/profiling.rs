@@ -0,0 +1,164 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{ChunkLocation, Dimension};
+
+/// Counts how often each chunk is accessed over a window of time
+#[derive(Clone)]
+pub struct AccessProfile {
+    counts: HashMap<ChunkLocation, u64>,
+    window_start: Instant,
+}
+
+/// The most and least accessed chunks over a window of time
+#[derive(Clone, Debug)]
+pub struct AccessReport {
+    /// how long the accesses were counted for
+    pub window: Duration,
+    /// the most accessed chunks, most accessed first
+    pub hottest: Vec<(ChunkLocation, u64)>,
+    /// the least accessed loaded chunks, least accessed first
+    pub coldest: Vec<(ChunkLocation, u64)>,
+}
+
+/// The kinds of chunk operations timed by the slow operation log
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkOp {
+    Load,
+    Generate,
+    Save,
+}
+
+/// A chunk operation that took longer than the slow operation threshold
+#[derive(Clone, Debug)]
+pub struct SlowOp {
+    pub op: ChunkOp,
+    pub location: ChunkLocation,
+    pub duration: Duration,
+    /// when the operation started
+    pub started: Instant,
+}
+
+/// The most recent slow chunk operations
+#[derive(Clone)]
+pub struct SlowOpLog {
+    threshold: Duration,
+    /// how many operations are kept, the oldest are dropped first
+    capacity: usize,
+    ops: VecDeque<SlowOp>,
+}
+
+impl AccessProfile {
+    fn new() -> AccessProfile {
+        AccessProfile {
+            counts: HashMap::new(),
+            window_start: Instant::now(),
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Starts counting chunk accesses in a fresh window. Only accesses through the Dimension
+    /// methods are counted, not reads through VoxelRead
+    pub fn enable_access_profiling(&mut self) {
+        self.access_profile = Some(AccessProfile::new());
+    }
+
+    pub fn disable_access_profiling(&mut self) {
+        self.access_profile = None;
+    }
+
+    /// Throws away the counts so far and starts a new window
+    pub fn reset_access_window(&mut self) {
+        if self.access_profile.is_some() {
+            self.enable_access_profiling();
+        }
+    }
+
+    /// Counts an access to a chunk, if profiling is enabled
+    pub fn record_access(&mut self, location: ChunkLocation) {
+        if let Some(profile) = &mut self.access_profile {
+            *profile.counts.entry(location).or_insert(0) += 1;
+        }
+    }
+
+    /// The count hottest and coldest chunks of the current window, or None if profiling is
+    /// disabled. Loaded chunks that were never accessed count as the coldest
+    pub fn access_report(&self, count: usize) -> Option<AccessReport> {
+        let profile = self.access_profile.as_ref()?;
+        let mut counts: Vec<(ChunkLocation, u64)> =
+            profile.counts.iter().map(|(l, c)| (*l, *c)).collect();
+        for location in self.loaded_chunks.keys() {
+            if !profile.counts.contains_key(location) {
+                counts.push((*location, 0));
+            }
+        }
+        // ties are ordered by location so reports are reproducible
+        counts.sort_by_key(|(location, count)| (*count, location.z, location.y, location.x));
+
+        let coldest = counts
+            .iter()
+            .filter(|(location, _)| self.chunk_loaded(*location))
+            .take(count)
+            .copied()
+            .collect();
+        let hottest = counts.iter().rev().take(count).copied().collect();
+        Some(AccessReport {
+            window: profile.window_start.elapsed(),
+            hottest,
+            coldest,
+        })
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Starts recording chunk loads, generations and saves that take longer than threshold,
+    /// keeping the latest capacity of them, to find what makes the world hitch
+    pub fn enable_slow_op_log(&mut self, threshold: Duration, capacity: usize) {
+        self.slow_op_log = Some(SlowOpLog {
+            threshold,
+            capacity,
+            ops: VecDeque::new(),
+        });
+    }
+
+    pub fn disable_slow_op_log(&mut self) {
+        self.slow_op_log = None;
+    }
+
+    /// The slow operations recorded so far, oldest first
+    pub fn slow_ops(&self) -> Vec<SlowOp> {
+        self.slow_op_log
+            .as_ref()
+            .map_or_else(Vec::new, |log| log.ops.iter().cloned().collect())
+    }
+
+    pub fn clear_slow_ops(&mut self) {
+        if let Some(log) = &mut self.slow_op_log {
+            log.ops.clear();
+        }
+    }
+
+    /// Records an operation that started at started and just finished, if it was slow
+    pub fn record_op(&mut self, op: ChunkOp, location: ChunkLocation, started: Instant) {
+        let log = match &mut self.slow_op_log {
+            Some(log) => log,
+            None => return,
+        };
+        let duration = started.elapsed();
+        if duration < log.threshold || log.capacity == 0 {
+            return;
+        }
+        if log.ops.len() == log.capacity {
+            log.ops.pop_front();
+        }
+        log.ops.push_back(SlowOp {
+            op,
+            location,
+            duration,
+            started,
+        });
+    }
+}
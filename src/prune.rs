@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::anchors::Anchor;
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::tombstones::now;
+use super::{ChunkLocation, Dimension};
+
+/// What a chunk has been through since it was first defined
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChunkHistory {
+    /// when the chunk was defined, in seconds since the unix epoch
+    pub first_seen: u64,
+    /// if an anchor ever covered the chunk
+    pub visited: bool,
+    /// if a voxel of the chunk was ever written, or the chunk wasn't generated
+    pub edited: bool,
+}
+
+/// The history of every defined chunk, for pruning the chunks nobody ever went near
+#[derive(Clone, Default)]
+pub struct ChunkHistories {
+    chunks: HashMap<ChunkLocation, ChunkHistory>,
+}
+
+impl ChunkHistories {
+    pub fn new() -> ChunkHistories {
+        ChunkHistories::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn get(&self, location: ChunkLocation) -> Option<&ChunkHistory> {
+        self.chunks.get(&location)
+    }
+
+    /// Starts the history of a chunk that was just defined, unless it has one
+    fn record(
+        &mut self,
+        location: ChunkLocation,
+        visited: bool,
+        edited: bool,
+    ) -> &mut ChunkHistory {
+        self.chunks.entry(location).or_insert_with(|| ChunkHistory {
+            first_seen: now(),
+            visited,
+            edited,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut chunks: Vec<(&ChunkLocation, &ChunkHistory)> = self.chunks.iter().collect();
+        chunks.sort_by_key(|(location, _)| (location.z, location.y, location.x));
+        out.write_u32::<LittleEndian>(chunks.len() as u32)?;
+        for (location, history) in chunks {
+            write_point(out, *location)?;
+            out.write_u64::<LittleEndian>(history.first_seen)?;
+            out.write_u8(history.visited as u8 | (history.edited as u8) << 1)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<ChunkHistories> {
+        let mut histories = ChunkHistories::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let location = read_point(input)?;
+            let first_seen = input.read_u64::<LittleEndian>()?;
+            let flags = input.read_u8()?;
+            histories.chunks.insert(
+                location,
+                ChunkHistory {
+                    first_seen,
+                    visited: flags & 1 != 0,
+                    edited: flags & 2 != 0,
+                },
+            );
+        }
+        Ok(histories)
+    }
+}
+
+/// Which chunks prune removes
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PrunePolicy {
+    /// chunks defined more recently than this are kept, whatever happened to them
+    pub min_age: Duration,
+    /// only reports what would be removed, without removing anything
+    pub dry_run: bool,
+}
+
+impl PrunePolicy {
+    /// Removes the untouched chunks older than min_age
+    pub fn new(min_age: Duration) -> PrunePolicy {
+        PrunePolicy {
+            min_age,
+            dry_run: false,
+        }
+    }
+
+    /// Reports the untouched chunks older than min_age, without removing them
+    pub fn dry_run(min_age: Duration) -> PrunePolicy {
+        PrunePolicy {
+            min_age,
+            dry_run: true,
+        }
+    }
+}
+
+/// What prune removed, or would have with a dry run, and why the other chunks were kept
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct PruneReport {
+    pub removed: Vec<ChunkLocation>,
+    pub kept_edited: usize,
+    pub kept_visited: usize,
+    pub kept_young: usize,
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The history of a defined chunk, None if it was defined before histories were kept
+    pub fn chunk_history(&self, location: ChunkLocation) -> Option<&ChunkHistory> {
+        self.histories.get(location)
+    }
+
+    /// Starts the history of a chunk that was just generated, and so can be generated again
+    pub fn chunk_created(&mut self, location: ChunkLocation) {
+        let visited = self.anchors.covers(location);
+        self.histories.record(location, visited, false);
+    }
+
+    /// Notes that a chunk was written to, or put in place whole
+    pub fn chunk_edited(&mut self, location: ChunkLocation) {
+        // called on every write, so chunks with a history skip the anchor check
+        if let Some(history) = self.histories.chunks.get_mut(&location) {
+            history.edited = true;
+            return;
+        }
+        let visited = self.anchors.covers(location);
+        self.histories.record(location, visited, true).edited = true;
+    }
+
+    /// Notes that a chunk was loaded from disk. Chunks saved before histories were kept
+    /// count as edited, so pruning never takes them
+    pub fn chunk_loaded_from_disk(&mut self, location: ChunkLocation) {
+        let visited = self.anchors.covers(location);
+        self.histories.record(location, visited, true);
+    }
+
+    /// Notes that an anchor covers the chunks around it now
+    pub fn chunks_visited(&mut self, anchor: &Anchor) {
+        for (location, history) in self.histories.chunks.iter_mut() {
+            if anchor.covers(*location) {
+                history.visited = true;
+            }
+        }
+    }
+
+    /// Removes the generated chunks no anchor ever covered and nothing ever wrote to, once
+    /// they are older than the policy's min_age. They go through remove_chunk, so they can
+    /// be restored until their tombstones expire, and otherwise come back as they were
+    /// generated when next needed. Chunks without a history are kept. With a dry run, only
+    /// reports which chunks would go
+    pub fn prune(&mut self, policy: PrunePolicy) -> Result<PruneReport> {
+        let now = now();
+        let mut report = PruneReport::default();
+        let mut chunks: Vec<ChunkLocation> = self.all_chunk_locations.iter().copied().collect();
+        chunks.sort_by_key(|location| (location.z, location.y, location.x));
+        for location in chunks {
+            let history = match self.histories.get(location) {
+                Some(history) => *history,
+                None => {
+                    report.kept_edited += 1;
+                    continue;
+                }
+            };
+            if history.edited {
+                report.kept_edited += 1;
+            } else if history.visited || self.anchors.covers(location) {
+                report.kept_visited += 1;
+            } else if now.saturating_sub(history.first_seen) < policy.min_age.as_secs() {
+                report.kept_young += 1;
+            } else {
+                report.removed.push(location);
+            }
+        }
+        if policy.dry_run {
+            return Ok(report);
+        }
+        for location in report.removed.iter().copied() {
+            self.remove_chunk(location)?;
+            self.histories.chunks.remove(&location);
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use super::codec::VoxelCodec;
+use super::edit::Edit;
+use super::error::{Error, Result};
+use super::occupancy::VoxelClass;
+use super::{Dimension, GlobalLocation};
+
+/// Identifies who an edit comes from, a player or a system of the game
+pub type SourceId = u32;
+
+/// How fast a source may edit, as a token bucket: burst edits at once, refilled at
+/// edits_per_second
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RateLimit {
+    pub edits_per_second: f64,
+    pub burst: u32,
+}
+
+/// What a source has been doing
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct SourceStats {
+    /// edits let through the rate limit
+    pub accepted: u64,
+    /// edits turned down by the rate limit
+    pub throttled: u64,
+    /// edits dropped because a later edit of the same batch wrote the same voxel
+    pub coalesced: u64,
+    /// batches applied
+    pub batches: u64,
+}
+
+/// The edits of one source waiting to be applied
+#[derive(Clone)]
+struct SourceState<T> {
+    tokens: f64,
+    refilled_at: Instant,
+    stats: SourceStats,
+    /// at most one edit per voxel, in the order they were first written
+    batch: Vec<Edit<T>>,
+    batch_index: HashMap<GlobalLocation, usize>,
+}
+
+/// Accounts for the edits of every source, throttling the ones that edit too fast and
+/// gathering the rest into batches
+#[derive(Clone)]
+pub struct EditLimiter<T> {
+    default_limit: Option<RateLimit>,
+    limits: HashMap<SourceId, Option<RateLimit>>,
+    sources: BTreeMap<SourceId, SourceState<T>>,
+    /// edits turned down by a call of apply_edit_batches that then failed, to be handed
+    /// back by the next call
+    denied: Vec<Edit<T>>,
+}
+
+impl<T> Default for EditLimiter<T> {
+    fn default() -> EditLimiter<T> {
+        EditLimiter {
+            default_limit: None,
+            limits: HashMap::new(),
+            sources: BTreeMap::new(),
+            denied: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy> EditLimiter<T> {
+    pub fn new() -> EditLimiter<T> {
+        EditLimiter::default()
+    }
+
+    fn limit_of(&self, source: SourceId) -> Option<RateLimit> {
+        self.limits
+            .get(&source)
+            .copied()
+            .unwrap_or(self.default_limit)
+    }
+
+    /// Takes a token from the source's bucket, returning false if it is empty
+    fn take_token(&mut self, source: SourceId) -> bool {
+        let limit = self.limit_of(source);
+        let now = Instant::now();
+        let state = self.sources.entry(source).or_insert_with(|| SourceState {
+            tokens: limit.map_or(0.0, |limit| limit.burst as f64),
+            refilled_at: now,
+            stats: SourceStats::default(),
+            batch: Vec::new(),
+            batch_index: HashMap::new(),
+        });
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * limit.edits_per_second).min(limit.burst as f64);
+        state.refilled_at = now;
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Limits how fast every source without its own limit may edit. None lifts the limit
+    pub fn set_edit_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.edit_limiter.default_limit = limit;
+    }
+
+    /// Gives a source its own limit, like a higher one for trusted systems. None exempts
+    /// the source from limiting altogether
+    pub fn set_source_rate_limit(&mut self, source: SourceId, limit: Option<RateLimit>) {
+        self.edit_limiter.limits.insert(source, limit);
+    }
+
+    /// Goes back to the default limit for a source
+    pub fn clear_source_rate_limit(&mut self, source: SourceId) {
+        self.edit_limiter.limits.remove(&source);
+    }
+
+    /// Queues an edit from a source into its batch, or fails with Error::RateLimited if
+    /// the source is editing too fast. A second edit of the same voxel in a batch replaces
+    /// the first
+    pub fn submit_edit(&mut self, source: SourceId, edit: Edit<T>) -> Result<()> {
+        let allowed = self.edit_limiter.take_token(source);
+        let state = self.edit_limiter.sources.get_mut(&source).unwrap();
+        if !allowed {
+            state.stats.throttled += 1;
+            return Err(Error::RateLimited { source_id: source });
+        }
+        state.stats.accepted += 1;
+        match state.batch_index.get(&edit.location) {
+            Some(index) => {
+                state.batch[*index] = edit;
+                state.stats.coalesced += 1;
+            }
+            None => {
+                state.batch_index.insert(edit.location, state.batch.len());
+                state.batch.push(edit);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the queued batch of every source, in order of source id, through
+    /// apply_edits. Returns the edits the claims or validator turned down. A batch that
+    /// fails stays queued along with the batches after it, and the edits turned down
+    /// before the failure are returned by the next call
+    pub fn apply_edit_batches(&mut self) -> Result<Vec<Edit<T>>> {
+        let mut denied = std::mem::take(&mut self.edit_limiter.denied);
+        let sources: Vec<SourceId> = self.edit_limiter.sources.keys().copied().collect();
+        for source in sources {
+            let batch = &self.edit_limiter.sources[&source].batch;
+            if batch.is_empty() {
+                continue;
+            }
+            match self.apply_edits(batch.clone()) {
+                Ok(turned_down) => denied.extend(turned_down),
+                Err(err) => {
+                    self.edit_limiter.denied = denied;
+                    return Err(err);
+                }
+            }
+            let state = self.edit_limiter.sources.get_mut(&source).unwrap();
+            state.batch.clear();
+            state.batch_index.clear();
+            state.stats.batches += 1;
+        }
+        Ok(denied)
+    }
+
+    /// What a source has been doing, None if it never submitted an edit
+    pub fn edit_stats(&self, source: SourceId) -> Option<SourceStats> {
+        self.edit_limiter
+            .sources
+            .get(&source)
+            .map(|state| state.stats)
+    }
+
+    /// The stats of every source that has submitted edits, by source id
+    pub fn all_edit_stats(&self) -> Vec<(SourceId, SourceStats)> {
+        self.edit_limiter
+            .sources
+            .iter()
+            .map(|(source, state)| (*source, state.stats))
+            .collect()
+    }
+}
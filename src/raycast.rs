@@ -0,0 +1,80 @@
+use super::faces::Face;
+use super::occupancy::VoxelClass;
+use super::{GlobalLocation, Point3D, VoxelRead};
+
+/// The first solid voxel a ray hit
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RayHit {
+    pub location: GlobalLocation,
+    /// the face the ray came in through, None if it started inside the voxel
+    pub face: Option<Face>,
+    /// how far along the ray the voxel was entered, in multiples of the direction's length
+    pub distance: f32,
+}
+
+/// The faces a ray crosses when it moves along each axis in the negative and positive
+/// direction
+const ENTRY_FACES: [[Face; 2]; 3] = [
+    [Face::East, Face::West],
+    [Face::North, Face::South],
+    [Face::Up, Face::Down],
+];
+
+/// Walks a ray voxel by voxel until it enters a solid voxel. The ray ends without a hit
+/// after max_distance, or at the first voxel the map can't read
+pub fn raycast<T: VoxelClass, M: VoxelRead<T> + ?Sized>(
+    map: &M,
+    origin: [f32; 3],
+    direction: [f32; 3],
+    max_distance: f32,
+) -> Option<RayHit> {
+    let mut cell = [0i64; 3];
+    let mut step = [0i64; 3];
+    let mut t_max = [f32::INFINITY; 3];
+    let mut t_delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        cell[axis] = origin[axis].floor() as i64;
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_max[axis] = ((cell[axis] + 1) as f32 - origin[axis]) / direction[axis];
+            t_delta[axis] = 1.0 / direction[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_max[axis] = (cell[axis] as f32 - origin[axis]) / direction[axis];
+            t_delta[axis] = -1.0 / direction[axis];
+        }
+    }
+
+    let mut face = None;
+    let mut distance = 0.0;
+    loop {
+        if cell
+            .iter()
+            .any(|x| *x < i32::MIN as i64 || *x > i32::MAX as i64)
+        {
+            return None;
+        }
+        let location = Point3D::new(cell[0] as i32, cell[1] as i32, cell[2] as i32);
+        if !map.within_bounds(location) {
+            return None;
+        }
+        if map.get(location).is_solid() {
+            return Some(RayHit {
+                location,
+                face,
+                distance,
+            });
+        }
+        // advance along whichever axis reaches its next voxel boundary first
+        let axis = (0..3)
+            .min_by(|a, b| t_max[*a].partial_cmp(&t_max[*b]).unwrap())
+            .unwrap();
+        if !t_max[axis].is_finite() || t_max[axis] > max_distance {
+            return None;
+        }
+        distance = t_max[axis];
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        face = Some(ENTRY_FACES[axis][(step[axis] > 0) as usize]);
+    }
+}
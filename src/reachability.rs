@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use super::chunk_local::boundary_voxels;
+use super::faces::Face;
+use super::traversal::{Agent, Capabilities};
+use super::{
+    is_traversable, Chunk, ChunkLocation, Dimension, GlobalLocation, Point3D, Voxel, VoxelLocation,
+    CHUNK_VOLUME, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE,
+};
+
+/// The connected pieces of traversable space inside one chunk
+struct ChunkLabels {
+    /// the stamps of the chunk and of the chunk underneath it, whose top layer decides
+    /// whether the bottom layer has a floor
+    key: (u64, Option<u64>),
+    /// the component of each voxel, 0 for voxels that can't be traversed
+    labels: Vec<u32>,
+    components: u32,
+}
+
+/// The components of every loaded chunk for one set of agent abilities, joined across chunk
+/// borders by a union-find
+#[derive(Default)]
+struct ComponentGraph {
+    chunks: HashMap<ChunkLocation, ChunkLabels>,
+    /// where each chunk's components start in parent
+    offsets: HashMap<ChunkLocation, usize>,
+    parent: Vec<usize>,
+}
+
+impl ComponentGraph {
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parent[node] != node {
+            self.parent[node] = self.parent[self.parent[node]];
+            node = self.parent[node];
+        }
+        node
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    /// The union-find node of the component containing location, if it is traversable
+    fn node(&self, location: GlobalLocation) -> Option<usize> {
+        let chunk = Dimension::<Voxel>::get_chunk_location(location);
+        let label = self.chunks.get(&chunk)?.labels
+            [index(Dimension::<Voxel>::get_voxel_location(location))];
+        if label == 0 {
+            None
+        } else {
+            Some(self.offsets[&chunk] + label as usize - 1)
+        }
+    }
+}
+
+/// Where a voxel is stored in its chunk's label array
+fn index(location: VoxelLocation) -> usize {
+    Chunk::<Voxel>::get_index(location)
+}
+
+/// Answers "can an agent get from a to b" from cached connected component labels instead of
+/// searching for a path. Labels are recomputed only for chunks whose stamps changed. The
+/// cache assumes the traversal rules don't change, clear it if they do
+#[derive(Default)]
+pub struct ReachabilityCache {
+    graphs: HashMap<Capabilities, ComponentGraph>,
+}
+
+impl ReachabilityCache {
+    pub fn new() -> ReachabilityCache {
+        ReachabilityCache::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.graphs.clear();
+    }
+
+    /// If an agent can walk from a to b through the loaded chunks
+    pub fn can_reach(
+        &mut self,
+        dimension: &Dimension<Voxel>,
+        a: GlobalLocation,
+        b: GlobalLocation,
+        agent: &Agent,
+    ) -> bool {
+        // agents going by no registry in particular go by the world's
+        let agent = &Agent {
+            registry: agent.registry.or(dimension.registry()),
+            ..*agent
+        };
+        let graph = self.graphs.entry(agent.capabilities).or_default();
+        refresh(graph, dimension, agent);
+        match (graph.node(a), graph.node(b)) {
+            (Some(a), Some(b)) => graph.find(a) == graph.find(b),
+            _ => false,
+        }
+    }
+}
+
+/// Brings the labels up to date with the loaded chunks, rebuilding the border unions if
+/// anything changed
+fn refresh(graph: &mut ComponentGraph, dimension: &Dimension<Voxel>, agent: &Agent) {
+    let mut changed = false;
+    let before = graph.chunks.len();
+    graph
+        .chunks
+        .retain(|location, _| dimension.chunk_loaded(*location));
+    changed |= graph.chunks.len() != before;
+
+    for (location, stamp) in dimension.chunk_stamps.iter() {
+        let below = dimension.chunk_stamp(*location - Point3D::new(0, 0, 1));
+        let key = (*stamp, below);
+        if graph
+            .chunks
+            .get(location)
+            .is_some_and(|labels| labels.key == key)
+        {
+            continue;
+        }
+        let (labels, components) = label_chunk(dimension, *location, agent);
+        graph.chunks.insert(
+            *location,
+            ChunkLabels {
+                key,
+                labels,
+                components,
+            },
+        );
+        changed = true;
+    }
+    if !changed && !graph.parent.is_empty() {
+        return;
+    }
+
+    // give every component of every chunk a node, in a fixed order
+    let mut locations: Vec<ChunkLocation> = graph.chunks.keys().copied().collect();
+    locations.sort_by_key(|location| (location.z, location.y, location.x));
+    graph.offsets.clear();
+    let mut total = 0;
+    for location in locations.iter() {
+        graph.offsets.insert(*location, total);
+        total += graph.chunks[location].components as usize;
+    }
+    graph.parent = (0..total).collect();
+
+    // join components that touch across the positive faces of each chunk
+    for location in locations.iter() {
+        let origin = Dimension::<Voxel>::get_chunk_bounds(*location).start;
+        for face in [Face::East, Face::North, Face::Up] {
+            for voxel in boundary_voxels(face) {
+                let inside = origin + voxel;
+                let outside = match face.neighbor(inside) {
+                    Some(outside) => outside,
+                    None => continue,
+                };
+                if let (Some(a), Some(b)) = (graph.node(inside), graph.node(outside)) {
+                    graph.union(a, b);
+                }
+            }
+        }
+    }
+}
+
+/// Flood fills the traversable voxels of a chunk into numbered components
+fn label_chunk(
+    dimension: &Dimension<Voxel>,
+    location: ChunkLocation,
+    agent: &Agent,
+) -> (Vec<u32>, u32) {
+    let origin = Dimension::<Voxel>::get_chunk_bounds(location).start;
+    let traversable = |voxel: VoxelLocation| is_traversable(dimension, origin + voxel, agent);
+
+    let mut labels = vec![0; CHUNK_VOLUME];
+    let mut components = 0;
+    let mut queue = VecDeque::new();
+    for start in VoxelLocation::all() {
+        if labels[index(start)] != 0 || !traversable(start) {
+            continue;
+        }
+        components += 1;
+        labels[index(start)] = components;
+        queue.push_back(start);
+        while let Some(voxel) = queue.pop_front() {
+            for neighbor in neighbors(voxel) {
+                if labels[index(neighbor)] == 0 && traversable(neighbor) {
+                    labels[index(neighbor)] = components;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+    (labels, components)
+}
+
+/// The face neighbors of a voxel that are inside the same chunk
+fn neighbors(voxel: VoxelLocation) -> Vec<VoxelLocation> {
+    let mut neighbors = Vec::with_capacity(6);
+    if voxel.x > 0 {
+        neighbors.push(voxel - Point3D::new(1, 0, 0));
+    }
+    if voxel.y > 0 {
+        neighbors.push(voxel - Point3D::new(0, 1, 0));
+    }
+    if voxel.z > 0 {
+        neighbors.push(voxel - Point3D::new(0, 0, 1));
+    }
+    if voxel.x + 1 < CHUNK_X_SIZE as i32 {
+        neighbors.push(voxel + Point3D::new(1, 0, 0));
+    }
+    if voxel.y + 1 < CHUNK_Y_SIZE as i32 {
+        neighbors.push(voxel + Point3D::new(0, 1, 0));
+    }
+    if voxel.z + 1 < CHUNK_Z_SIZE as i32 {
+        neighbors.push(voxel + Point3D::new(0, 0, 1));
+    }
+    neighbors
+}
@@ -0,0 +1,773 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use twox_hash::XxHash64;
+
+use super::codec::VoxelCodec;
+use super::compression::hash_dictionary;
+#[cfg(feature = "encryption")]
+use super::encryption::Keyring;
+use super::error::{ErrorContext, Operation, Result, ResultExt};
+use super::event_log::{ChunkBase, ChunkLog, PersistenceMode};
+use super::format::{can_read, FORMAT_VERSION};
+use super::occupancy::VoxelClass;
+use super::profiling::ChunkOp;
+use super::{Chunk, ChunkLocation, Dimension};
+
+/// How many chunks a region file holds along each axis
+pub const REGION_SIZE: i32 = 8;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// Marks the start of a region file
+const REGION_MAGIC: &[u8; 4] = b"RCRG";
+const REGION_VERSION: u32 = FORMAT_VERSION;
+
+/// Each index entry is the offset (u64), length (u32) and hash (u64) of a chunk record
+const INDEX_ENTRY_SIZE: u64 = 20;
+const HEADER_SIZE: u64 = 8 + INDEX_ENTRY_SIZE * REGION_CHUNKS as u64;
+
+/// Region files are rewritten without their dead records once these make up more than half
+/// of the file and at least this many bytes
+const COMPACTION_MIN_WASTE: u64 = 1 << 20;
+
+/// What a chunk record holds
+const SNAPSHOT_RECORD: u8 = 0;
+const LOG_RECORD: u8 = 1;
+/// Set on the record kind when the rest of the record is encrypted
+const ENCRYPTED_RECORD: u8 = 0x80;
+/// The kind and dictionary hash every record starts with
+const RECORD_HEADER_SIZE: usize = 9;
+
+/// Where a chunk's record lies in its region file
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+struct IndexEntry {
+    /// 0 if the chunk isn't stored
+    offset: u64,
+    length: u32,
+    /// XxHash64 of the record, to catch corruption
+    hash: u64,
+}
+
+/// A file holding up to REGION_SIZE^3 chunks. It starts with an index of where every
+/// chunk's record lies, so single chunks can be read without scanning the file. Records
+/// are appended, and the index entry is pointed at the new record, so a crash while writing
+/// leaves the old record in place
+pub struct RegionFile {
+    file: File,
+    /// the format version the file is stamped with
+    version: u32,
+    index: Vec<IndexEntry>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// What an encrypted record's payload is authenticated with besides itself
+#[cfg(feature = "encryption")]
+fn associated_data(location: ChunkLocation, header: &[u8]) -> Vec<u8> {
+    let mut data = header.to_vec();
+    for axis in [location.x, location.y, location.z].iter() {
+        data.write_i32::<LittleEndian>(*axis).unwrap();
+    }
+    data
+}
+
+impl RegionFile {
+    /// Opens a region file, creating it empty if it doesn't exist yet
+    pub fn open(path: &Path) -> io::Result<RegionFile> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut index = vec![IndexEntry::default(); REGION_CHUNKS];
+        let mut version = REGION_VERSION;
+        if file.metadata()?.len() == 0 {
+            file.write_all(REGION_MAGIC)?;
+            file.write_u32::<LittleEndian>(REGION_VERSION)?;
+            file.write_all(&vec![0; (HEADER_SIZE - 8) as usize])?;
+            file.flush()?;
+        } else {
+            let mut header = vec![0; HEADER_SIZE as usize];
+            file.read_exact(&mut header)?;
+            let mut header = &header[..];
+            let mut magic = [0; 4];
+            header.read_exact(&mut magic)?;
+            version = header.read_u32::<LittleEndian>()?;
+            if &magic != REGION_MAGIC || !can_read(version) {
+                return Err(invalid("not a region file"));
+            }
+            for entry in index.iter_mut() {
+                entry.offset = header.read_u64::<LittleEndian>()?;
+                entry.length = header.read_u32::<LittleEndian>()?;
+                entry.hash = header.read_u64::<LittleEndian>()?;
+            }
+            let len = file.metadata()?.len();
+            for entry in index.iter().filter(|entry| entry.offset != 0) {
+                let end = entry.offset.checked_add(entry.length as u64);
+                if entry.offset < HEADER_SIZE || end.is_none_or(|end| end > len) {
+                    return Err(invalid("region index points past the end of the file"));
+                }
+            }
+        }
+        Ok(RegionFile {
+            file,
+            version,
+            index,
+        })
+    }
+
+    /// The name of the region file holding a chunk
+    pub fn file_name(location: ChunkLocation) -> String {
+        format!(
+            "r.{}.{}.{}.region",
+            location.x.div_euclid(REGION_SIZE),
+            location.y.div_euclid(REGION_SIZE),
+            location.z.div_euclid(REGION_SIZE)
+        )
+    }
+
+    /// The slot of a chunk within its region
+    pub fn slot(location: ChunkLocation) -> usize {
+        (location.z.rem_euclid(REGION_SIZE) * REGION_SIZE * REGION_SIZE
+            + location.y.rem_euclid(REGION_SIZE) * REGION_SIZE
+            + location.x.rem_euclid(REGION_SIZE)) as usize
+    }
+
+    /// Where the record of a slot starts in the file, 0 if the slot is empty
+    pub fn offset(&self, slot: usize) -> u64 {
+        self.index[slot].offset
+    }
+
+    /// The slots holding a chunk
+    pub fn slots(&self) -> Vec<usize> {
+        (0..REGION_CHUNKS)
+            .filter(|slot| self.index[*slot].offset != 0)
+            .collect()
+    }
+
+    /// The record of the chunk in a slot, None if the slot is empty
+    pub fn read(&mut self, slot: usize) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.index[slot];
+        if entry.offset == 0 {
+            return Ok(None);
+        }
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let mut record = vec![0; entry.length as usize];
+        self.file.read_exact(&mut record)?;
+        if XxHash64::oneshot(0, &record) != entry.hash {
+            return Err(invalid("chunk record is corrupted"));
+        }
+        Ok(Some(record))
+    }
+
+    /// Stores a chunk record in a slot, replacing whatever was there
+    pub fn write(&mut self, slot: usize, record: &[u8]) -> io::Result<()> {
+        self.stamp_version()?;
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(record)?;
+        self.set_entry(
+            slot,
+            IndexEntry {
+                offset,
+                length: record.len() as u32,
+                hash: XxHash64::oneshot(0, record),
+            },
+        )
+    }
+
+    /// Stores many records at once, in a single write followed by a single update of the
+    /// index
+    pub fn write_batch(&mut self, records: &[(usize, Vec<u8>)]) -> io::Result<()> {
+        self.stamp_version()?;
+        let mut offset = self.file.seek(SeekFrom::End(0))?;
+        let mut bytes = Vec::with_capacity(records.iter().map(|(_, record)| record.len()).sum());
+        let mut entries = Vec::with_capacity(records.len());
+        for (slot, record) in records.iter() {
+            bytes.extend_from_slice(record);
+            entries.push((
+                *slot,
+                IndexEntry {
+                    offset,
+                    length: record.len() as u32,
+                    hash: XxHash64::oneshot(0, record),
+                },
+            ));
+            offset += record.len() as u64;
+        }
+        self.file.write_all(&bytes)?;
+        for (slot, entry) in entries {
+            self.index[slot] = entry;
+        }
+        let mut index = Vec::with_capacity(HEADER_SIZE as usize - 8);
+        for entry in self.index.iter() {
+            index.write_u64::<LittleEndian>(entry.offset)?;
+            index.write_u32::<LittleEndian>(entry.length)?;
+            index.write_u64::<LittleEndian>(entry.hash)?;
+        }
+        self.file.seek(SeekFrom::Start(8))?;
+        self.file.write_all(&index)?;
+        self.file.flush()
+    }
+
+    /// Empties a slot
+    pub fn remove(&mut self, slot: usize) -> io::Result<()> {
+        self.set_entry(slot, IndexEntry::default())
+    }
+
+    /// Stamps a file saved by an older build with the current version before records of
+    /// the current version are written into it, so older builds refuse the file as a whole
+    /// rather than failing on single records
+    fn stamp_version(&mut self) -> io::Result<()> {
+        if self.version != REGION_VERSION {
+            self.file.seek(SeekFrom::Start(4))?;
+            self.file.write_u32::<LittleEndian>(REGION_VERSION)?;
+            self.version = REGION_VERSION;
+        }
+        Ok(())
+    }
+
+    fn set_entry(&mut self, slot: usize, entry: IndexEntry) -> io::Result<()> {
+        self.index[slot] = entry;
+        self.file
+            .seek(SeekFrom::Start(8 + slot as u64 * INDEX_ENTRY_SIZE))?;
+        self.file.write_u64::<LittleEndian>(entry.offset)?;
+        self.file.write_u32::<LittleEndian>(entry.length)?;
+        self.file.write_u64::<LittleEndian>(entry.hash)?;
+        self.file.flush()
+    }
+
+    /// How many bytes of the file belong to records that were replaced or removed
+    pub fn wasted_bytes(&self) -> io::Result<u64> {
+        let live: u64 = self.index.iter().map(|entry| entry.length as u64).sum();
+        self.file
+            .metadata()?
+            .len()
+            .checked_sub(HEADER_SIZE + live)
+            .ok_or_else(|| invalid("region records overlap"))
+    }
+
+    /// If the records are laid out the one way compact lays them out: in slot order with
+    /// nothing dead between them
+    pub fn is_canonical(&self) -> io::Result<bool> {
+        let mut end = HEADER_SIZE;
+        for slot in self.slots() {
+            let entry = self.index[slot];
+            if entry.offset != end {
+                return Ok(false);
+            }
+            end += entry.length as u64;
+        }
+        Ok(end == self.file.metadata()?.len())
+    }
+
+    /// Rewrites the file at path with only its live records
+    pub fn compact(&mut self, path: &Path) -> io::Result<()> {
+        let partial = path.with_extension("partial");
+        {
+            let _ = fs::remove_file(&partial);
+            let mut compacted = RegionFile::open(&partial)?;
+            for slot in self.slots() {
+                if let Some(record) = self.read(slot)? {
+                    compacted.write(slot, &record)?;
+                }
+            }
+            compacted.file.sync_all()?;
+        }
+        fs::rename(&partial, path)?;
+        *self = RegionFile::open(path)?;
+        Ok(())
+    }
+}
+
+/// A region file and the records of the chunks to write into it
+type RegionBatch = (PathBuf, Vec<(ChunkLocation, Vec<u8>)>);
+
+/// Writes each region file's records on up to threads threads at once, returning how
+/// writing each file went
+fn write_region_batches(batches: &[RegionBatch], threads: usize) -> Vec<io::Result<()>> {
+    let write = |path: &Path, records: &[(ChunkLocation, Vec<u8>)]| -> io::Result<()> {
+        let records: Vec<(usize, Vec<u8>)> = records
+            .iter()
+            .map(|(location, record)| (RegionFile::slot(*location), record.clone()))
+            .collect();
+        fs::create_dir_all(path.parent().unwrap())?;
+        RegionFile::open(path)?.write_batch(&records)
+    };
+    let next = Mutex::new(0);
+    let results: Mutex<Vec<Option<io::Result<()>>>> =
+        Mutex::new(batches.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..threads.min(batches.len()) {
+            scope.spawn(|| loop {
+                let i = {
+                    let mut next = next.lock().unwrap();
+                    *next += 1;
+                    *next - 1
+                };
+                let (path, records) = match batches.get(i) {
+                    Some(batch) => batch,
+                    None => break,
+                };
+                let result = write(path, records);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap())
+        .collect()
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Chunk<T> {
+    /// Reads a chunk written by write_to
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Chunk<T>> {
+        let mut payload = vec![0; input.read_u32::<LittleEndian>()? as usize];
+        input.read_exact(&mut payload)?;
+        Chunk::decode_payload(&payload)
+    }
+
+    /// Writes the chunk uncompressed, prefixed by its length
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let payload = self.encode_payload()?;
+        out.write_u32::<LittleEndian>(payload.len() as u32)?;
+        out.write_all(&payload)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The folder region files are kept in, if there is a disk cache
+    fn regions_folder(&self) -> Option<PathBuf> {
+        self.disk_cache
+            .as_ref()
+            .map(|folder| Path::new(folder).join("regions"))
+    }
+
+    /// The region file holding a chunk, if there is a disk cache
+    pub fn region_path(&self, location: ChunkLocation) -> Option<PathBuf> {
+        self.regions_folder()
+            .map(|folder| folder.join(RegionFile::file_name(location)))
+    }
+
+    /// A hash identifying the compression dictionary, 0 for none. Stored with every record
+    /// so chunks compressed with an earlier dictionary are read with that one
+    fn dictionary_hash(&self) -> u64 {
+        hash_dictionary(self.compression_dictionary.as_deref())
+    }
+
+    /// Reads a chunk from its region file, None if it was never saved
+    pub fn read_chunk_from_disk(&mut self, location: ChunkLocation) -> Result<Option<Chunk<T>>> {
+        self.promote_chunk(location)?;
+        let path = match self.region_path(location) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(None),
+        };
+        let context = || {
+            ErrorContext::new(Operation::LoadChunk)
+                .chunk(location)
+                .path(&path)
+        };
+        let mut region = RegionFile::open(&path).with_context(context)?;
+        let slot = RegionFile::slot(location);
+        let offset = region.offset(slot);
+        let record = match region
+            .read(slot)
+            .with_context(|| context().offset(offset))?
+        {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        self.decode_record(location, &record)
+            .map(Some)
+            .with_context(|| context().offset(offset))
+    }
+
+    /// Puts a record together from its kind and compressed payload, encrypting the payload
+    /// if encryption is set. The header and the chunk's location are authenticated along
+    /// with the payload, so records can't be swapped between chunks unnoticed
+    pub fn seal_record(
+        &self,
+        location: ChunkLocation,
+        kind: u8,
+        compressed: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut record = vec![kind];
+        record.write_u64::<LittleEndian>(self.dictionary_hash())?;
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(keyring) = &self.encryption {
+                record[0] |= ENCRYPTED_RECORD;
+                let sealed = keyring.seal(&associated_data(location, &record), compressed)?;
+                record.extend_from_slice(&sealed);
+                return Ok(record);
+            }
+        }
+        record.extend_from_slice(compressed);
+        Ok(record)
+    }
+
+    /// The kind and compressed payload of a record, decrypting it if it was encrypted
+    pub fn open_record(&self, location: ChunkLocation, record: &[u8]) -> Result<(u8, Vec<u8>)> {
+        if record.len() < RECORD_HEADER_SIZE {
+            return Err(invalid("truncated chunk record").into());
+        }
+        let (header, body) = record.split_at(RECORD_HEADER_SIZE);
+        let kind = header[0];
+        if kind & ENCRYPTED_RECORD == 0 {
+            return Ok((kind, body.to_vec()));
+        }
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(keyring) = &self.encryption {
+                let compressed = keyring.open(&associated_data(location, header), body)?;
+                return Ok((kind & !ENCRYPTED_RECORD, compressed));
+            }
+        }
+        Err(invalid("chunk is encrypted and no key is set").into())
+    }
+
+    /// The id of the key a record was encrypted with, None if it is plain text
+    #[cfg(feature = "encryption")]
+    pub fn record_key_id(&self, record: &[u8]) -> Result<Option<u32>> {
+        match record.first() {
+            Some(kind) if kind & ENCRYPTED_RECORD != 0 && record.len() >= RECORD_HEADER_SIZE => {
+                Ok(Some(Keyring::sealed_key_id(&record[RECORD_HEADER_SIZE..])?))
+            }
+            Some(_) => Ok(None),
+            None => Err(invalid("truncated chunk record").into()),
+        }
+    }
+
+    /// Rebuilds a chunk from its record
+    fn decode_record(&mut self, location: ChunkLocation, record: &[u8]) -> Result<Chunk<T>> {
+        let (kind, compressed) = self.open_record(location, record)?;
+        let hash = (&record[1..]).read_u64::<LittleEndian>()?;
+        let payload = self.decompress_payload_with(&compressed, hash)?;
+        match kind {
+            SNAPSHOT_RECORD => Ok(Chunk::decode_payload(&payload)?),
+            LOG_RECORD => {
+                let log: ChunkLog<T> = ChunkLog::read_from(&mut &payload[..])?;
+                let base = match log.base {
+                    // the generator only makes chunks for the current seed and version, and
+                    // replaying the edits onto other terrain would corrupt the chunk
+                    ChunkBase::Generated {
+                        seed,
+                        generator_version,
+                    } => {
+                        if seed != self.generation_seed {
+                            return Err(invalid("chunk was generated from a different seed").into());
+                        }
+                        if generator_version != self.generator_version {
+                            return Err(invalid(
+                                "chunk was generated by a different generator version",
+                            )
+                            .into());
+                        }
+                        match self.generate_chunk(location)? {
+                            Some(chunk) => Some(chunk),
+                            None => {
+                                return Err(invalid("no generator to rebuild the chunk from").into())
+                            }
+                        }
+                    }
+                    ChunkBase::Snapshot(_) => None,
+                };
+                let chunk = log.replay(location, &|_, _| base.clone().unwrap())?;
+                self.set_chunk_log(location, log);
+                Ok(chunk)
+            }
+            _ => Err(invalid("unknown chunk record").into()),
+        }
+    }
+
+    /// Writes a loaded chunk to its region file: the whole chunk when snapshotting, or its
+    /// edit log when event sourcing
+    pub fn write_chunk_to_disk(&mut self, location: ChunkLocation) -> Result<()> {
+        let path = match self.region_path(location) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let context = || {
+            ErrorContext::new(Operation::SaveChunk)
+                .chunk(location)
+                .path(&path)
+        };
+        let record = match self.chunk_record(location).with_context(context)? {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        fs::create_dir_all(path.parent().unwrap()).with_context(context)?;
+        RegionFile::open(&path)
+            .and_then(|mut region| region.write(RegionFile::slot(location), &record))
+            .with_context(context)
+    }
+
+    /// The record a chunk is saved as, None if there is nothing loaded to save
+    fn chunk_record(&self, location: ChunkLocation) -> Result<Option<Vec<u8>>> {
+        // writing into a Vec can't fail
+        let (kind, payload) = match (self.persistence_mode, self.chunk_logs.get(&location)) {
+            (PersistenceMode::EventSourced { .. }, Some(log)) => {
+                let mut payload = Vec::new();
+                log.write_to(&mut payload).unwrap();
+                (LOG_RECORD, payload)
+            }
+            _ => match self.loaded_chunks.get(&location) {
+                Some(chunk) => (SNAPSHOT_RECORD, chunk.encode_payload().unwrap()),
+                None => return Ok(None),
+            },
+        };
+        let compressed = self.compress_payload(&payload)?;
+        Ok(Some(self.seal_record(location, kind, &compressed)?))
+    }
+
+    /// How many region files flush writes at once. 4 unless set otherwise
+    pub fn set_flush_threads(&mut self, threads: usize) {
+        self.flush_threads = threads.max(1);
+    }
+
+    pub fn flush_threads(&self) -> usize {
+        self.flush_threads
+    }
+
+    /// Writes every dirty chunk, grouped by region file so each file is opened once and
+    /// written in one go. The region files are written on up to flush_threads threads at
+    /// once. Chunks of the regions that fail to be written stay dirty, and the first
+    /// failure is returned
+    pub fn write_dirty_chunks(&mut self) -> Result<()> {
+        let mut dirty: Vec<ChunkLocation> = self.dirty_chunks.iter().copied().collect();
+        dirty.sort_by_key(|location| (location.z, location.y, location.x));
+        let mut batches: BTreeMap<PathBuf, Vec<(ChunkLocation, Vec<u8>)>> = BTreeMap::new();
+        for location in dirty {
+            let started = Instant::now();
+            self.prepare_chunk_for_save(location);
+            let path = match self.region_path(location) {
+                Some(path) => path,
+                None => {
+                    // without a disk cache there is nowhere to save the chunk to
+                    self.dirty_chunks.remove(&location);
+                    continue;
+                }
+            };
+            let record = self.chunk_record(location).with_context(|| {
+                ErrorContext::new(Operation::SaveChunk)
+                    .chunk(location)
+                    .path(&path)
+            })?;
+            match record {
+                Some(record) => batches.entry(path).or_default().push((location, record)),
+                None => {
+                    self.dirty_chunks.remove(&location);
+                }
+            }
+            self.record_op(ChunkOp::Save, location, started);
+        }
+        let batches: Vec<RegionBatch> = batches.into_iter().collect();
+        let results = write_region_batches(&batches, self.flush_threads);
+        let mut first_error = None;
+        for ((path, records), result) in batches.iter().zip(results) {
+            match result {
+                Ok(()) => {
+                    for (location, _) in records.iter() {
+                        self.dirty_chunks.remove(location);
+                    }
+                }
+                Err(err) => {
+                    if first_error.is_none() {
+                        first_error =
+                            Some(Err(err).with_context(|| {
+                                ErrorContext::new(Operation::SaveChunk).path(path)
+                            }));
+                    }
+                }
+            }
+        }
+        first_error.unwrap_or(Ok(()))
+    }
+
+    /// Removes a chunk from its region file
+    pub fn delete_chunk_from_disk(&mut self, location: ChunkLocation) -> Result<()> {
+        self.delete_cold_chunk(location)?;
+        if let Some(path) = self.region_path(location) {
+            if path.exists() {
+                RegionFile::open(&path)
+                    .and_then(|mut region| region.remove(RegionFile::slot(location)))
+                    .with_context(|| {
+                        ErrorContext::new(Operation::DeleteChunk)
+                            .chunk(location)
+                            .path(&path)
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines every chunk found in the region files, so they are paged in when accessed
+    pub fn scan_regions(&mut self) -> Result<()> {
+        let folder = match self.regions_folder() {
+            Some(folder) if folder.exists() => folder,
+            _ => return Ok(()),
+        };
+        let context = || ErrorContext::new(Operation::ScanRegions).path(&folder);
+        for entry in fs::read_dir(&folder).with_context(context)? {
+            let path = entry.with_context(context)?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let coordinates: Vec<i32> = match name
+                .strip_prefix("r.")
+                .and_then(|name| name.strip_suffix(".region"))
+            {
+                Some(coordinates) => coordinates
+                    .split('.')
+                    .filter_map(|x| x.parse().ok())
+                    .collect(),
+                None => continue,
+            };
+            if coordinates.len() != 3 {
+                continue;
+            }
+            let region = RegionFile::open(&path)
+                .with_context(|| ErrorContext::new(Operation::ScanRegions).path(&path))?;
+            for slot in region.slots() {
+                let slot = slot as i32;
+                self.all_chunk_locations.insert(ChunkLocation::new(
+                    coordinates[0] * REGION_SIZE + slot % REGION_SIZE,
+                    coordinates[1] * REGION_SIZE + (slot / REGION_SIZE) % REGION_SIZE,
+                    coordinates[2] * REGION_SIZE + slot / (REGION_SIZE * REGION_SIZE),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the same content as the same bytes from now on, so worlds can be deduplicated,
+    /// hashed and kept in version control. Chunk palettes are sorted, every region file a
+    /// flush leaves out of slot order is rewritten in it, and metadata is written in a
+    /// fixed order. The world stats count edits rather than describe content, so they
+    /// aren't saved. Compression is single threaded at a fixed level either way. Encrypted
+    /// records use random nonces and event sourced ones hold the edit history, so neither
+    /// comes out the same
+    pub fn set_canonical_saves(&mut self, canonical: bool) {
+        self.canonical_saves = canonical;
+    }
+
+    pub fn canonical_saves(&self) -> bool {
+        self.canonical_saves
+    }
+
+    /// Rewrites the region files that are mostly dead records, and when saving canonically
+    /// the ones that aren't laid out canonically
+    pub fn compact_regions(&mut self) -> Result<usize> {
+        let canonical = self.canonical_saves;
+        let folder = match self.regions_folder() {
+            Some(folder) if folder.exists() => folder,
+            _ => return Ok(0),
+        };
+        let mut compacted = 0;
+        let context = || ErrorContext::new(Operation::CompactRegions).path(&folder);
+        for entry in fs::read_dir(&folder).with_context(context)? {
+            let path = entry.with_context(context)?.path();
+            if path.extension().and_then(|x| x.to_str()) != Some("region") {
+                continue;
+            }
+            let compact = || -> io::Result<bool> {
+                let mut region = RegionFile::open(&path)?;
+                let wasted = region.wasted_bytes()?;
+                let size = region.file.metadata()?.len();
+                let mostly_dead = wasted >= COMPACTION_MIN_WASTE && wasted * 2 > size;
+                if mostly_dead || (canonical && !region.is_canonical()?) {
+                    region.compact(&path)?;
+                    return Ok(true);
+                }
+                Ok(false)
+            };
+            if compact()
+                .with_context(|| ErrorContext::new(Operation::CompactRegions).path(&path))?
+            {
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path for a region file no other test uses, with nothing there yet
+    fn temp_region(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "rust-chunks-region-{}-{}.region",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn writes_reads_and_removes_records() {
+        let path = temp_region("records");
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read(3).unwrap(), None);
+        region.write(3, b"first").unwrap();
+        region
+            .write_batch(&[(5, b"second".to_vec()), (3, b"third".to_vec())])
+            .unwrap();
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.slots(), vec![3, 5]);
+        assert_eq!(region.read(3).unwrap(), Some(b"third".to_vec()));
+        assert_eq!(region.read(5).unwrap(), Some(b"second".to_vec()));
+        region.remove(3).unwrap();
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read(3).unwrap(), None);
+        assert_eq!(region.slots(), vec![5]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compacts_dead_records() {
+        let path = temp_region("compact");
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write(7, &[1; 100]).unwrap();
+        region.write(2, &[2; 50]).unwrap();
+        region.write(7, &[3; 100]).unwrap();
+        assert_eq!(region.wasted_bytes().unwrap(), 100);
+        assert!(!region.is_canonical().unwrap());
+        region.compact(&path).unwrap();
+        assert_eq!(region.wasted_bytes().unwrap(), 0);
+        assert!(region.is_canonical().unwrap());
+        assert_eq!(region.read(2).unwrap(), Some(vec![2; 50]));
+        assert_eq!(region.read(7).unwrap(), Some(vec![3; 100]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let path = temp_region("truncated");
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write(0, &[4; 64]).unwrap();
+        let len = region.file.metadata().unwrap().len();
+        drop(region);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 1).unwrap();
+        let err = RegionFile::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        file.set_len(HEADER_SIZE / 2).unwrap();
+        assert!(RegionFile::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
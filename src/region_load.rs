@@ -0,0 +1,172 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::{Aabb, ChunkLocation, Dimension, Point3D, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE};
+
+/// The progress of one load_region_async call, shared between the dimension and the future
+pub struct RegionLoadState {
+    /// chunks that still have to be made resident
+    remaining: Vec<ChunkLocation>,
+    /// set once every chunk is resident
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// Resolves once every defined or generated chunk of a region is resident. The dimension
+/// does the loading when driven by drive_region_loads or idle_work, so the future itself
+/// needs no particular executor
+pub struct RegionLoad {
+    state: Arc<Mutex<RegionLoadState>>,
+}
+
+impl Future for RegionLoad {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Result<()>> {
+        let mut state = self.state.lock().unwrap();
+        if state.done {
+            Poll::Ready(Ok(()))
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// The chunks overlapping a box of voxels
+pub fn chunks_in(region: Aabb) -> Vec<ChunkLocation> {
+    let mut chunks = Vec::new();
+    if region.is_empty() {
+        return chunks;
+    }
+    let size = [
+        CHUNK_X_SIZE as i32,
+        CHUNK_Y_SIZE as i32,
+        CHUNK_Z_SIZE as i32,
+    ];
+    for z in region.start.z.div_euclid(size[2])..=(region.end.z - 1).div_euclid(size[2]) {
+        for y in region.start.y.div_euclid(size[1])..=(region.end.y - 1).div_euclid(size[1]) {
+            for x in region.start.x.div_euclid(size[0])..=(region.end.x - 1).div_euclid(size[0]) {
+                chunks.push(ChunkLocation::new(x, y, z));
+            }
+        }
+    }
+    chunks
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Asks for every defined chunk overlapping the region, or every chunk if there is a
+    /// generator, to be made resident, returning a future that resolves once they are, so
+    /// cutscene or teleport code can await the world instead of polling it
+    pub fn load_region_async(&mut self, region: Aabb) -> RegionLoad {
+        let state = Arc::new(Mutex::new(RegionLoadState {
+            remaining: chunks_in(region),
+            done: false,
+            waker: None,
+        }));
+        self.region_loads.push(Arc::clone(&state));
+        // resolve right away if everything is already there
+        self.drive_region_loads(Instant::now());
+        RegionLoad { state }
+    }
+
+    /// Loads chunks for the pending load_region_async calls until deadline, waking the
+    /// ones that are complete. Returns how many chunks were loaded
+    pub fn drive_region_loads(&mut self, deadline: Instant) -> usize {
+        let mut loaded = 0;
+        let loads = std::mem::take(&mut self.region_loads);
+        for load in loads {
+            let mut state = load.lock().unwrap();
+            let mut remaining = std::mem::take(&mut state.remaining);
+            remaining.retain(|location| {
+                if !self.chunk_available(*location) || self.chunk_loaded(*location) {
+                    return false;
+                }
+                if Instant::now() < deadline {
+                    // chunks that fail to load are retried on the next call
+                    if self.load_chunk(*location).is_ok() {
+                        loaded += 1;
+                        return false;
+                    }
+                }
+                true
+            });
+            state.remaining = remaining;
+            if state.remaining.is_empty() {
+                state.done = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            } else {
+                drop(state);
+                self.region_loads.push(load);
+            }
+        }
+        loaded
+    }
+
+    /// How many load_region_async calls are still waiting
+    pub fn pending_region_loads(&self) -> usize {
+        self.region_loads.len()
+    }
+}
+
+/// What ensure_region_loaded managed to do within its budget
+pub struct AreaReadiness {
+    /// if the chunk at the center of the region and its neighbors are resident, so a
+    /// player can be placed there
+    pub safe: bool,
+    /// resolves once the rest of the region is resident too
+    pub rest: RegionLoad,
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Loads the chunk at the center of the region and the chunks touching it right away,
+    /// nearest first, for at most budget, then hands the rest of the region to
+    /// load_region_async. Teleport code should only move the player once the area is safe
+    pub fn ensure_region_loaded(&mut self, region: Aabb, budget: Duration) -> AreaReadiness {
+        let deadline = Instant::now() + budget;
+        let center = Self::get_chunk_location(Point3D::new(
+            region.start.x + (region.end.x - region.start.x) / 2,
+            region.start.y + (region.end.y - region.start.y) / 2,
+            region.start.z + (region.end.z - region.start.z) / 2,
+        ));
+        let mut safe_area = Vec::with_capacity(27);
+        for z in center.z.saturating_sub(1)..=center.z.saturating_add(1) {
+            for y in center.y.saturating_sub(1)..=center.y.saturating_add(1) {
+                for x in center.x.saturating_sub(1)..=center.x.saturating_add(1) {
+                    safe_area.push(ChunkLocation::new(x, y, z));
+                }
+            }
+        }
+        safe_area.sort_by_key(|location| {
+            location.x.abs_diff(center.x)
+                + location.y.abs_diff(center.y)
+                + location.z.abs_diff(center.z)
+        });
+        // the target chunk is loaded even if the budget is already used up
+        let mut safe = true;
+        for (i, location) in safe_area.iter().enumerate() {
+            if !self.chunk_available(*location) {
+                // nothing to stand on in the target chunk means it isn't safe
+                safe &= i != 0;
+                continue;
+            }
+            if !self.chunk_loaded(*location) && (i == 0 || Instant::now() < deadline) {
+                // a chunk that fails to load leaves the area unsafe
+                let _ = self.load_chunk(*location);
+            }
+            safe &= self.chunk_loaded(*location);
+        }
+        AreaReadiness {
+            safe,
+            rest: self.load_region_async(region),
+        }
+    }
+}
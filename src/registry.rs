@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use super::codec::VoxelCodec;
+use super::error::{Error, Result};
+use super::occupancy::VoxelClass;
+use super::schema::stable_hash;
+use super::shapes::ShapeRegistry;
+use super::tags::VoxelTagSet;
+use super::{Dimension, VoxelType};
+
+/// A custom property of a voxel type, for game specific data the registry doesn't know about
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// The layout of a registry file, a list of `voxel` entries
+#[derive(Serialize, Deserialize)]
+struct RegistryFile {
+    #[serde(rename = "voxel", default)]
+    voxels: Vec<VoxelType>,
+}
+
+/// Every voxel type a world knows about, by id. Normally loaded from a RON or TOML file
+/// so game content doesn't have to be written in Rust
+#[derive(Clone, Default, Debug)]
+pub struct VoxelRegistry {
+    types: BTreeMap<u32, VoxelType>,
+    ids: HashMap<String, u32>,
+    /// the shapes voxels of these types can take
+    shapes: ShapeRegistry,
+}
+
+impl VoxelRegistry {
+    pub fn new() -> VoxelRegistry {
+        VoxelRegistry::default()
+    }
+
+    /// The voxel types every world starts out with
+    pub fn builtin() -> &'static VoxelRegistry {
+        static BUILTIN: OnceLock<VoxelRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            let mut registry = VoxelRegistry::new();
+            for (id, name, solid, color, opacity) in [
+                (0, "unknown", true, Some([255, 0, 255]), 1.0),
+                (1, "air", false, None, 0.0),
+                (2, "water", false, Some([40, 90, 200]), 0.4),
+                (3, "stone", true, Some([120, 120, 120]), 1.0),
+            ] {
+                let mut voxel_type = VoxelType::new(id, name, solid);
+                voxel_type.color = color;
+                voxel_type.opacity = opacity;
+                if id == 2 {
+                    voxel_type.tags.insert(String::from("liquid"));
+                }
+                if id == 1 || id == 2 {
+                    voxel_type.tags.insert(String::from("replaceable"));
+                }
+                registry.register(voxel_type).unwrap();
+            }
+            registry
+        })
+    }
+
+    /// Adds a voxel type, failing if its id or name is already taken
+    pub fn register(&mut self, voxel_type: VoxelType) -> Result<()> {
+        if voxel_type.name.is_empty() {
+            return Err(Error::InvalidRegistry(format!(
+                "voxel type {} has no name",
+                voxel_type.id
+            )));
+        }
+        if voxel_type.tags.iter().any(|tag| tag.is_empty()) {
+            return Err(Error::InvalidRegistry(format!(
+                "voxel type \"{}\" has an empty tag",
+                voxel_type.name
+            )));
+        }
+        if self.types.contains_key(&voxel_type.id) {
+            return Err(Error::InvalidRegistry(format!(
+                "voxel id {} is used more than once",
+                voxel_type.id
+            )));
+        }
+        if self.ids.contains_key(&voxel_type.name) {
+            return Err(Error::InvalidRegistry(format!(
+                "voxel name \"{}\" is used more than once",
+                voxel_type.name
+            )));
+        }
+        self.ids.insert(voxel_type.name.clone(), voxel_type.id);
+        self.types.insert(voxel_type.id, voxel_type);
+        Ok(())
+    }
+
+    fn from_file(file: RegistryFile) -> Result<VoxelRegistry> {
+        let mut registry = VoxelRegistry::new();
+        for voxel_type in file.voxels {
+            registry.register(voxel_type)?;
+        }
+        Ok(registry)
+    }
+
+    /// Reads a registry from TOML, a list of `[[voxel]]` tables
+    pub fn from_toml_str(source: &str) -> Result<VoxelRegistry> {
+        let file = toml::from_str(source).map_err(|err| Error::InvalidRegistry(err.to_string()))?;
+        VoxelRegistry::from_file(file)
+    }
+
+    /// Reads a registry from RON, a struct with a `voxel` list
+    pub fn from_ron_str(source: &str) -> Result<VoxelRegistry> {
+        let file = ron::from_str(source).map_err(|err| Error::InvalidRegistry(err.to_string()))?;
+        VoxelRegistry::from_file(file)
+    }
+
+    /// Reads a registry file, choosing the format by its extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<VoxelRegistry> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => VoxelRegistry::from_toml_str(&source),
+            Some("ron") => VoxelRegistry::from_ron_str(&source),
+            _ => Err(Error::InvalidRegistry(format!(
+                "{} is neither a .toml nor a .ron file",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn get(&self, id: u32) -> Option<&VoxelType> {
+        self.types.get(&id)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &VoxelType> {
+        self.types.values()
+    }
+
+    pub fn shapes(&self) -> &ShapeRegistry {
+        &self.shapes
+    }
+
+    pub fn shapes_mut(&mut self) -> &mut ShapeRegistry {
+        &mut self.shapes
+    }
+
+    /// If the type with the id carries the tag
+    pub fn has_tag(&self, id: u32, tag: &str) -> bool {
+        self.get(id)
+            .is_some_and(|voxel_type| voxel_type.tags.contains(tag))
+    }
+
+    /// Every type carrying the tag
+    pub fn tagged(&self, tag: &str) -> VoxelTagSet {
+        VoxelTagSet::from_ids(
+            self.iter()
+                .filter(|voxel_type| voxel_type.tags.contains(tag))
+                .map(|voxel_type| voxel_type.id),
+        )
+    }
+
+    /// The color a type is drawn with. Types without one get a color made up from their
+    /// name, so custom types can be told apart without any setup
+    pub fn color_of(&self, id: u32) -> [u8; 3] {
+        match self.get(id) {
+            Some(VoxelType {
+                color: Some(color), ..
+            }) => *color,
+            Some(voxel_type) => {
+                let hash = stable_hash(voxel_type.name.as_bytes()).to_le_bytes();
+                [hash[0], hash[1], hash[2]]
+            }
+            None => [255, 0, 255],
+        }
+    }
+
+    /// How much a type hides what is behind it, unknown types hide everything
+    pub fn opacity_of(&self, id: u32) -> f32 {
+        self.get(id)
+            .map_or(1.0, |voxel_type| voxel_type.opacity.clamp(0.0, 1.0))
+    }
+
+    /// How much light a type gives off
+    pub fn emissive_of(&self, id: u32) -> u8 {
+        self.get(id).map_or(0, |voxel_type| voxel_type.emissive)
+    }
+
+    /// The name of every id, as stored in the world metadata
+    pub fn id_names(&self) -> BTreeMap<u32, String> {
+        self.types
+            .iter()
+            .map(|(id, voxel_type)| (*id, voxel_type.name.clone()))
+            .collect()
+    }
+
+    /// A hash of the ids, names and solidity of every type, for the voxel schema
+    pub fn stable_hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        for voxel_type in self.types.values() {
+            bytes.extend_from_slice(&voxel_type.id.to_le_bytes());
+            bytes.extend_from_slice(voxel_type.name.as_bytes());
+            bytes.push(voxel_type.solid as u8);
+        }
+        stable_hash(&bytes)
+    }
+
+    /// Checks that every id a world was saved with still means the same voxel type, since
+    /// otherwise the saved voxels would silently turn into something else
+    pub fn check_stable_ids(&self, saved: &BTreeMap<u32, String>) -> Result<()> {
+        for (id, name) in saved.iter() {
+            let current = self.get(*id).map(|voxel_type| voxel_type.name.clone());
+            if current.as_ref() != Some(name) {
+                return Err(Error::UnstableVoxelId {
+                    id: *id,
+                    saved: name.clone(),
+                    current,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Uses a registry for the world's voxel types, failing if it gives any id the world
+    /// was saved with a different meaning
+    pub fn set_registry(&mut self, registry: Arc<VoxelRegistry>) -> Result<()> {
+        if let Some(saved) = &self.saved_voxel_ids {
+            registry.check_stable_ids(saved)?;
+        }
+        self.saved_voxel_ids = Some(registry.id_names());
+        self.registry = Some(registry);
+        Ok(())
+    }
+
+    pub fn registry(&self) -> Option<&VoxelRegistry> {
+        self.registry.as_deref()
+    }
+}
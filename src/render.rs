@@ -0,0 +1,54 @@
+use super::registry::VoxelRegistry;
+use super::{Aabb, Point3D, Voxel, VoxelRead};
+
+/// How dark the bottom of a rendered box is drawn compared to its top
+const DEPTH_SHADING: f32 = 0.5;
+
+/// Draws a box of the world as seen from above, using the colors, opacities and emissive
+/// values of the registry. Returns one rgb pixel per column, in rows of increasing x, one
+/// row per y. Deeper voxels are drawn darker unless they give off light
+pub fn render_top_down<M: VoxelRead<Voxel>>(
+    map: &M,
+    registry: &VoxelRegistry,
+    region: Aabb,
+) -> Vec<[u8; 3]> {
+    let height = region.end.z.saturating_sub(region.start.z).max(1) as f32;
+    let mut pixels = Vec::new();
+    for y in region.start.y..region.end.y {
+        for x in region.start.x..region.end.x {
+            let mut color = [0.0f32; 3];
+            // how much of what is further down can still be seen
+            let mut transmittance = 1.0f32;
+            for z in (region.start.z..region.end.z).rev() {
+                let location = Point3D::new(x, y, z);
+                if !map.within_bounds(location) {
+                    continue;
+                }
+                let id = map.get(location).id;
+                let opacity = registry.opacity_of(id);
+                if opacity <= 0.0 {
+                    continue;
+                }
+                let shade = if registry.emissive_of(id) > 0 {
+                    1.0
+                } else {
+                    1.0 - DEPTH_SHADING * (region.end.z - 1 - z) as f32 / height
+                };
+                let voxel_color = registry.color_of(id);
+                for channel in 0..3 {
+                    color[channel] += transmittance * opacity * shade * voxel_color[channel] as f32;
+                }
+                transmittance *= 1.0 - opacity;
+                if transmittance <= 0.01 {
+                    break;
+                }
+            }
+            pixels.push([
+                color[0].round().min(255.0) as u8,
+                color[1].round().min(255.0) as u8,
+                color[2].round().min(255.0) as u8,
+            ]);
+        }
+    }
+    pixels
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use super::codec::VoxelCodec;
+use super::generator::ChunkGenerator;
+use super::occupancy::VoxelClass;
+use super::{Chunk, ChunkLocation, Point3D, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE};
+
+/// The height of the ground of the base terrain in a column, one above its top solid voxel
+pub type HeightFn = Arc<dyn Fn(i32, i32) -> i32 + Send + Sync>;
+
+/// Where rivers start and how they are carved, in voxels
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RiverSettings {
+    pub seed: u64,
+    /// sources are picked one per cell of a grid this many columns on a side
+    pub source_spacing: i32,
+    /// the chance of a cell having a source at all
+    pub source_chance: f64,
+    /// sources lower than this are skipped, so rivers start in the hills
+    pub min_source_height: i32,
+    /// the most columns a river runs before it stops
+    pub max_length: usize,
+    /// how far the water of a river lies below the ground it runs along
+    pub depth: i32,
+    /// how far the channel reaches out from the river's course
+    pub radius: i32,
+    /// how far past the channel the bank reaches
+    pub bank_width: i32,
+    /// the water table where the ground is at this height. Rivers end once they reach it
+    pub water_level: i32,
+    /// how closely the water table follows the ground above water_level, from 0 for flat
+    /// to 1 for right at the surface
+    pub water_table_rise: f32,
+}
+
+impl Default for RiverSettings {
+    fn default() -> RiverSettings {
+        RiverSettings {
+            seed: 0,
+            source_spacing: 64,
+            source_chance: 0.3,
+            min_source_height: 40,
+            max_length: 512,
+            depth: 2,
+            radius: 1,
+            bank_width: 1,
+            water_level: 0,
+            water_table_rise: 0.5,
+        }
+    }
+}
+
+/// A deterministic hash of a column, for picking sources without keeping any state
+fn column_hash(seed: u64, x: i32, y: i32) -> u64 {
+    let mut hash = seed ^ ((x as u32 as u64) << 32 | y as u32 as u64);
+    // splitmix64's finalizer
+    hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// A river's course from its source down
+pub type RiverCourse = Arc<Vec<RiverPoint>>;
+
+/// One column of a river's course, with the height its water reaches
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RiverPoint {
+    pub x: i32,
+    pub y: i32,
+    /// the z of the top water voxel
+    pub surface: i32,
+}
+
+/// A generator pass adding groundwater and rivers to the terrain of another generator.
+/// Empty voxels below the water table turn into water, flooding low ground and caves, and
+/// rivers run downhill from sources picked by noise along the steepest slope of the
+/// heightmap, carving a channel of water lined with banks. Rivers end where they reach
+/// the water table or a hollow with no way further down
+pub struct RiverGenerator<T> {
+    base: Arc<dyn ChunkGenerator<T>>,
+    height: HeightFn,
+    settings: RiverSettings,
+    water: T,
+    bank: T,
+    /// the courses traced so far, by source cell, since neighboring chunks share them
+    courses: Mutex<HashMap<(i32, i32), RiverCourse>>,
+}
+
+impl<T: Copy + Default + VoxelClass> RiverGenerator<T> {
+    /// Adds rivers to base, whose ground height height gives. Channels are filled with
+    /// water and lined with bank
+    pub fn new(
+        base: Arc<dyn ChunkGenerator<T>>,
+        height: HeightFn,
+        settings: RiverSettings,
+        water: T,
+        bank: T,
+    ) -> RiverGenerator<T> {
+        RiverGenerator {
+            base,
+            height,
+            settings,
+            water,
+            bank,
+            courses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn settings(&self) -> &RiverSettings {
+        &self.settings
+    }
+
+    /// The height groundwater reaches in a column: empty voxels below it are water
+    pub fn water_table(&self, x: i32, y: i32) -> i32 {
+        let ground = (self.height)(x, y);
+        let level = self.settings.water_level;
+        if ground <= level {
+            return level;
+        }
+        level + ((ground - level) as f32 * self.settings.water_table_rise.clamp(0.0, 1.0)) as i32
+    }
+
+    /// The source of the river of a grid cell, if it has one
+    fn source(&self, cell: (i32, i32)) -> Option<(i32, i32)> {
+        let settings = &self.settings;
+        let spacing = settings.source_spacing.max(1);
+        let hash = column_hash(settings.seed, cell.0, cell.1);
+        if (hash >> 11) as f64 / (1u64 << 53) as f64 >= settings.source_chance {
+            return None;
+        }
+        let x = cell.0 * spacing + (hash % spacing as u64) as i32;
+        let y = cell.1 * spacing + ((hash >> 32) % spacing as u64) as i32;
+        if (self.height)(x, y) < settings.min_source_height {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    /// Follows the steepest way down from a source. Flat ground is crossed to columns the
+    /// river hasn't been to yet, so plateaus don't end rivers early
+    fn trace(&self, source: (i32, i32)) -> Vec<RiverPoint> {
+        let (mut x, mut y) = source;
+        let mut ground = (self.height)(x, y);
+        let mut course = Vec::new();
+        let mut visited = HashSet::new();
+        while course.len() < self.settings.max_length {
+            visited.insert((x, y));
+            course.push(RiverPoint {
+                x,
+                y,
+                surface: ground - self.settings.depth,
+            });
+            if ground <= self.water_table(x, y) {
+                break;
+            }
+            // ties go to the first neighbor, so courses are the same every time
+            let mut next: Option<(i32, i32, i32)> = None;
+            for (dx, dy) in [
+                (1, 0),
+                (-1, 0),
+                (0, 1),
+                (0, -1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ] {
+                let column = (x + dx, y + dy);
+                let height = (self.height)(column.0, column.1);
+                let better = match next {
+                    Some((_, _, best)) => height < best,
+                    None => height < ground || (height == ground && !visited.contains(&column)),
+                };
+                if better && !visited.contains(&column) {
+                    next = Some((column.0, column.1, height));
+                }
+            }
+            match next {
+                Some((next_x, next_y, height)) => {
+                    x = next_x;
+                    y = next_y;
+                    ground = height;
+                }
+                None => break,
+            }
+        }
+        course
+    }
+
+    /// The course of the river of a grid cell, traced once and shared afterwards
+    pub fn course(&self, cell: (i32, i32)) -> Option<RiverCourse> {
+        if let Some(course) = self.courses.lock().unwrap().get(&cell) {
+            return Some(Arc::clone(course));
+        }
+        let course = Arc::new(self.trace(self.source(cell)?));
+        self.courses
+            .lock()
+            .unwrap()
+            .insert(cell, Arc::clone(&course));
+        Some(course)
+    }
+
+    /// The courses of every river that can reach the columns from start up to end
+    pub fn courses_near(&self, start: (i32, i32), end: (i32, i32)) -> Vec<RiverCourse> {
+        let spacing = self.settings.source_spacing.max(1);
+        let reach =
+            self.settings.max_length as i32 + self.settings.radius + self.settings.bank_width;
+        let cells = |low: i32, high: i32| {
+            (low - reach).div_euclid(spacing)..=(high + reach).div_euclid(spacing)
+        };
+        let mut courses = Vec::new();
+        for cell_y in cells(start.1, end.1) {
+            for cell_x in cells(start.0, end.0) {
+                if let Some(course) = self.course((cell_x, cell_y)) {
+                    courses.push(course);
+                }
+            }
+        }
+        courses
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec + Send + Sync> ChunkGenerator<T>
+    for RiverGenerator<T>
+{
+    fn generate(&self, location: ChunkLocation) -> Chunk<T> {
+        let mut chunk = self.base.generate(location);
+        let size = Point3D::new(
+            CHUNK_X_SIZE as i32,
+            CHUNK_Y_SIZE as i32,
+            CHUNK_Z_SIZE as i32,
+        );
+        let origin = Point3D::new(
+            location.x * size.x,
+            location.y * size.y,
+            location.z * size.z,
+        );
+
+        // groundwater
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let table = self.water_table(origin.x + x, origin.y + y);
+                for z in 0..size.z.min(table - origin.z) {
+                    let local = Point3D::new(x, y, z);
+                    if chunk.get(local).is_empty() {
+                        chunk.set(local, self.water);
+                    }
+                }
+            }
+        }
+
+        // the lowest water surface of the channels over each column, and the columns the
+        // banks reach
+        let (radius, bank_width) = (self.settings.radius, self.settings.bank_width);
+        let reach = radius + bank_width;
+        let mut channels: HashMap<(i32, i32), i32> = HashMap::new();
+        let mut banks: Vec<(i32, i32)> = Vec::new();
+        let end = (origin.x + size.x, origin.y + size.y);
+        for course in self.courses_near((origin.x, origin.y), end) {
+            for point in course.iter() {
+                if point.x + reach < origin.x
+                    || point.x - reach >= end.0
+                    || point.y + reach < origin.y
+                    || point.y - reach >= end.1
+                {
+                    continue;
+                }
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let column = (point.x + dx, point.y + dy);
+                        let distance = dx * dx + dy * dy;
+                        if distance <= radius * radius {
+                            let surface = channels.entry(column).or_insert(point.surface);
+                            *surface = (*surface).min(point.surface);
+                        } else if distance <= reach * reach {
+                            banks.push(column);
+                        }
+                    }
+                }
+            }
+        }
+        for ((column_x, column_y), surface) in channels.iter() {
+            let (x, y) = (column_x - origin.x, column_y - origin.y);
+            if x < 0 || x >= size.x || y < 0 || y >= size.y {
+                continue;
+            }
+            let ground = (self.height)(*column_x, *column_y);
+            let bed = surface - self.settings.depth + 1;
+            for z in bed.max(origin.z)..ground.max(surface + 1).min(origin.z + size.z) {
+                let value = if z <= *surface {
+                    self.water
+                } else {
+                    T::empty()
+                };
+                chunk.set(Point3D::new(x, y, z - origin.z), value);
+            }
+        }
+        for (column_x, column_y) in banks {
+            let (x, y) = (column_x - origin.x, column_y - origin.y);
+            if x < 0 || x >= size.x || y < 0 || y >= size.y {
+                continue;
+            }
+            if channels.contains_key(&(column_x, column_y)) {
+                continue;
+            }
+            let z = (self.height)(column_x, column_y) - 1 - origin.z;
+            if (0..size.z).contains(&z) && chunk.get(Point3D::new(x, y, z)).is_solid() {
+                chunk.set(Point3D::new(x, y, z), self.bank);
+            }
+        }
+        chunk
+    }
+}
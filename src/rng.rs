@@ -0,0 +1,124 @@
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::Dimension;
+
+/// Copies random sources, so worlds holding one can be cloned along with where it is in its
+/// sequence. Every cloneable source has it
+pub trait CloneRng {
+    fn clone_rng(&self) -> BoxedRng;
+}
+
+impl<R: WorldRng + Clone + Send + Sync + 'static> CloneRng for R {
+    fn clone_rng(&self) -> BoxedRng {
+        Box::new(self.clone())
+    }
+}
+
+/// A source of random numbers for the chance events of a world. Simulations drawing from
+/// one seeded source instead of global randomness come out the same every run, so tests
+/// and replays can reproduce them. With the rand feature, every cloneable rand_core::Rng is
+/// one
+pub trait WorldRng: CloneRng {
+    fn next_u64(&mut self) -> u64;
+
+    /// A number from 0 up to 1
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number from 0 up to bound, with every number equally likely. Panics if bound is 0
+    fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "bound must be positive");
+        // numbers past the last whole multiple of bound would favor the low results
+        let limit = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return value % bound;
+            }
+        }
+    }
+
+    /// True with the given probability
+    fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
+
+/// The PCG-XSH-RR generator with 64 bits of state, small and fast, seeded from a number
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+    /// A generator whose numbers are decided by seed and stream. Different streams give
+    /// unrelated numbers for the same seed
+    pub fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn seeded(seed: u64) -> Pcg32 {
+        Pcg32::new(seed, 0)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(Pcg32::MULTIPLIER)
+            .wrapping_add(self.increment);
+        let shifted = (((old >> 18) ^ old) >> 27) as u32;
+        shifted.rotate_right((old >> 59) as u32)
+    }
+}
+
+impl WorldRng for Pcg32 {
+    fn next_u64(&mut self) -> u64 {
+        (self.next_u32() as u64) << 32 | self.next_u32() as u64
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<R: rand_core::Rng + Clone + Send + Sync + 'static> WorldRng for R {
+    fn next_u64(&mut self) -> u64 {
+        rand_core::Rng::next_u64(self)
+    }
+}
+
+/// The random source of a world
+pub type BoxedRng = Box<dyn WorldRng + Send + Sync>;
+
+impl Clone for BoxedRng {
+    fn clone(&self) -> BoxedRng {
+        self.clone_rng()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Where the chance events of the world draw from, and what drop tables and weather
+    /// evolution are handed to roll their chances with. Seeded with 0 unless seeded or
+    /// replaced, so two runs making the same calls see the same numbers
+    pub fn rng(&mut self) -> &mut (dyn WorldRng + Send + Sync) {
+        &mut *self.rng
+    }
+
+    /// Starts the world's random source over from seed
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Box::new(Pcg32::seeded(seed));
+    }
+
+    /// Replaces the world's random source, e.g. with a rand generator shared with the game
+    pub fn set_rng(&mut self, rng: BoxedRng) {
+        self.rng = rng;
+    }
+}
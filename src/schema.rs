@@ -0,0 +1,86 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::metadata::{read_string, write_string};
+use super::occupancy::VoxelClass;
+use super::registry::VoxelRegistry;
+use super::{Dimension, Voxel};
+
+/// Describes the layout of a voxel type, so that a world saved with one voxel type can't be
+/// opened with an incompatible one
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VoxelSchema {
+    /// how many bits of the voxel identify its type
+    pub id_bits: u8,
+    /// the names of the extra attributes stored with each voxel, in order
+    pub channels: Vec<String>,
+    /// a hash of the voxel types the ids refer to
+    pub registry_hash: u64,
+}
+
+/// Voxel types that can describe their own layout
+pub trait SchemaVoxel {
+    fn schema() -> VoxelSchema;
+}
+
+/// 64 bit FNV-1a, which unlike the std hashers is guaranteed to give the same result on
+/// every run and every platform
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl VoxelSchema {
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u8(self.id_bits)?;
+        out.write_u16::<LittleEndian>(self.channels.len() as u16)?;
+        for channel in self.channels.iter() {
+            write_string(out, channel)?;
+        }
+        out.write_u64::<LittleEndian>(self.registry_hash)
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<VoxelSchema> {
+        let id_bits = input.read_u8()?;
+        let mut channels = Vec::new();
+        for _ in 0..input.read_u16::<LittleEndian>()? {
+            channels.push(read_string(input)?);
+        }
+        Ok(VoxelSchema {
+            id_bits,
+            channels,
+            registry_hash: input.read_u64::<LittleEndian>()?,
+        })
+    }
+}
+
+impl SchemaVoxel for Voxel {
+    fn schema() -> VoxelSchema {
+        VoxelSchema {
+            id_bits: 32,
+            channels: vec![String::from("shape"), String::from("extra_data")],
+            registry_hash: VoxelRegistry::builtin().stable_hash(),
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Declares the layout of the voxels, checked against the saved one when loading
+    pub fn declare_schema(&mut self, schema: VoxelSchema) {
+        self.schema = Some(schema);
+    }
+
+    pub fn schema(&self) -> Option<&VoxelSchema> {
+        self.schema.as_ref()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec + SchemaVoxel> Dimension<T> {
+    /// Declares the schema the voxel type describes itself with
+    pub fn declare_voxel_schema(&mut self) {
+        self.declare_schema(T::schema());
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use super::error::{Error, Result};
+use super::faces::Face;
+use super::registry::VoxelRegistry;
+use super::{GlobalLocation, Point3D, Voxel, VoxelRead};
+
+/// Identifies a shape in a shape registry. 0 is always the full cube
+pub type ShapeId = u8;
+
+/// How many sub-cells a shape has along each axis
+pub const SHAPE_RESOLUTION: u32 = 4;
+
+/// The full cube every voxel has unless it says otherwise
+pub const FULL_SHAPE: ShapeId = 0;
+
+/// Which parts of its cell a voxel fills, as a 4x4x4 grid of sub-cells. Bit
+/// `x + 4 * y + 16 * z` is set when the sub-cell is filled
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Shape {
+    pub name: String,
+    pub mask: u64,
+}
+
+impl Shape {
+    /// A shape filling the sub-cells fill says are filled
+    pub fn from_fn<F: Fn(u32, u32, u32) -> bool>(name: &str, fill: F) -> Shape {
+        let mut mask = 0;
+        for z in 0..SHAPE_RESOLUTION {
+            for y in 0..SHAPE_RESOLUTION {
+                for x in 0..SHAPE_RESOLUTION {
+                    if fill(x, y, z) {
+                        mask |= 1 << sub_cell_index(x, y, z);
+                    }
+                }
+            }
+        }
+        Shape {
+            name: String::from(name),
+            mask,
+        }
+    }
+
+    /// A shape at half resolution, from a 2x2x2 mask with bit `x + 2 * y + 4 * z` set for
+    /// every filled half
+    pub fn from_half_mask(name: &str, mask: u8) -> Shape {
+        Shape::from_fn(name, |x, y, z| {
+            mask & (1 << (x / 2 + 2 * (y / 2) + 4 * (z / 2))) != 0
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.mask == u64::MAX
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mask == 0
+    }
+
+    /// If the sub-cell is filled
+    pub fn occupies(&self, x: u32, y: u32, z: u32) -> bool {
+        self.mask & (1 << sub_cell_index(x, y, z)) != 0
+    }
+
+    /// If the shape fills the whole of one face of its cell, so it can hide its neighbor
+    pub fn covers_face(&self, face: Face) -> bool {
+        let last = SHAPE_RESOLUTION - 1;
+        (0..SHAPE_RESOLUTION).all(|a| {
+            (0..SHAPE_RESOLUTION).all(|b| match face {
+                Face::East => self.occupies(last, a, b),
+                Face::West => self.occupies(0, a, b),
+                Face::North => self.occupies(a, last, b),
+                Face::South => self.occupies(a, 0, b),
+                Face::Up => self.occupies(a, b, last),
+                Face::Down => self.occupies(a, b, 0),
+            })
+        })
+    }
+
+    /// The filled parts of the cell as boxes, in fractions of a voxel, for collision.
+    /// Filled sub-cells are merged into runs along x
+    pub fn boxes(&self) -> Vec<([f32; 3], [f32; 3])> {
+        let size = 1.0 / SHAPE_RESOLUTION as f32;
+        if self.is_full() {
+            return vec![([0.0; 3], [1.0; 3])];
+        }
+        let mut boxes = Vec::new();
+        for z in 0..SHAPE_RESOLUTION {
+            for y in 0..SHAPE_RESOLUTION {
+                let mut x = 0;
+                while x < SHAPE_RESOLUTION {
+                    if !self.occupies(x, y, z) {
+                        x += 1;
+                        continue;
+                    }
+                    let start = x;
+                    while x < SHAPE_RESOLUTION && self.occupies(x, y, z) {
+                        x += 1;
+                    }
+                    boxes.push((
+                        [start as f32 * size, y as f32 * size, z as f32 * size],
+                        [
+                            x as f32 * size,
+                            (y + 1) as f32 * size,
+                            (z + 1) as f32 * size,
+                        ],
+                    ));
+                }
+            }
+        }
+        boxes
+    }
+}
+
+fn sub_cell_index(x: u32, y: u32, z: u32) -> u32 {
+    x + SHAPE_RESOLUTION * y + SHAPE_RESOLUTION * SHAPE_RESOLUTION * z
+}
+
+/// Every shape voxels can take, by id. Starts out with the full cube, slabs, stairs and
+/// panels
+#[derive(Clone, Debug)]
+pub struct ShapeRegistry {
+    shapes: Vec<Shape>,
+    ids: HashMap<String, ShapeId>,
+}
+
+impl Default for ShapeRegistry {
+    fn default() -> ShapeRegistry {
+        let mut registry = ShapeRegistry {
+            shapes: Vec::new(),
+            ids: HashMap::new(),
+        };
+        let half = SHAPE_RESOLUTION / 2;
+        let last = SHAPE_RESOLUTION - 1;
+        let builtin = [
+            Shape::from_fn("full", |_, _, _| true),
+            Shape::from_fn("slab_bottom", |_, _, z| z < half),
+            Shape::from_fn("slab_top", |_, _, z| z >= half),
+            Shape::from_fn("stairs_east", |x, _, z| z < half || x >= half),
+            Shape::from_fn("stairs_west", |x, _, z| z < half || x < half),
+            Shape::from_fn("stairs_north", |_, y, z| z < half || y >= half),
+            Shape::from_fn("stairs_south", |_, y, z| z < half || y < half),
+            Shape::from_fn("panel_east", |x, _, _| x == last),
+            Shape::from_fn("panel_west", |x, _, _| x == 0),
+            Shape::from_fn("panel_north", |_, y, _| y == last),
+            Shape::from_fn("panel_south", |_, y, _| y == 0),
+        ];
+        for shape in builtin.iter() {
+            registry.register(shape.clone()).unwrap();
+        }
+        registry
+    }
+}
+
+impl ShapeRegistry {
+    pub fn new() -> ShapeRegistry {
+        ShapeRegistry::default()
+    }
+
+    /// Adds a shape, returning its id. Fails if the name is taken or every id is used
+    pub fn register(&mut self, shape: Shape) -> Result<ShapeId> {
+        if self.ids.contains_key(&shape.name) {
+            return Err(Error::InvalidRegistry(format!(
+                "shape name \"{}\" is used more than once",
+                shape.name
+            )));
+        }
+        if self.shapes.len() > ShapeId::MAX as usize {
+            return Err(Error::InvalidRegistry(String::from("too many shapes")));
+        }
+        let id = self.shapes.len() as ShapeId;
+        self.ids.insert(shape.name.clone(), id);
+        self.shapes.push(shape);
+        Ok(id)
+    }
+
+    pub fn get(&self, id: ShapeId) -> Option<&Shape> {
+        self.shapes.get(id as usize)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<ShapeId> {
+        self.ids.get(name).copied()
+    }
+
+    /// If a voxel with the shape fills the whole of one face. Unknown shapes are treated
+    /// as full cubes
+    pub fn covers_face(&self, id: ShapeId, face: Face) -> bool {
+        self.get(id).is_none_or(|shape| shape.covers_face(face))
+    }
+}
+
+/// If a point lies inside a solid voxel, taking the voxel's shape into account
+pub fn solid_at<M: VoxelRead<Voxel>>(map: &M, registry: &VoxelRegistry, point: [f64; 3]) -> bool {
+    if point
+        .iter()
+        .any(|x| *x < i32::MIN as f64 || *x >= i32::MAX as f64)
+    {
+        return false;
+    }
+    let location = Point3D::new(
+        point[0].floor() as i32,
+        point[1].floor() as i32,
+        point[2].floor() as i32,
+    );
+    if !map.within_bounds(location) {
+        return false;
+    }
+    let voxel = map.get(location);
+    if !registry
+        .get(voxel.id)
+        .is_none_or(|voxel_type| voxel_type.solid)
+    {
+        return false;
+    }
+    match registry.shapes().get(voxel.shape) {
+        Some(shape) => {
+            let sub = |x: f64| {
+                (((x - x.floor()) * SHAPE_RESOLUTION as f64) as u32).min(SHAPE_RESOLUTION - 1)
+            };
+            shape.occupies(sub(point[0]), sub(point[1]), sub(point[2]))
+        }
+        None => true,
+    }
+}
+
+/// The solid boxes of a voxel, in world coordinates, for collision
+pub fn collision_boxes(
+    registry: &VoxelRegistry,
+    location: GlobalLocation,
+    voxel: &Voxel,
+) -> Vec<([f64; 3], [f64; 3])> {
+    if !registry
+        .get(voxel.id)
+        .is_none_or(|voxel_type| voxel_type.solid)
+    {
+        return Vec::new();
+    }
+    let origin = [location.x as f64, location.y as f64, location.z as f64];
+    let boxes = match registry.shapes().get(voxel.shape) {
+        Some(shape) => shape.boxes(),
+        None => vec![([0.0; 3], [1.0; 3])],
+    };
+    boxes
+        .into_iter()
+        .map(|(start, end)| {
+            (
+                [
+                    origin[0] + start[0] as f64,
+                    origin[1] + start[1] as f64,
+                    origin[2] + start[2] as f64,
+                ],
+                [
+                    origin[0] + end[0] as f64,
+                    origin[1] + end[1] as f64,
+                    origin[2] + end[2] as f64,
+                ],
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{
+    ChunkLocation, Dimension, GlobalLocation, Point3D, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE,
+};
+
+/// The highest opaque voxel of every column of the loaded world, kept up to date as voxels
+/// are written so sunlight and sky exposure don't have to scan columns
+#[derive(Clone, Default)]
+pub struct SkyColumns {
+    /// the highest loaded opaque voxel of each (x, y) column that has one
+    heights: HashMap<(i32, i32), i32>,
+    /// the z of every loaded chunk, for each column of chunks
+    chunk_columns: HashMap<(i32, i32), BTreeSet<i32>>,
+}
+
+impl SkyColumns {
+    pub fn new() -> SkyColumns {
+        SkyColumns::default()
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The z of the highest loaded opaque voxel in a column
+    pub fn highest_opaque(&self, x: i32, y: i32) -> Option<i32> {
+        self.sky.heights.get(&(x, y)).copied()
+    }
+
+    /// If nothing opaque is above the location. Chunks that aren't loaded are taken to be
+    /// open to the sky
+    pub fn is_exposed_to_sky(&self, location: GlobalLocation) -> bool {
+        self.highest_opaque(location.x, location.y)
+            .is_none_or(|height| height < location.z)
+    }
+
+    /// Accounts for a chunk being loaded
+    pub fn sky_chunk_added(&mut self, location: ChunkLocation) {
+        self.sky
+            .chunk_columns
+            .entry((location.x, location.y))
+            .or_default()
+            .insert(location.z);
+        self.rescan_chunk_columns(location);
+    }
+
+    /// Accounts for a chunk being unloaded
+    pub fn sky_chunk_removed(&mut self, location: ChunkLocation) {
+        if let Some(zs) = self.sky.chunk_columns.get_mut(&(location.x, location.y)) {
+            zs.remove(&location.z);
+            if zs.is_empty() {
+                self.sky.chunk_columns.remove(&(location.x, location.y));
+            }
+        }
+        self.rescan_chunk_columns(location);
+    }
+
+    /// Accounts for a voxel having been written
+    pub fn sky_voxel_written(&mut self, location: GlobalLocation, value: &T) {
+        let column = (location.x, location.y);
+        let height = self.sky.heights.get(&column).copied();
+        if value.is_opaque_in(self.registry()) {
+            if height.is_none_or(|height| height < location.z) {
+                self.sky.heights.insert(column, location.z);
+            }
+        } else if height == Some(location.z) {
+            self.rescan_column(location.x, location.y);
+        }
+    }
+
+    /// Finds the highest opaque voxel of every column of a chunk again
+    fn rescan_chunk_columns(&mut self, location: ChunkLocation) {
+        let bounds = Self::get_chunk_bounds(location);
+        for y in bounds.start.y..bounds.end.y {
+            for x in bounds.start.x..bounds.end.x {
+                self.rescan_column(x, y);
+            }
+        }
+    }
+
+    /// Finds the highest opaque voxel of a column again, looking only at loaded chunks
+    fn rescan_column(&mut self, x: i32, y: i32) {
+        let chunk_column = (
+            x.div_euclid(CHUNK_X_SIZE as i32),
+            y.div_euclid(CHUNK_Y_SIZE as i32),
+        );
+        let mut found = None;
+        if let Some(zs) = self.sky.chunk_columns.get(&chunk_column) {
+            'chunks: for chunk_z in zs.iter().rev() {
+                let chunk = match self.loaded_chunks.get(&ChunkLocation::new(
+                    chunk_column.0,
+                    chunk_column.1,
+                    *chunk_z,
+                )) {
+                    Some(chunk) => chunk,
+                    None => continue,
+                };
+                for z in (0..CHUNK_Z_SIZE as i32).rev() {
+                    let local = Point3D::new(
+                        x.rem_euclid(CHUNK_X_SIZE as i32),
+                        y.rem_euclid(CHUNK_Y_SIZE as i32),
+                        z,
+                    );
+                    if chunk.get(local).is_opaque_in(self.registry()) {
+                        found = Some(chunk_z * CHUNK_Z_SIZE as i32 + z);
+                        break 'chunks;
+                    }
+                }
+            }
+        }
+        match found {
+            Some(height) => self.sky.heights.insert((x, y), height),
+            None => self.sky.heights.remove(&(x, y)),
+        };
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use super::{Aabb, GlobalLocation, Point3D};
+
+/// Identifies an object stored in a spatial index
+pub type EntryId = u64;
+
+/// Tracks things that aren't voxels (structures, regions of interest, entity bounding boxes)
+/// by the box of world space they occupy. Boxes are hashed into a uniform grid of cells, so
+/// queries only look at objects near the queried location
+#[derive(Clone)]
+pub struct SpatialIndex<V> {
+    /// the edge length of a grid cell, in voxels
+    cell_size: u32,
+    /// the id that will be handed out to the next object
+    next_id: EntryId,
+    /// every object along with the box it occupies
+    entries: HashMap<EntryId, (Aabb, V)>,
+    /// the objects overlapping each grid cell
+    cells: HashMap<Point3D, Vec<EntryId>>,
+}
+
+impl<V> SpatialIndex<V> {
+    pub fn new(cell_size: u32) -> SpatialIndex<V> {
+        assert!(cell_size > 0, "cell size must be positive");
+        SpatialIndex {
+            cell_size,
+            next_id: 0,
+            entries: HashMap::new(),
+            cells: HashMap::new(),
+        }
+    }
+
+    /// The grid cells a box overlaps. Empty boxes are treated as a point at their start
+    fn cells_of(&self, bounds: Aabb) -> Vec<Point3D> {
+        let first = self.cell_of(bounds.start);
+        let last = if bounds.is_empty() {
+            first
+        } else {
+            self.cell_of(bounds.end - Point3D::new(1, 1, 1))
+        };
+        let mut cells = Vec::new();
+        for z in first.z..=last.z {
+            for y in first.y..=last.y {
+                for x in first.x..=last.x {
+                    cells.push(Point3D::new(x, y, z));
+                }
+            }
+        }
+        cells
+    }
+
+    fn cell_of(&self, location: GlobalLocation) -> Point3D {
+        let cell_size = self.cell_size as i32;
+        Point3D::new(
+            location.x.div_euclid(cell_size),
+            location.y.div_euclid(cell_size),
+            location.z.div_euclid(cell_size),
+        )
+    }
+
+    /// Adds an object occupying bounds, returning the id used to refer to it later
+    pub fn insert(&mut self, bounds: Aabb, value: V) -> EntryId {
+        let id = self.next_id;
+        self.next_id += 1;
+        for cell in self.cells_of(bounds) {
+            self.cells.entry(cell).or_default().push(id);
+        }
+        self.entries.insert(id, (bounds, value));
+        id
+    }
+
+    /// Removes an object, returning it if it existed
+    pub fn remove(&mut self, id: EntryId) -> Option<V> {
+        let (bounds, value) = self.entries.remove(&id)?;
+        for cell in self.cells_of(bounds) {
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|x| *x != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+        Some(value)
+    }
+
+    /// Moves an object to a new box. Returns false if there is no such object
+    pub fn update(&mut self, id: EntryId, bounds: Aabb) -> bool {
+        match self.remove(id) {
+            Some(value) => {
+                for cell in self.cells_of(bounds) {
+                    self.cells.entry(cell).or_default().push(id);
+                }
+                self.entries.insert(id, (bounds, value));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: EntryId) -> Option<&(Aabb, V)> {
+        self.entries.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: EntryId) -> Option<&mut V> {
+        self.entries.get_mut(&id).map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ids of every object, in no particular order
+    pub fn ids(&self) -> Vec<EntryId> {
+        self.entries.keys().copied().collect()
+    }
+
+    /// All objects whose box contains the location
+    pub fn query_point(&self, location: GlobalLocation) -> Vec<EntryId> {
+        match self.cells.get(&self.cell_of(location)) {
+            Some(ids) => ids
+                .iter()
+                .filter(|id| self.entries[*id].0.contains(location))
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// All objects whose box shares at least one voxel with bounds
+    pub fn query_box(&self, bounds: Aabb) -> Vec<EntryId> {
+        let mut found = HashSet::new();
+        for cell in self.cells_of(bounds) {
+            if let Some(ids) = self.cells.get(&cell) {
+                for id in ids {
+                    if self.entries[id].0.intersects(&bounds) {
+                        found.insert(*id);
+                    }
+                }
+            }
+        }
+        let mut found: Vec<EntryId> = found.into_iter().collect();
+        found.sort_unstable();
+        found
+    }
+
+    /// All objects hit by a ray, nearest first, along with the distance to where the ray
+    /// enters them. Distances are measured in multiples of the direction's length, and
+    /// objects further than max_distance are ignored. max_distance may be infinite, since
+    /// the walk stops once the ray has left the occupied cells. Rays with an origin or
+    /// direction that isn't finite, or a max_distance that is NaN, hit nothing
+    pub fn query_ray(
+        &self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        max_distance: f32,
+    ) -> Vec<(EntryId, f32)> {
+        if origin.iter().chain(&direction).any(|x| !x.is_finite()) || max_distance.is_nan() {
+            return Vec::new();
+        }
+        // the smallest box of cells holding every object
+        let mut occupied_min = [i64::MAX; 3];
+        let mut occupied_max = [i64::MIN; 3];
+        for key in self.cells.keys() {
+            for (axis, x) in [key.x, key.y, key.z].iter().enumerate() {
+                occupied_min[axis] = occupied_min[axis].min(*x as i64);
+                occupied_max[axis] = occupied_max[axis].max(*x as i64);
+            }
+        }
+        let cell_size = self.cell_size as f32;
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            cell[axis] = (origin[axis] / cell_size).floor() as i64;
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                t_max[axis] =
+                    ((cell[axis] + 1) as f32 * cell_size - origin[axis]) / direction[axis];
+                t_delta[axis] = cell_size / direction[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (cell[axis] as f32 * cell_size - origin[axis]) / direction[axis];
+                t_delta[axis] = -cell_size / direction[axis];
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut hits = Vec::new();
+        loop {
+            // walk the cells along the ray, testing every object in them
+            if cell
+                .iter()
+                .all(|x| *x >= i32::MIN as i64 && *x <= i32::MAX as i64)
+            {
+                let key = Point3D::new(cell[0] as i32, cell[1] as i32, cell[2] as i32);
+                for id in self.cells.get(&key).into_iter().flatten() {
+                    if seen.insert(*id) {
+                        if let Some(t) = ray_hit(&self.entries[id].0, origin, direction) {
+                            if t <= max_distance {
+                                hits.push((*id, t));
+                            }
+                        }
+                    }
+                }
+            }
+            // past the occupied cells and moving away from them, nothing more can be hit
+            if (0..3).any(|axis| {
+                (step[axis] >= 0 && cell[axis] > occupied_max[axis])
+                    || (step[axis] <= 0 && cell[axis] < occupied_min[axis])
+            }) {
+                break;
+            }
+            // advance along whichever axis reaches its next cell boundary first
+            let axis = (0..3)
+                .min_by(|a, b| t_max[*a].total_cmp(&t_max[*b]))
+                .unwrap();
+            if !t_max[axis].is_finite() || t_max[axis] > max_distance {
+                break;
+            }
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+        }
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        hits
+    }
+}
+
+/// Where a ray enters a box, if it hits it at all (slab test)
+fn ray_hit(bounds: &Aabb, origin: [f32; 3], direction: [f32; 3]) -> Option<f32> {
+    let start = [bounds.start.x, bounds.start.y, bounds.start.z];
+    let end = [bounds.end.x, bounds.end.y, bounds.end.z];
+    let mut t_enter = 0.0f32;
+    let mut t_exit = f32::INFINITY;
+    for axis in 0..3 {
+        let (low, high) = (start[axis] as f32, end[axis] as f32);
+        if direction[axis] == 0.0 {
+            if origin[axis] < low || origin[axis] > high {
+                return None;
+            }
+        } else {
+            let t1 = (low - origin[axis]) / direction[axis];
+            let t2 = (high - origin[axis]) / direction[axis];
+            t_enter = t_enter.max(t1.min(t2));
+            t_exit = t_exit.min(t1.max(t2));
+        }
+    }
+    if t_enter <= t_exit {
+        Some(t_enter)
+    } else {
+        None
+    }
+}
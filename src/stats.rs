@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::Dimension;
+
+/// How many voxels of each type have been placed and broken over the life of the world
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct WorldStats {
+    placed: BTreeMap<u32, u64>,
+    broken: BTreeMap<u32, u64>,
+}
+
+impl WorldStats {
+    pub fn new() -> WorldStats {
+        WorldStats::default()
+    }
+
+    /// Accounts for old being overwritten by new. Replacing an empty voxel places one,
+    /// emptying a voxel breaks one, and swapping one type for another does both
+    pub fn record<T: VoxelClass>(&mut self, old: &T, new: &T) {
+        if old.type_id() == new.type_id() {
+            return;
+        }
+        if !old.is_empty() {
+            *self.broken.entry(old.type_id()).or_default() += 1;
+        }
+        if !new.is_empty() {
+            *self.placed.entry(new.type_id()).or_default() += 1;
+        }
+    }
+
+    pub fn placed(&self, type_id: u32) -> u64 {
+        self.placed.get(&type_id).copied().unwrap_or(0)
+    }
+
+    pub fn broken(&self, type_id: u32) -> u64 {
+        self.broken.get(&type_id).copied().unwrap_or(0)
+    }
+
+    pub fn total_placed(&self) -> u64 {
+        self.placed.values().sum()
+    }
+
+    pub fn total_broken(&self) -> u64 {
+        self.broken.values().sum()
+    }
+
+    /// Every type placed at least once, with how many were placed
+    pub fn iter_placed(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.placed.iter().map(|(id, count)| (*id, *count))
+    }
+
+    /// Every type broken at least once, with how many were broken
+    pub fn iter_broken(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.broken.iter().map(|(id, count)| (*id, *count))
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for counts in [&self.placed, &self.broken].iter() {
+            out.write_u32::<LittleEndian>(counts.len() as u32)?;
+            for (id, count) in counts.iter() {
+                out.write_u32::<LittleEndian>(*id)?;
+                out.write_u64::<LittleEndian>(*count)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<WorldStats> {
+        let mut stats = WorldStats::new();
+        for counts in [&mut stats.placed, &mut stats.broken].iter_mut() {
+            for _ in 0..input.read_u32::<LittleEndian>()? {
+                let id = input.read_u32::<LittleEndian>()?;
+                counts.insert(id, input.read_u64::<LittleEndian>()?);
+            }
+        }
+        Ok(stats)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The placed and broken counters of the world, saved with its metadata
+    pub fn stats(&self) -> &WorldStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = WorldStats::new();
+    }
+}
@@ -0,0 +1,309 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::CHUNK_VOLUME;
+
+/// Chunks with more distinct voxels than this are stored densely
+pub const PALETTE_LIMIT: usize = 256;
+
+/// Tags identifying the storage of a chunk in its payload
+const UNIFORM_TAG: u8 = 0;
+const PALETTE_TAG: u8 = 1;
+const DENSE_TAG: u8 = 2;
+const PACKED_PALETTE_TAG: u8 = 3;
+const DENSE_RUNS_TAG: u8 = 4;
+
+/// How many bits a palette index takes when packed
+fn index_bits(palette_len: usize) -> usize {
+    ((usize::BITS - palette_len.saturating_sub(1).leading_zeros()) as usize).max(1)
+}
+
+/// Packs palette indices into bits bits each, lowest bit first
+fn pack_indices(indices: &[u8], bits: usize) -> Vec<u8> {
+    let mut packed = vec![0; (indices.len() * bits).div_ceil(8)];
+    for (i, index) in indices.iter().enumerate() {
+        for bit in 0..bits {
+            if index & (1 << bit) != 0 {
+                let position = i * bits + bit;
+                packed[position / 8] |= 1 << (position % 8);
+            }
+        }
+    }
+    packed
+}
+
+fn unpack_indices(packed: &[u8], bits: usize) -> Vec<u8> {
+    (0..CHUNK_VOLUME)
+        .map(|i| {
+            (0..bits).fold(0, |index, bit| {
+                let position = i * bits + bit;
+                index | (((packed[position / 8] >> (position % 8)) & 1) << bit)
+            })
+        })
+        .collect()
+}
+
+/// How the voxels of a chunk are held in memory. Most chunks are a handful of kinds of
+/// voxel, like all air or stone with some ore, so they are held as a palette of the
+/// distinct voxels and a byte per voxel pointing into it. Chunks that outgrow the palette
+/// are upgraded to a plain array
+#[derive(Clone)]
+pub enum ChunkStorage<T> {
+    /// Every voxel is the same
+    Uniform(T),
+    /// Distinct voxels, and the position in the palette of every voxel
+    Palette { palette: Vec<T>, indices: Box<[u8]> },
+    /// Every voxel, in index order
+    Dense(Box<[T]>),
+}
+
+impl<T: Copy + PartialEq + VoxelCodec> ChunkStorage<T> {
+    pub fn get(&self, index: usize) -> T {
+        match self {
+            ChunkStorage::Uniform(value) => *value,
+            ChunkStorage::Palette { palette, indices } => palette[indices[index] as usize],
+            ChunkStorage::Dense(voxels) => voxels[index],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        match self {
+            ChunkStorage::Uniform(current) => {
+                if *current != value {
+                    let mut indices = vec![0; CHUNK_VOLUME].into_boxed_slice();
+                    indices[index] = 1;
+                    *self = ChunkStorage::Palette {
+                        palette: vec![*current, value],
+                        indices,
+                    };
+                }
+                return;
+            }
+            ChunkStorage::Palette { palette, indices } => {
+                if let Some(entry) = palette.iter().position(|entry| *entry == value) {
+                    indices[index] = entry as u8;
+                    return;
+                }
+                if palette.len() < PALETTE_LIMIT {
+                    indices[index] = palette.len() as u8;
+                    palette.push(value);
+                    return;
+                }
+            }
+            ChunkStorage::Dense(voxels) => {
+                voxels[index] = value;
+                return;
+            }
+        }
+        // the palette is full, so it either has room after dropping unused entries or
+        // the chunk is too diverse for one
+        self.compact_palette();
+        if self.palette_len() == Some(PALETTE_LIMIT) {
+            *self = ChunkStorage::Dense(self.to_vec().into_boxed_slice());
+        }
+        self.set(index, value);
+    }
+
+    fn palette_len(&self) -> Option<usize> {
+        match self {
+            ChunkStorage::Palette { palette, .. } => Some(palette.len()),
+            _ => None,
+        }
+    }
+
+    /// Drops the palette entries no voxel points to anymore
+    fn compact_palette(&mut self) {
+        if let ChunkStorage::Palette { palette, indices } = self {
+            let mut remap = vec![None; palette.len()];
+            let mut compacted = Vec::new();
+            for index in indices.iter_mut() {
+                let entry = *remap[*index as usize].get_or_insert_with(|| {
+                    compacted.push(palette[*index as usize]);
+                    compacted.len() - 1
+                });
+                *index = entry as u8;
+            }
+            *palette = compacted;
+        }
+    }
+
+    /// Every voxel, in index order
+    pub fn to_vec(&self) -> Vec<T> {
+        (0..CHUNK_VOLUME).map(|index| self.get(index)).collect()
+    }
+
+    /// Switches to the most compact storage that holds the voxels, e.g. after a dense chunk
+    /// was mostly overwritten with air
+    pub fn optimize(&mut self) {
+        let voxels = self.to_vec();
+        let mut optimized = ChunkStorage::Uniform(voxels[0]);
+        for (index, value) in voxels.into_iter().enumerate() {
+            optimized.set(index, value);
+        }
+        optimized.compact_palette();
+        if let ChunkStorage::Palette { palette, .. } = &optimized {
+            if palette.len() == 1 {
+                optimized = ChunkStorage::Uniform(palette[0]);
+            }
+        }
+        *self = optimized;
+    }
+
+    /// Optimizes the storage and orders the palette by the voxels' encodings, so the same
+    /// voxels are always stored, and written, the same way
+    pub fn canonicalize(&mut self) {
+        self.optimize();
+        if let ChunkStorage::Palette { palette, indices } = self {
+            // writing into a Vec can't fail
+            let encoded: Vec<Vec<u8>> = palette
+                .iter()
+                .map(|value| {
+                    let mut bytes = Vec::new();
+                    value.encode(&mut bytes).unwrap();
+                    bytes
+                })
+                .collect();
+            let mut order: Vec<usize> = (0..palette.len()).collect();
+            order.sort_by(|a, b| encoded[*a].cmp(&encoded[*b]));
+            let mut remap = vec![0; palette.len()];
+            for (entry, old) in order.iter().enumerate() {
+                remap[*old] = entry as u8;
+            }
+            *palette = order.iter().map(|old| palette[*old]).collect();
+            for index in indices.iter_mut() {
+                *index = remap[*index as usize];
+            }
+        }
+    }
+
+    /// Roughly how many bytes the voxels take in memory
+    pub fn memory_size(&self) -> usize {
+        let voxel = std::mem::size_of::<T>();
+        match self {
+            ChunkStorage::Uniform(_) => voxel,
+            ChunkStorage::Palette { palette, indices } => palette.len() * voxel + indices.len(),
+            ChunkStorage::Dense(voxels) => voxels.len() * voxel,
+        }
+    }
+
+    /// Writes the voxels in their storage's form, in whichever encoding comes out smallest
+    /// for them, tagged so it can be read back. Palette indices are run length encoded when
+    /// neighboring voxels are mostly the same, and bit packed when they're noisy. Dense
+    /// voxels are run length encoded only if that saves space
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            ChunkStorage::Uniform(value) => {
+                out.write_u8(UNIFORM_TAG)?;
+                value.encode(out)
+            }
+            ChunkStorage::Palette { palette, indices } => {
+                let runs = indices.chunk_by(|a, b| a == b).count();
+                let bits = index_bits(palette.len());
+                let packed = (indices.len() * bits).div_ceil(8) < runs * 3;
+                out.write_u8(if packed {
+                    PACKED_PALETTE_TAG
+                } else {
+                    PALETTE_TAG
+                })?;
+                out.write_u16::<LittleEndian>(palette.len() as u16)?;
+                for value in palette.iter() {
+                    value.encode(out)?;
+                }
+                if packed {
+                    return out.write_all(&pack_indices(indices, bits));
+                }
+                let mut runs = indices.chunk_by(|a, b| a == b);
+                runs.try_for_each(|run| {
+                    out.write_u16::<LittleEndian>(run.len() as u16)?;
+                    out.write_u8(run[0])
+                })
+            }
+            ChunkStorage::Dense(voxels) => {
+                // writing into a Vec can't fail
+                let mut raw = Vec::new();
+                voxels
+                    .iter()
+                    .try_for_each(|value| value.encode(&mut raw))
+                    .unwrap();
+                let mut runs = Vec::new();
+                voxels
+                    .chunk_by(|a, b| a == b)
+                    .try_for_each(|run| {
+                        runs.write_u16::<LittleEndian>(run.len() as u16)?;
+                        run[0].encode(&mut runs)
+                    })
+                    .unwrap();
+                if runs.len() < raw.len() {
+                    out.write_u8(DENSE_RUNS_TAG)?;
+                    out.write_all(&runs)
+                } else {
+                    out.write_u8(DENSE_TAG)?;
+                    out.write_all(&raw)
+                }
+            }
+        }
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<ChunkStorage<T>> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        match input.read_u8()? {
+            UNIFORM_TAG => Ok(ChunkStorage::Uniform(T::decode(input)?)),
+            tag @ (PALETTE_TAG | PACKED_PALETTE_TAG) => {
+                let count = input.read_u16::<LittleEndian>()? as usize;
+                if count == 0 || count > PALETTE_LIMIT {
+                    return Err(invalid("bad chunk palette size"));
+                }
+                let palette = (0..count)
+                    .map(|_| T::decode(input))
+                    .collect::<io::Result<Vec<T>>>()?;
+                let indices = if tag == PACKED_PALETTE_TAG {
+                    let bits = index_bits(count);
+                    let mut packed = vec![0; (CHUNK_VOLUME * bits).div_ceil(8)];
+                    input.read_exact(&mut packed)?;
+                    let indices = unpack_indices(&packed, bits);
+                    if indices.iter().any(|entry| *entry as usize >= count) {
+                        return Err(invalid("bad chunk palette index"));
+                    }
+                    indices
+                } else {
+                    let mut indices = Vec::with_capacity(CHUNK_VOLUME);
+                    while indices.len() < CHUNK_VOLUME {
+                        let run = input.read_u16::<LittleEndian>()? as usize;
+                        let entry = input.read_u8()?;
+                        if run == 0 || indices.len() + run > CHUNK_VOLUME || entry as usize >= count
+                        {
+                            return Err(invalid("bad chunk palette run"));
+                        }
+                        indices.resize(indices.len() + run, entry);
+                    }
+                    indices
+                };
+                Ok(ChunkStorage::Palette {
+                    palette,
+                    indices: indices.into_boxed_slice(),
+                })
+            }
+            DENSE_TAG => Ok(ChunkStorage::Dense(
+                (0..CHUNK_VOLUME)
+                    .map(|_| T::decode(input))
+                    .collect::<io::Result<Vec<T>>>()?
+                    .into_boxed_slice(),
+            )),
+            DENSE_RUNS_TAG => {
+                let mut voxels = Vec::with_capacity(CHUNK_VOLUME);
+                while voxels.len() < CHUNK_VOLUME {
+                    let run = input.read_u16::<LittleEndian>()? as usize;
+                    let value = T::decode(input)?;
+                    if run == 0 || voxels.len() + run > CHUNK_VOLUME {
+                        return Err(invalid("bad chunk voxel run"));
+                    }
+                    voxels.resize(voxels.len() + run, value);
+                }
+                Ok(ChunkStorage::Dense(voxels.into_boxed_slice()))
+            }
+            _ => Err(invalid("unknown chunk storage")),
+        }
+    }
+}
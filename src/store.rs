@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::codec::VoxelCodec;
+use super::error::{ErrorContext, Operation, Result, ResultExt};
+use super::occupancy::VoxelClass;
+use super::{Chunk, ChunkLocation, Dimension};
+
+/// Somewhere chunks that aren't in the disk cache can be fetched from, like a shared
+/// world on a server. Chunks are exchanged as compressed payloads
+pub trait ChunkStore: Send + Sync {
+    /// The compressed payload of a chunk, None if the store doesn't have it
+    fn fetch(&self, location: ChunkLocation) -> Result<Option<Vec<u8>>>;
+}
+
+/// The name a chunk is stored under in a store
+pub fn chunk_file_name(location: ChunkLocation) -> String {
+    format!("{}.{}.{}.chunk", location.x, location.y, location.z)
+}
+
+/// Chunks kept one file per chunk in a folder, the layout remote stores are served from
+#[derive(Clone, Debug)]
+pub struct FolderStore {
+    folder: PathBuf,
+}
+
+impl FolderStore {
+    pub fn new<P: AsRef<Path>>(folder: P) -> FolderStore {
+        FolderStore {
+            folder: folder.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    pub fn store(&self, location: ChunkLocation, payload: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.folder)?;
+        // written under another name first so readers never see half a chunk
+        let path = self.folder.join(chunk_file_name(location));
+        let partial = path.with_extension("partial");
+        fs::write(&partial, payload)?;
+        fs::rename(partial, path)?;
+        Ok(())
+    }
+}
+
+impl ChunkStore for FolderStore {
+    fn fetch(&self, location: ChunkLocation) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.folder.join(chunk_file_name(location))) {
+            Ok(payload) => Ok(Some(payload)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Fetches chunks over http(s) from a folder store served at a url, keeping every fetched
+/// chunk in a local folder so it is only downloaded once
+#[cfg(feature = "remote")]
+pub struct RemoteStore {
+    base_url: String,
+    cache: FolderStore,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "remote")]
+impl RemoteStore {
+    pub fn new<P: AsRef<Path>>(base_url: &str, cache_folder: P) -> RemoteStore {
+        RemoteStore {
+            base_url: String::from(base_url.trim_end_matches('/')),
+            cache: FolderStore::new(cache_folder),
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+impl ChunkStore for RemoteStore {
+    fn fetch(&self, location: ChunkLocation) -> Result<Option<Vec<u8>>> {
+        use std::io::Read;
+
+        use super::error::Error;
+
+        if let Some(payload) = self.cache.fetch(location)? {
+            return Ok(Some(payload));
+        }
+        let url = format!("{}/{}", self.base_url, chunk_file_name(location));
+        let response = match self.agent.get(&url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => return Err(Error::Remote(err.to_string())),
+        };
+        let mut payload = Vec::new();
+        response.into_reader().read_to_end(&mut payload)?;
+        self.cache.store(location, &payload)?;
+        Ok(Some(payload))
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Fetches chunks missing from the disk cache from a store
+    pub fn set_chunk_store(&mut self, store: Arc<dyn ChunkStore>) {
+        self.chunk_store = Some(store);
+    }
+
+    pub fn clear_chunk_store(&mut self) {
+        self.chunk_store = None;
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// A chunk from the chunk store, None if there is no store or it doesn't have the
+    /// chunk. Chunks that aren't saved locally are fetched this way when first loaded
+    pub fn fetch_from_store(&self, location: ChunkLocation) -> Result<Option<Chunk<T>>> {
+        let store = match &self.chunk_store {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+        let fetched = store
+            .fetch(location)
+            .and_then(|compressed| match compressed {
+                Some(compressed) => {
+                    let payload = self.decompress_payload(&compressed)?;
+                    Ok(Some(Chunk::decode_payload(&payload)?))
+                }
+                None => Ok(None),
+            });
+        fetched.with_context(|| ErrorContext::new(Operation::FetchChunk).chunk(location))
+    }
+
+    /// Loads a chunk from the chunk store if it isn't loaded already. Returns false if
+    /// there is no store or it doesn't have the chunk
+    pub fn fetch_chunk(&mut self, location: ChunkLocation) -> Result<bool> {
+        if self.chunk_loaded(location) {
+            return Ok(true);
+        }
+        match self.fetch_from_store(location)? {
+            Some(chunk) => {
+                self.add_chunk_in_place(location, chunk);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Fetches whichever of the chunks aren't loaded, e.g. the ones around an anchor.
+    /// Returns how many were fetched
+    pub fn fetch_missing(&mut self, chunks: &[ChunkLocation]) -> Result<usize> {
+        let mut fetched = 0;
+        for location in chunks {
+            if !self.chunk_loaded(*location) && self.fetch_chunk(*location)? {
+                fetched += 1;
+            }
+        }
+        Ok(fetched)
+    }
+
+    /// Writes every defined chunk into a folder store, to be served to remote clients.
+    /// Chunks that aren't loaded are loaded to be written, and dropped again afterwards
+    pub fn publish_chunks(&mut self, store: &FolderStore) -> Result<()> {
+        let mut chunks: Vec<ChunkLocation> = self.all_chunk_locations.iter().copied().collect();
+        chunks.sort_by_key(|location| (location.z, location.y, location.x));
+        for location in chunks {
+            let was_loaded = self.chunk_loaded(location);
+            let payload = self.get_chunk(location)?.encode_payload()?;
+            self.compress_payload(&payload)
+                .and_then(|compressed| store.store(location, &compressed))
+                .with_context(|| ErrorContext::new(Operation::PublishChunk).chunk(location))?;
+            if !was_loaded && !self.chunk_dirty(location) {
+                self.discard_chunk(location);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::codec::VoxelCodec;
+use super::error::{Error, Result};
+use super::occupancy::VoxelClass;
+use super::Dimension;
+
+/// What the dimension does when reading or writing the disk cache fails
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StoreFailurePolicy {
+    /// The error is returned right away
+    FailFast,
+    /// The operation is tried again up to attempts more times, waiting backoff before the
+    /// first retry and twice as long before each one after, and the last error is returned
+    Retry { attempts: u32, backoff: Duration },
+    /// Failed writes are dropped with a StoreEvent::Degraded warning, leaving the chunks
+    /// dirty in memory to be written once the disk works again. Reads can't be made up for,
+    /// so failed reads are still returned
+    MemoryOnly,
+}
+
+/// What listeners are told about store failures
+#[derive(Debug)]
+pub enum StoreEvent<'a> {
+    /// An operation failed and will be tried again
+    Retrying { attempt: u32, error: &'a Error },
+    /// A write failed and the dimension carries on in memory only
+    Degraded { error: &'a Error },
+    /// A write worked again after the dimension was degraded
+    Recovered,
+}
+
+/// Called on every store event, e.g. to log them or alert someone
+pub type StoreListener = Arc<dyn Fn(&StoreEvent) + Send + Sync>;
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    pub fn set_store_failure_policy(&mut self, policy: StoreFailurePolicy) {
+        self.store_failure_policy = policy;
+    }
+
+    pub fn store_failure_policy(&self) -> StoreFailurePolicy {
+        self.store_failure_policy
+    }
+
+    pub fn set_store_listener(&mut self, listener: StoreListener) {
+        self.store_listener = Some(listener);
+    }
+
+    pub fn clear_store_listener(&mut self) {
+        self.store_listener = None;
+    }
+
+    /// If writes to the disk cache are failing and the world only lives in memory
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    fn emit_store_event(&self, event: StoreEvent) {
+        if let Some(listener) = &self.store_listener {
+            listener(&event);
+        }
+    }
+
+    /// Runs an operation reading the disk cache under the failure policy
+    pub fn store_read<R, F: FnMut(&mut Self) -> Result<R>>(&mut self, op: F) -> Result<R> {
+        self.with_retries(op)
+    }
+
+    /// Runs an operation writing the disk cache under the failure policy. Returns false if
+    /// the write failed and was dropped because the dimension degraded to memory only
+    pub fn store_write<F: FnMut(&mut Self) -> Result<()>>(&mut self, mut op: F) -> Result<bool> {
+        if self.store_failure_policy != StoreFailurePolicy::MemoryOnly {
+            return self.with_retries(op).map(|_| true);
+        }
+        match op(self) {
+            Ok(()) => {
+                if self.degraded {
+                    self.degraded = false;
+                    self.emit_store_event(StoreEvent::Recovered);
+                }
+                Ok(true)
+            }
+            Err(error) => {
+                self.degraded = true;
+                self.emit_store_event(StoreEvent::Degraded { error: &error });
+                Ok(false)
+            }
+        }
+    }
+
+    fn with_retries<R, F: FnMut(&mut Self) -> Result<R>>(&mut self, mut op: F) -> Result<R> {
+        let (attempts, mut backoff) = match self.store_failure_policy {
+            StoreFailurePolicy::Retry { attempts, backoff } => (attempts, backoff),
+            _ => return op(self),
+        };
+        let mut attempt = 0;
+        loop {
+            match op(self) {
+                Err(error) if attempt < attempts => {
+                    attempt += 1;
+                    self.emit_store_event(StoreEvent::Retrying {
+                        attempt,
+                        error: &error,
+                    });
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
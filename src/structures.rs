@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::spatial_index::{EntryId, SpatialIndex};
+use super::{Aabb, Dimension, GlobalLocation, Point3D, Volume};
+
+/// Identifies a structure template in a library
+pub type StructureId = u32;
+
+/// Identifies a placed structure
+pub type PlacementId = EntryId;
+
+/// The size of the cells placements are bucketed into
+const PLACEMENT_CELL_SIZE: u32 = 64;
+
+/// How a template is turned before it is placed. The template is mirrored along x first,
+/// then turned counterclockwise around the z axis
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Transform {
+    /// counterclockwise quarter turns, 0 to 3
+    pub quarter_turns: u8,
+    pub mirror_x: bool,
+}
+
+impl Transform {
+    /// The size of a template of the given size once transformed
+    pub fn placed_size(self, size: Point3D) -> Point3D {
+        if self.quarter_turns % 2 == 1 {
+            Point3D::new(size.y, size.x, size.z)
+        } else {
+            size
+        }
+    }
+
+    /// Where a location inside the placed structure, relative to its origin, comes from in
+    /// a template of the given size
+    pub fn to_template(self, local: Point3D, size: Point3D) -> Point3D {
+        let (u, v) = (local.x, local.y);
+        let (x, y) = match self.quarter_turns % 4 {
+            0 => (u, v),
+            1 => (v, size.y - 1 - u),
+            2 => (size.x - 1 - u, size.y - 1 - v),
+            _ => (size.x - 1 - v, u),
+        };
+        let x = if self.mirror_x { size.x - 1 - x } else { x };
+        Point3D::new(x, y, local.z)
+    }
+
+    fn to_byte(self) -> u8 {
+        (self.quarter_turns % 4) | ((self.mirror_x as u8) << 2)
+    }
+
+    fn from_byte(byte: u8) -> Transform {
+        Transform {
+            quarter_turns: byte & 3,
+            mirror_x: byte & 4 != 0,
+        }
+    }
+}
+
+/// A template stored in the library, along with how many placements use it
+#[derive(Clone)]
+struct Template<T> {
+    voxels: Volume<T>,
+    references: usize,
+}
+
+/// Where one copy of a template stands in the world
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Placement {
+    pub structure: StructureId,
+    /// the lowest corner of the placed structure
+    pub origin: GlobalLocation,
+    pub transform: Transform,
+}
+
+/// Structures that repeat across the world, like trees or buildings, stored once as
+/// templates and referenced by every placement, so huge numbers of copies take almost no
+/// space. Templates are counted by how many placements use them and can only be removed
+/// once none do
+#[derive(Clone)]
+pub struct StructureLibrary<T> {
+    next_id: StructureId,
+    templates: BTreeMap<StructureId, Template<T>>,
+    placements: SpatialIndex<Placement>,
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> StructureLibrary<T> {
+    pub fn new() -> StructureLibrary<T> {
+        StructureLibrary {
+            next_id: 0,
+            templates: BTreeMap::new(),
+            placements: SpatialIndex::new(PLACEMENT_CELL_SIZE),
+        }
+    }
+
+    /// Stores a template, returning the id placements refer to it by. Empty voxels of the
+    /// template don't cover what is underneath when placed
+    pub fn add_template(&mut self, voxels: Volume<T>) -> StructureId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.templates.insert(
+            id,
+            Template {
+                voxels,
+                references: 0,
+            },
+        );
+        id
+    }
+
+    /// Removes a template, unless placements still use it
+    pub fn remove_template(&mut self, id: StructureId) -> bool {
+        match self.templates.get(&id) {
+            Some(template) if template.references == 0 => {
+                self.templates.remove(&id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes every template no placement uses, returning how many were removed
+    pub fn prune_templates(&mut self) -> usize {
+        let before = self.templates.len();
+        self.templates.retain(|_, template| template.references > 0);
+        before - self.templates.len()
+    }
+
+    pub fn template(&self, id: StructureId) -> Option<&Volume<T>> {
+        self.templates.get(&id).map(|template| &template.voxels)
+    }
+
+    /// How many placements use a template
+    pub fn references(&self, id: StructureId) -> usize {
+        self.templates
+            .get(&id)
+            .map_or(0, |template| template.references)
+    }
+
+    fn template_size(&self, id: StructureId) -> Option<Point3D> {
+        self.template(id).map(|voxels| {
+            let (start, end) = (voxels.start_location, voxels.end_location);
+            end - start
+        })
+    }
+
+    /// Places a copy of a template, or None if there is no such template
+    pub fn place(&mut self, placement: Placement) -> Option<PlacementId> {
+        let size = placement
+            .transform
+            .placed_size(self.template_size(placement.structure)?);
+        self.templates.get_mut(&placement.structure)?.references += 1;
+        Some(self.placements.insert(
+            Aabb::new(placement.origin, placement.origin + size),
+            placement,
+        ))
+    }
+
+    pub fn unplace(&mut self, id: PlacementId) -> Option<Placement> {
+        let placement = self.placements.remove(id)?;
+        if let Some(template) = self.templates.get_mut(&placement.structure) {
+            template.references -= 1;
+        }
+        Some(placement)
+    }
+
+    pub fn placement(&self, id: PlacementId) -> Option<&Placement> {
+        self.placements.get(id).map(|(_, placement)| placement)
+    }
+
+    /// The voxel placed structures put at location, if any. Where placements overlap, the
+    /// latest one that isn't empty there wins
+    pub fn voxel_at(&self, location: GlobalLocation) -> Option<T> {
+        let mut ids = self.placements.query_point(location);
+        ids.sort_unstable();
+        ids.into_iter().rev().find_map(|id| {
+            let (_, placement) = self.placements.get(id)?;
+            let voxels = self.template(placement.structure)?;
+            let size = voxels.end_location - voxels.start_location;
+            let local = placement
+                .transform
+                .to_template(location - placement.origin, size);
+            let voxel = voxels.get(voxels.start_location + local);
+            if voxel.is_empty() {
+                None
+            } else {
+                Some(voxel)
+            }
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Templates are written voxel by voxel, placements as just their template id, origin
+    /// and transform
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u32::<LittleEndian>(self.next_id)?;
+        out.write_u32::<LittleEndian>(self.templates.len() as u32)?;
+        for (id, template) in self.templates.iter() {
+            out.write_u32::<LittleEndian>(*id)?;
+            write_point(out, template.voxels.start_location)?;
+            write_point(out, template.voxels.end_location)?;
+            for voxel in template.voxels.voxels.iter() {
+                voxel.encode(out)?;
+            }
+        }
+        let mut ids = self.placements.ids();
+        // in placement order, so overlapping placements resolve the same once read back
+        ids.sort_unstable();
+        out.write_u32::<LittleEndian>(ids.len() as u32)?;
+        for id in ids {
+            let placement = self.placement(id).unwrap();
+            out.write_u32::<LittleEndian>(placement.structure)?;
+            write_point(out, placement.origin)?;
+            out.write_u8(placement.transform.to_byte())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<StructureLibrary<T>> {
+        let mut library = StructureLibrary::new();
+        library.next_id = input.read_u32::<LittleEndian>()?;
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let id = input.read_u32::<LittleEndian>()?;
+            let start = read_point(input)?;
+            let end = read_point(input)?;
+            let mut voxels = Volume::new(start, end, T::default());
+            for voxel in voxels.voxels.iter_mut() {
+                *voxel = T::decode(input)?;
+            }
+            library.templates.insert(
+                id,
+                Template {
+                    voxels,
+                    references: 0,
+                },
+            );
+        }
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let placement = Placement {
+                structure: input.read_u32::<LittleEndian>()?,
+                origin: read_point(input)?,
+                transform: Transform::from_byte(input.read_u8()?),
+            };
+            if library.place(placement).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "placement of a missing structure template",
+                ));
+            }
+        }
+        Ok(library)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    pub fn structures(&self) -> &StructureLibrary<T> {
+        &self.structures
+    }
+
+    pub fn structures_mut(&mut self) -> &mut StructureLibrary<T> {
+        &mut self.structures
+    }
+
+    /// Makes voxel reads see placed structures over the chunks' own voxels, so structures
+    /// can stand in chunks that were never defined. Writes still go to the chunks only
+    pub fn set_resolve_structures(&mut self, resolve: bool) {
+        self.resolve_structures = resolve;
+    }
+
+    pub fn resolves_structures(&self) -> bool {
+        self.resolve_structures
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use super::registry::VoxelRegistry;
+use super::traversal::{ConditionalPassage, TraversalRules};
+use super::{Aabb, GlobalLocation, Point3D, Voxel, VoxelRead, VoxelWrite};
+
+/// A set of voxel types picked by their tags, so code can ask "is this an ore" instead of
+/// keeping its own list of ids. Sets are resolved against a registry once and are cheap
+/// to test against afterwards
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct VoxelTagSet {
+    ids: HashSet<u32>,
+}
+
+impl VoxelTagSet {
+    /// A set matching no voxels
+    pub fn new() -> VoxelTagSet {
+        VoxelTagSet::default()
+    }
+
+    pub fn from_ids<I: IntoIterator<Item = u32>>(ids: I) -> VoxelTagSet {
+        VoxelTagSet {
+            ids: ids.into_iter().collect(),
+        }
+    }
+
+    /// Types carrying at least one of the tags
+    pub fn any_of(registry: &VoxelRegistry, tags: &[&str]) -> VoxelTagSet {
+        VoxelTagSet::from_ids(
+            registry
+                .iter()
+                .filter(|voxel_type| tags.iter().any(|tag| voxel_type.tags.contains(*tag)))
+                .map(|voxel_type| voxel_type.id),
+        )
+    }
+
+    /// Types carrying every one of the tags
+    pub fn all_of(registry: &VoxelRegistry, tags: &[&str]) -> VoxelTagSet {
+        VoxelTagSet::from_ids(
+            registry
+                .iter()
+                .filter(|voxel_type| tags.iter().all(|tag| voxel_type.tags.contains(*tag)))
+                .map(|voxel_type| voxel_type.id),
+        )
+    }
+
+    /// Types in the registry that aren't in this set
+    pub fn complement(&self, registry: &VoxelRegistry) -> VoxelTagSet {
+        VoxelTagSet::from_ids(
+            registry
+                .iter()
+                .map(|voxel_type| voxel_type.id)
+                .filter(|id| !self.ids.contains(id)),
+        )
+    }
+
+    pub fn union(&self, other: &VoxelTagSet) -> VoxelTagSet {
+        VoxelTagSet {
+            ids: self.ids.union(&other.ids).copied().collect(),
+        }
+    }
+
+    pub fn intersection(&self, other: &VoxelTagSet) -> VoxelTagSet {
+        VoxelTagSet {
+            ids: self.ids.intersection(&other.ids).copied().collect(),
+        }
+    }
+
+    pub fn difference(&self, other: &VoxelTagSet) -> VoxelTagSet {
+        VoxelTagSet {
+            ids: self.ids.difference(&other.ids).copied().collect(),
+        }
+    }
+
+    pub fn contains_id(&self, id: u32) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn matches(&self, voxel: &Voxel) -> bool {
+        self.ids.contains(&voxel.id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.ids.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl TraversalRules {
+    /// Registers the same conditional passage for every type in the set
+    pub fn register_conditional_set(&mut self, set: &VoxelTagSet, passage: ConditionalPassage) {
+        for id in set.ids() {
+            self.register_conditional(id, passage);
+        }
+    }
+}
+
+/// Every location in the region holding a voxel in the set
+pub fn find_matching<M: VoxelRead<Voxel>>(
+    map: &M,
+    region: Aabb,
+    set: &VoxelTagSet,
+) -> Vec<GlobalLocation> {
+    let mut found = Vec::new();
+    for z in region.start.z..region.end.z {
+        for y in region.start.y..region.end.y {
+            for x in region.start.x..region.end.x {
+                let location = Point3D::new(x, y, z);
+                if map.within_bounds(location) && set.matches(&map.get(location)) {
+                    found.push(location);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Overwrites every voxel in the region that is in the set, like filling a box with stone
+/// while only replacing "replaceable" voxels. Returns how many voxels were written
+pub fn fill_matching<M: VoxelWrite<Voxel>>(
+    map: &mut M,
+    region: Aabb,
+    set: &VoxelTagSet,
+    value: Voxel,
+) -> usize {
+    let found = find_matching(map, region, set);
+    for location in found.iter() {
+        map.set(*location, value);
+    }
+    found.len()
+}
+
+/// Overwrites the voxels in the set that are connected to start through faces, stopping
+/// after limit voxels. Returns how many voxels were written
+pub fn flood_fill<M: VoxelWrite<Voxel>>(
+    map: &mut M,
+    start: GlobalLocation,
+    set: &VoxelTagSet,
+    value: Voxel,
+    limit: usize,
+) -> usize {
+    if !map.within_bounds(start) || !set.matches(&map.get(start)) {
+        return 0;
+    }
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(start);
+    queue.push_back(start);
+    let mut filled = 0;
+    while let Some(location) = queue.pop_front() {
+        if filled == limit {
+            break;
+        }
+        map.set(location, value);
+        filled += 1;
+        let neighbors = [
+            location
+                .x
+                .checked_sub(1)
+                .map(|x| Point3D::new(x, location.y, location.z)),
+            location
+                .x
+                .checked_add(1)
+                .map(|x| Point3D::new(x, location.y, location.z)),
+            location
+                .y
+                .checked_sub(1)
+                .map(|y| Point3D::new(location.x, y, location.z)),
+            location
+                .y
+                .checked_add(1)
+                .map(|y| Point3D::new(location.x, y, location.z)),
+            location
+                .z
+                .checked_sub(1)
+                .map(|z| Point3D::new(location.x, location.y, z)),
+            location
+                .z
+                .checked_add(1)
+                .map(|z| Point3D::new(location.x, location.y, z)),
+        ];
+        for neighbor in neighbors.iter().flatten().copied() {
+            if map.within_bounds(neighbor)
+                && !visited.contains(&neighbor)
+                && set.matches(&map.get(neighbor))
+            {
+                visited.insert(neighbor);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    filled
+}
@@ -0,0 +1,126 @@
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Aabb, Dimension, Point3D, Volume, VoxelRead};
+
+/// The height of the ground in a column: one above its highest solid voxel from z_start up
+/// to z_end. None if the column holds nothing solid there
+pub fn surface_height<T: VoxelClass, M: VoxelRead<T> + ?Sized>(
+    map: &M,
+    x: i32,
+    y: i32,
+    z_start: i32,
+    z_end: i32,
+) -> Option<f32> {
+    (z_start..z_end).rev().find_map(|z| {
+        let location = Point3D::new(x, y, z);
+        if map.within_bounds(location) && map.get(location).is_solid() {
+            Some((z + 1) as f32)
+        } else {
+            None
+        }
+    })
+}
+
+/// The steepness of the ground of every column of region, in degrees from flat, laid out
+/// as a volume one voxel deep at region's bottom. Heights are taken from the solid voxels
+/// between region's z bounds, and compared with the neighboring columns on each side, one
+/// sided at gaps. Columns without ground are NaN, and an empty region gives an empty volume
+pub fn slope_map<T: VoxelClass, M: VoxelRead<T> + ?Sized>(map: &M, region: Aabb) -> Volume<f32> {
+    let (start, end) = (region.start, region.end);
+    if region.is_empty() {
+        return Volume::new(start, start, f32::NAN);
+    }
+    let mut slopes = Volume::new(start, Point3D::new(end.x, end.y, start.z + 1), f32::NAN);
+    // the heights of the columns of region and the ring of columns around it
+    let width = (end.x - start.x + 2) as usize;
+    let heights: Vec<Option<f32>> = (start.y - 1..end.y + 1)
+        .flat_map(|y| {
+            (start.x - 1..end.x + 1).map(move |x| surface_height(map, x, y, start.z, end.z))
+        })
+        .collect();
+    let height =
+        |x: i32, y: i32| heights[(y - start.y + 1) as usize * width + (x - start.x + 1) as usize];
+    // the change in height per voxel from low to high, whichever of them have ground
+    let gradient = |low: Option<f32>, center: f32, high: Option<f32>| match (low, high) {
+        (Some(low), Some(high)) => (high - low) / 2.0,
+        (Some(low), None) => center - low,
+        (None, Some(high)) => high - center,
+        (None, None) => 0.0,
+    };
+    for y in start.y..end.y {
+        for x in start.x..end.x {
+            let center = match height(x, y) {
+                Some(center) => center,
+                None => continue,
+            };
+            let dx = gradient(height(x - 1, y), center, height(x + 1, y));
+            let dy = gradient(height(x, y - 1), center, height(x, y + 1));
+            let slope = (dx * dx + dy * dy).sqrt().atan().to_degrees();
+            slopes.set(Point3D::new(x, y, start.z), slope);
+        }
+    }
+    slopes
+}
+
+/// The outward normal of every solid voxel of region that borders open space, pointing away
+/// from the solid voxels around it. Each normal sums the directions to the open voxels among
+/// the 26 around it, so slopes and overhangs come out smoother than the voxel faces. Other
+/// voxels, and voxels whose neighbors are all solid, get a zero vector. Voxels that can't
+/// be read don't count either way
+pub fn estimate_surface_normals<T: VoxelClass, M: VoxelRead<T> + ?Sized>(
+    map: &M,
+    region: Aabb,
+) -> Volume<[f32; 3]> {
+    let mut normals = Volume::new(region.start, region.end, [0.0; 3]);
+    for z in region.start.z..region.end.z {
+        for y in region.start.y..region.end.y {
+            for x in region.start.x..region.end.x {
+                let location = Point3D::new(x, y, z);
+                if !map.within_bounds(location) || !map.get(location).is_solid() {
+                    continue;
+                }
+                let mut sum = [0.0f32; 3];
+                for dz in -1..=1 {
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let neighbor = location + Point3D::new(dx, dy, dz);
+                            if neighbor == location
+                                || !map.within_bounds(neighbor)
+                                || map.get(neighbor).is_solid()
+                            {
+                                continue;
+                            }
+                            // farther neighbors pull less, so edges don't outweigh faces
+                            let weight = 1.0 / ((dx * dx + dy * dy + dz * dz) as f32).sqrt();
+                            sum[0] += dx as f32 * weight;
+                            sum[1] += dy as f32 * weight;
+                            sum[2] += dz as f32 * weight;
+                        }
+                    }
+                }
+                let length = (sum[0] * sum[0] + sum[1] * sum[1] + sum[2] * sum[2]).sqrt();
+                if length > 0.0 {
+                    normals.set(
+                        location,
+                        [sum[0] / length, sum[1] / length, sum[2] / length],
+                    );
+                }
+            }
+        }
+    }
+    normals
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The slope of the loaded ground inside region, like slope_map. Decorators can skip
+    /// steep columns and path costs can go up with it
+    pub fn slope_map(&self, region: Aabb) -> Volume<f32> {
+        slope_map(self, region)
+    }
+
+    /// The normals of the loaded surface inside region, like estimate_surface_normals,
+    /// e.g. for picking textures by steepness
+    pub fn estimate_surface_normals(&self, region: Aabb) -> Volume<[f32; 3]> {
+        estimate_surface_normals(self, region)
+    }
+}
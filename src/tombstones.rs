@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::error::{ErrorContext, Operation, Result, ResultExt};
+use super::event_log::ChunkLog;
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::region::RegionFile;
+use super::{Chunk, ChunkLocation, Dimension};
+
+/// How long removed chunks can be restored for, unless set otherwise
+pub const DEFAULT_TOMBSTONE_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Moves the record in a slot from one region file to another, returning false if there
+/// was none. The record is written to its new file before it is removed from the old one,
+/// so a crash in between leaves it in both rather than neither
+fn move_record(from: &Path, to: &Path, slot: usize) -> io::Result<bool> {
+    if !from.exists() {
+        return Ok(false);
+    }
+    let mut source = RegionFile::open(from)?;
+    let record = match source.read(slot)? {
+        Some(record) => record,
+        None => return Ok(false),
+    };
+    fs::create_dir_all(to.parent().unwrap())?;
+    RegionFile::open(to)?.write(slot, &record)?;
+    source.remove(slot)?;
+    Ok(true)
+}
+
+/// A removed chunk, kept until its retention runs out
+#[derive(Clone)]
+struct Tombstone<T> {
+    /// when the chunk was removed, in seconds since the unix epoch
+    removed_at: u64,
+    /// the chunk itself, if it couldn't be saved before it was removed. Otherwise its
+    /// record is moved out of the region files, if it was ever saved
+    chunk: Option<Chunk<T>>,
+    /// the chunk's edit log, when event sourcing
+    log: Option<ChunkLog<T>>,
+}
+
+/// Chunks that were removed but can still be restored, so a tool removing chunks by
+/// mistake doesn't lose them for good
+#[derive(Clone)]
+pub struct Tombstones<T> {
+    retention: Duration,
+    removed: HashMap<ChunkLocation, Tombstone<T>>,
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Tombstones<T> {
+    pub fn new() -> Tombstones<T> {
+        Tombstones {
+            retention: DEFAULT_TOMBSTONE_RETENTION,
+            removed: HashMap::new(),
+        }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    pub fn set_retention(&mut self, retention: Duration) {
+        self.retention = retention;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+    }
+
+    /// Writes when each chunk whose record was moved aside was removed. Chunks only kept in
+    /// memory aren't written, since they couldn't be saved in the first place
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut saved: Vec<(&ChunkLocation, &Tombstone<T>)> = self
+            .removed
+            .iter()
+            .filter(|(_, tombstone)| tombstone.chunk.is_none())
+            .collect();
+        // in a fixed order, so canonical saves come out the same
+        saved.sort_by_key(|(location, _)| (location.z, location.y, location.x));
+        out.write_u32::<LittleEndian>(saved.len() as u32)?;
+        for (location, tombstone) in saved {
+            write_point(out, *location)?;
+            out.write_u64::<LittleEndian>(tombstone.removed_at)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the tombstones back, with the default retention
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Tombstones<T>> {
+        let mut tombstones = Tombstones::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let location = read_point(input)?;
+            tombstones.removed.insert(
+                location,
+                Tombstone {
+                    removed_at: input.read_u64::<LittleEndian>()?,
+                    chunk: None,
+                    log: None,
+                },
+            );
+        }
+        Ok(tombstones)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Where the records of removed chunks are moved, if there is a disk cache
+    fn tombstone_path(&self, location: ChunkLocation) -> Option<PathBuf> {
+        self.disk_cache.as_ref().map(|folder| {
+            Path::new(folder)
+                .join("tombstones")
+                .join(RegionFile::file_name(location))
+        })
+    }
+
+    /// How long removed chunks can be restored for before purge_expired_tombstones deletes
+    /// them. A week unless set otherwise
+    pub fn set_tombstone_retention(&mut self, retention: Duration) {
+        self.tombstones.set_retention(retention);
+    }
+
+    pub fn tombstone_retention(&self) -> Duration {
+        self.tombstones.retention()
+    }
+
+    /// Removes a chunk from the world, keeping it aside so restore_chunk can bring it back
+    /// until its retention runs out. Returns false if the chunk wasn't defined. Removing a
+    /// chunk that was removed before drops the older copy
+    pub fn remove_chunk(&mut self, location: ChunkLocation) -> Result<bool> {
+        if !self.chunk_defined(location) {
+            return Ok(false);
+        }
+        self.promote_chunk(location)?;
+        // what gets restored is the chunk as it was last edited
+        self.sync_chunk(location)?;
+        let chunk = if self.disk_cache.is_none() || self.chunk_dirty(location) {
+            self.loaded_chunks.get(&location).cloned()
+        } else {
+            None
+        };
+        if let (None, Some(from), Some(to)) = (
+            &chunk,
+            self.region_path(location),
+            self.tombstone_path(location),
+        ) {
+            move_record(&from, &to, RegionFile::slot(location)).with_context(|| {
+                ErrorContext::new(Operation::RemoveChunk)
+                    .chunk(location)
+                    .path(&from)
+            })?;
+        }
+        self.all_chunk_locations.remove(&location);
+        self.discard_chunk(location);
+        let log = self.chunk_logs.remove(&location);
+        self.tombstones.removed.insert(
+            location,
+            Tombstone {
+                removed_at: now(),
+                chunk,
+                log,
+            },
+        );
+        Ok(true)
+    }
+
+    /// Brings back a removed chunk, replacing whatever was defined there since. Returns
+    /// false if there is no such removed chunk
+    pub fn restore_chunk(&mut self, location: ChunkLocation) -> Result<bool> {
+        let tombstone = match self.tombstones.removed.remove(&location) {
+            Some(tombstone) => tombstone,
+            None => return Ok(false),
+        };
+        if self.chunk_defined(location) {
+            self.all_chunk_locations.remove(&location);
+            self.discard_chunk(location);
+            self.chunk_logs.remove(&location);
+            self.delete_chunk_from_disk(location)?;
+        }
+        if let Some(log) = tombstone.log {
+            self.chunk_logs.insert(location, log);
+        }
+        match tombstone.chunk {
+            Some(chunk) => self.add_chunk_in_place(location, chunk),
+            None => {
+                if let (Some(from), Some(to)) =
+                    (self.tombstone_path(location), self.region_path(location))
+                {
+                    move_record(&from, &to, RegionFile::slot(location)).with_context(|| {
+                        ErrorContext::new(Operation::RestoreChunk)
+                            .chunk(location)
+                            .path(&from)
+                    })?;
+                }
+                self.all_chunk_locations.insert(location);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn is_tombstoned(&self, location: ChunkLocation) -> bool {
+        self.tombstones.removed.contains_key(&location)
+    }
+
+    /// The removed chunks and when they were removed, oldest first
+    pub fn tombstones(&self) -> Vec<(ChunkLocation, SystemTime)> {
+        let mut tombstones: Vec<(ChunkLocation, u64)> = self
+            .tombstones
+            .removed
+            .iter()
+            .map(|(location, tombstone)| (*location, tombstone.removed_at))
+            .collect();
+        tombstones.sort_by_key(|(location, removed_at)| {
+            (*removed_at, location.z, location.y, location.x)
+        });
+        tombstones
+            .into_iter()
+            .map(|(location, removed_at)| (location, UNIX_EPOCH + Duration::from_secs(removed_at)))
+            .collect()
+    }
+
+    /// Deletes a removed chunk for good, without waiting for its retention to run out.
+    /// Returns false if there is no such removed chunk
+    pub fn purge_tombstone(&mut self, location: ChunkLocation) -> Result<bool> {
+        if !self.is_tombstoned(location) {
+            return Ok(false);
+        }
+        if let Some(path) = self.tombstone_path(location) {
+            if path.exists() {
+                let context = || {
+                    ErrorContext::new(Operation::DeleteChunk)
+                        .chunk(location)
+                        .path(&path)
+                };
+                let mut region = RegionFile::open(&path).with_context(context)?;
+                region
+                    .remove(RegionFile::slot(location))
+                    .with_context(context)?;
+                if region.slots().is_empty() {
+                    drop(region);
+                    fs::remove_file(&path).with_context(context)?;
+                }
+            }
+        }
+        self.tombstones.removed.remove(&location);
+        Ok(true)
+    }
+
+    /// Deletes the removed chunks whose retention ran out, returning how many were deleted.
+    /// Called on every flush
+    pub fn purge_expired_tombstones(&mut self) -> Result<usize> {
+        let retention = self.tombstone_retention().as_secs();
+        let now = now();
+        let expired: Vec<ChunkLocation> = self
+            .tombstones
+            .removed
+            .iter()
+            .filter(|(_, tombstone)| now.saturating_sub(tombstone.removed_at) >= retention)
+            .map(|(location, _)| *location)
+            .collect();
+        for location in expired.iter() {
+            self.purge_tombstone(*location)?;
+        }
+        Ok(expired.len())
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use super::occupancy::VoxelClass;
+use super::registry::VoxelRegistry;
+use super::Voxel;
+
+/// The abilities an agent has for getting past voxels it couldn't otherwise walk through
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    pub const OPEN_DOORS: Capabilities = Capabilities(1);
+    pub const SWIM: Capabilities = Capabilities(1 << 1);
+    pub const CLIMB: Capabilities = Capabilities(1 << 2);
+
+    /// Custom abilities, for bits 8 and up
+    pub const fn custom(bit: u32) -> Capabilities {
+        Capabilities(1 << (bit + 8))
+    }
+
+    pub const fn union(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+
+    /// If every ability in other is also in self
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// How a conditionally traversable voxel type may be passed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ConditionalPassage {
+    /// the abilities needed to pass through the voxel
+    pub requires: Capabilities,
+    /// if agents inside the voxel don't need solid ground underneath, like swimmers in
+    /// water or climbers on a ladder
+    pub floor_free: bool,
+}
+
+/// Voxel types whose traversability depends on who is moving, like doors or water
+#[derive(Clone, Default)]
+pub struct TraversalRules {
+    conditional: HashMap<u32, ConditionalPassage>,
+}
+
+impl TraversalRules {
+    pub fn new() -> TraversalRules {
+        TraversalRules::default()
+    }
+
+    /// Makes voxels with the id traversable only by agents with the required abilities,
+    /// whether or not they are solid
+    pub fn register_conditional(&mut self, id: u32, passage: ConditionalPassage) {
+        self.conditional.insert(id, passage);
+    }
+
+    pub fn unregister_conditional(&mut self, id: u32) {
+        self.conditional.remove(&id);
+    }
+
+    pub fn conditional(&self, voxel: &Voxel) -> Option<&ConditionalPassage> {
+        self.conditional.get(&voxel.id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditional.is_empty()
+    }
+}
+
+/// Who is moving, for deciding which voxels can be passed
+#[derive(Copy, Clone)]
+pub struct Agent<'a> {
+    pub capabilities: Capabilities,
+    pub rules: &'a TraversalRules,
+    /// the voxel types that say what is solid, the builtin ones if None
+    pub registry: Option<&'a VoxelRegistry>,
+}
+
+impl<'a> Agent<'a> {
+    pub fn new(capabilities: Capabilities, rules: &'a TraversalRules) -> Agent<'a> {
+        Agent {
+            capabilities,
+            rules,
+            registry: None,
+        }
+    }
+
+    /// The agent going by a world's loaded voxel types, as given by Dimension::registry,
+    /// so custom types are passed or stood on as they say
+    pub fn in_registry(self, registry: Option<&'a VoxelRegistry>) -> Agent<'a> {
+        Agent { registry, ..self }
+    }
+
+    /// If the agent can occupy the voxel, and whether it then still needs a floor
+    pub fn can_occupy(&self, voxel: &Voxel) -> Option<bool> {
+        match self.rules.conditional(voxel) {
+            Some(passage) if self.capabilities.contains(passage.requires) => {
+                Some(!passage.floor_free)
+            }
+            Some(_) => None,
+            None if voxel.is_solid_in(self.registry) => None,
+            None => Some(true),
+        }
+    }
+}
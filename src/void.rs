@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{ChunkLocation, Dimension, GlobalLocation, CHUNK_Z_SIZE};
+
+/// Builds the voxel at a location of the void
+pub type VoidFn<T> = Arc<dyn Fn(GlobalLocation) -> T + Send + Sync>;
+
+/// What the space below or above the world is made of
+#[derive(Clone)]
+pub enum VoidPolicy<T> {
+    /// Every voxel is the same, like bedrock below the world or air above it
+    Fill(T),
+    /// Voxels come from a callback, e.g. a pattern of bedrock and lava
+    Generate(VoidFn<T>),
+}
+
+impl<T: Copy> VoidPolicy<T> {
+    fn voxel(&self, location: GlobalLocation) -> T {
+        match self {
+            VoidPolicy::Fill(value) => *value,
+            VoidPolicy::Generate(generate) => generate(location),
+        }
+    }
+}
+
+/// The void below a floor and above a ceiling, where the world isn't stored but reads
+/// still give an answer
+#[derive(Clone)]
+pub struct Void<T> {
+    /// voxels with a z below this are void
+    below: Option<(i32, VoidPolicy<T>)>,
+    /// voxels with a z at or above this are void
+    above: Option<(i32, VoidPolicy<T>)>,
+}
+
+impl<T: Copy> Void<T> {
+    pub fn new() -> Void<T> {
+        Void {
+            below: None,
+            above: None,
+        }
+    }
+
+    /// If voxels at this z are void
+    pub fn covers(&self, z: i32) -> bool {
+        self.below.as_ref().is_some_and(|(floor, _)| z < *floor)
+            || self
+                .above
+                .as_ref()
+                .is_some_and(|(ceiling, _)| z >= *ceiling)
+    }
+
+    /// If any voxel of the chunk is void
+    pub fn touches_chunk(&self, location: ChunkLocation) -> bool {
+        let bottom = location.z * CHUNK_Z_SIZE as i32;
+        self.covers(bottom) || self.covers(bottom + CHUNK_Z_SIZE as i32 - 1)
+    }
+
+    /// The voxel at location, None if it isn't void
+    pub fn voxel(&self, location: GlobalLocation) -> Option<T> {
+        match (&self.below, &self.above) {
+            (Some((floor, policy)), _) if location.z < *floor => Some(policy.voxel(location)),
+            (_, Some((ceiling, policy))) if location.z >= *ceiling => Some(policy.voxel(location)),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Makes every voxel below floor void. Reads there give what the policy says, whether
+    /// or not a chunk is defined there, so get_voxel, get_volume, raycasts and paths never
+    /// run into undefined chunks below the world. Writes there are dropped
+    pub fn set_void_below(&mut self, floor: i32, policy: VoidPolicy<T>) {
+        self.void.below = Some((floor, policy));
+    }
+
+    /// Makes every voxel at ceiling and above void, like set_void_below
+    pub fn set_void_above(&mut self, ceiling: i32, policy: VoidPolicy<T>) {
+        self.void.above = Some((ceiling, policy));
+    }
+
+    pub fn clear_void_below(&mut self) {
+        self.void.below = None;
+    }
+
+    pub fn clear_void_above(&mut self) {
+        self.void.above = None;
+    }
+
+    /// The voxel the void puts at location, None if location isn't void
+    pub fn void_voxel(&self, location: GlobalLocation) -> Option<T> {
+        self.void.voxel(location)
+    }
+}
@@ -0,0 +1,256 @@
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+
+use super::codec::VoxelCodec;
+use super::error::Result;
+use super::occupancy::VoxelClass;
+use super::region_load::chunks_in;
+use super::{
+    Aabb, Chunk, ChunkLocation, Dimension, GlobalLocation, Point3D, Volume, VoxelRead, VoxelWrite,
+};
+
+/// The voxels two boxes share, an empty box if they don't overlap
+fn overlap(a: Aabb, b: Aabb) -> Aabb {
+    Aabb::new(
+        Point3D::new(
+            max(a.start.x, b.start.x),
+            max(a.start.y, b.start.y),
+            max(a.start.z, b.start.z),
+        ),
+        Point3D::new(
+            min(a.end.x, b.end.x),
+            min(a.end.y, b.end.y),
+            min(a.end.z, b.end.z),
+        ),
+    )
+}
+
+/// A portion of the world copied out into a Volume, that writes the voxels changed through
+/// it back into the chunks it was copied from when committed or dropped. Reads and writes
+/// go to the copy, so they don't need a chunk lookup per voxel. Writing back skips claims
+/// and the validator, like write_voxel
+pub struct Scope<'a, T: Copy + Default + VoxelClass + VoxelCodec> {
+    dimension: &'a mut Dimension<T>,
+    volume: Volume<T>,
+    /// which voxels of the volume were written
+    written: Vec<bool>,
+}
+
+impl<'a, T: Copy + Default + VoxelClass + VoxelCodec> Scope<'a, T> {
+    /// Writes the changed voxels back into the world. Dropping the scope does the same, but
+    /// has no way to report chunks that failed to load
+    pub fn commit(mut self) -> Result<()> {
+        let result = self
+            .dimension
+            .write_volume(&self.volume, Some(&self.written));
+        self.written.iter_mut().for_each(|written| *written = false);
+        result
+    }
+
+    /// Forgets every change made through the scope
+    pub fn discard(mut self) {
+        self.written.iter_mut().for_each(|written| *written = false);
+    }
+
+    /// The copy of the voxels the scope works on
+    pub fn volume(&self) -> &Volume<T> {
+        &self.volume
+    }
+}
+
+impl<'a, T: Copy + Default + VoxelClass + VoxelCodec> VoxelRead<T> for Scope<'a, T> {
+    fn bounds(&self) -> (GlobalLocation, GlobalLocation) {
+        self.volume.bounds()
+    }
+
+    fn within_bounds(&self, location: GlobalLocation) -> bool {
+        self.volume.within_bounds(location)
+    }
+
+    fn get(&self, location: GlobalLocation) -> T {
+        self.volume.get(location)
+    }
+}
+
+impl<'a, T: Copy + Default + VoxelClass + VoxelCodec> VoxelWrite<T> for Scope<'a, T> {
+    /// Panics if location is outside of the scope
+    fn set(&mut self, location: GlobalLocation, value: T) {
+        self.volume.set(location, value);
+        self.written[self.volume.get_index(location)] = true;
+    }
+}
+
+impl<'a, T: Copy + Default + VoxelClass + VoxelCodec> Drop for Scope<'a, T> {
+    fn drop(&mut self) {
+        if self.written.iter().any(|written| *written) {
+            let _ = self
+                .dimension
+                .write_volume(&self.volume, Some(&self.written));
+        }
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Copies the voxels from start up to end out of the world, loading chunks as needed and
+    /// looking each one up once instead of once per voxel. Each voxel is what try_get_voxel
+    /// would read there, except that voxels in chunks that aren't defined and can't be
+    /// generated are left as the default value
+    pub fn get_volume(&mut self, start: GlobalLocation, end: GlobalLocation) -> Result<Volume<T>> {
+        let mut volume = Volume::new(start, end, T::default());
+        // the part of the box inside the world's bounds is copied chunk by chunk
+        let inside = match self.bounds {
+            Some(bounds) => overlap(Aabb::new(start, end), bounds),
+            None => Aabb::new(start, end),
+        };
+        for chunk_location in chunks_in(inside) {
+            // entirely void chunks don't need loading
+            let bounds = Self::get_chunk_bounds(chunk_location);
+            if (bounds.start.z..bounds.end.z).all(|z| self.void.covers(z)) {
+                continue;
+            }
+            if !self.chunk_available(chunk_location) {
+                continue;
+            }
+            let chunk = self.get_chunk(chunk_location)?;
+            let part = overlap(bounds, inside);
+            let row = (part.end.x - part.start.x) as usize;
+            for z in part.start.z..part.end.z {
+                for y in part.start.y..part.end.y {
+                    let location = Point3D::new(part.start.x, y, z);
+                    let from = Chunk::<T>::get_index(Self::get_voxel_location(location));
+                    let to = volume.get_index(location);
+                    for i in 0..row {
+                        volume.voxels[to + i] = chunk.voxels.get(from + i);
+                    }
+                }
+            }
+        }
+        // the rest is read wherever the out of bounds policy sends it
+        if self.bounds.is_some() {
+            for z in start.z..end.z {
+                for y in start.y..end.y {
+                    for x in start.x..end.x {
+                        let location = Point3D::new(x, y, z);
+                        if inside.contains(location)
+                            || self.void.covers(z)
+                            || self.structure_at(location).is_some()
+                        {
+                            continue;
+                        }
+                        if let Some(resolved) = self.resolve_location(location)? {
+                            let chunk_location = Self::get_chunk_location(resolved);
+                            if self.void.covers(resolved.z) {
+                                continue;
+                            }
+                            if self.chunk_available(chunk_location) {
+                                let index = volume.get_index(location);
+                                volume.voxels[index] = self
+                                    .get_chunk(chunk_location)?
+                                    .get(Self::get_voxel_location(resolved));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for z in start.z..end.z {
+            for y in start.y..end.y {
+                for x in start.x..end.x {
+                    let location = Point3D::new(x, y, z);
+                    let value = match self.structure_at(location) {
+                        Some(value) => value,
+                        None => match self.void.voxel(location) {
+                            Some(value) => value,
+                            None => continue,
+                        },
+                    };
+                    let index = volume.get_index(location);
+                    volume.voxels[index] = value;
+                }
+            }
+        }
+        Ok(volume)
+    }
+
+    /// The voxel placed structures put at location, if they are resolved on reads
+    fn structure_at(&self, location: GlobalLocation) -> Option<T> {
+        if self.resolve_structures {
+            self.structures.voxel_at(location)
+        } else {
+            None
+        }
+    }
+
+    /// Writes every voxel of the volume back into the world, loading chunks as needed. Like
+    /// write_voxel, it goes through the out of bounds policy but doesn't check claims or the
+    /// validator
+    pub fn set_volume(&mut self, volume: &Volume<T>) -> Result<()> {
+        self.write_volume(volume, None)
+    }
+
+    /// Copies the voxels from start up to end into a Scope, which writes the ones changed
+    /// through it back when it is committed or dropped
+    pub fn scope(&mut self, start: GlobalLocation, end: GlobalLocation) -> Result<Scope<'_, T>> {
+        let volume = self.get_volume(start, end)?;
+        let written = vec![false; volume.voxels.len()];
+        Ok(Scope {
+            dimension: self,
+            volume,
+            written,
+        })
+    }
+
+    /// Writes the voxels of the volume into the world, only the ones marked as written if
+    /// given. Locations go through the out of bounds policy like with write_voxel, and
+    /// nothing is written if one of them is out of bounds under OutOfBounds::Error. Each
+    /// chunk is looked up once, and every write is accounted for like with set_voxel
+    fn write_volume(&mut self, volume: &Volume<T>, written: Option<&[bool]>) -> Result<()> {
+        let (start, end) = volume.bounds();
+        let mut writes: BTreeMap<(i32, i32, i32), Vec<(GlobalLocation, T)>> = BTreeMap::new();
+        for z in start.z..end.z {
+            for y in start.y..end.y {
+                for x in start.x..end.x {
+                    let location = Point3D::new(x, y, z);
+                    let index = volume.get_index(location);
+                    if written.is_some_and(|written| !written[index]) {
+                        continue;
+                    }
+                    // writes into the void or dropped by the out of bounds policy are skipped
+                    let location = match self.resolve_location(location)? {
+                        Some(location) if !self.void.covers(location.z) => location,
+                        _ => continue,
+                    };
+                    let chunk_location = Self::get_chunk_location(location);
+                    writes
+                        .entry((chunk_location.z, chunk_location.y, chunk_location.x))
+                        .or_default()
+                        .push((location, volume.voxels[index]));
+                }
+            }
+        }
+        // chunks without writes aren't touched, so a scope may span undefined chunks
+        for ((z, y, x), writes) in writes {
+            let chunk_location = ChunkLocation::new(x, y, z);
+            let chunk = self.get_chunk_mut(chunk_location)?;
+            let mut edits = Vec::with_capacity(writes.len());
+            for (location, value) in writes {
+                let voxel_location = Self::get_voxel_location(location);
+                edits.push((location, chunk.get(voxel_location), value));
+                chunk.set(voxel_location, value);
+            }
+            self.dirty_chunks.insert(chunk_location);
+            self.chunk_changed(chunk_location);
+            self.chunk_edited(chunk_location);
+            for (location, old, value) in edits {
+                self.stats.record(&old, &value);
+                self.sky_voxel_written(location, &value);
+                self.log_edit(location, value);
+                self.changes.record(location, old, value);
+                self.voxel_mesh_changed(location);
+                self.voxel_region_dirtied(location);
+                self.multiblock_voxel_written(location, old, value);
+            }
+        }
+        Ok(())
+    }
+}
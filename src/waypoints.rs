@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::metadata::{read_point, read_string, write_point, write_string};
+use super::occupancy::VoxelClass;
+use super::{Aabb, Dimension, GlobalLocation};
+
+/// Named locations saved with the world, like a spawn point or a quest marker
+#[derive(Clone, Default)]
+pub struct Waypoints {
+    points: BTreeMap<String, GlobalLocation>,
+}
+
+impl Waypoints {
+    pub fn new() -> Waypoints {
+        Waypoints::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Writes the waypoints in name order
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_u32::<LittleEndian>(self.points.len() as u32)?;
+        for (name, location) in self.points.iter() {
+            write_string(out, name)?;
+            write_point(out, *location)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Waypoints> {
+        let mut waypoints = Waypoints::new();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let name = read_string(input)?;
+            waypoints.points.insert(name, read_point(input)?);
+        }
+        Ok(waypoints)
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Names a location, returning where the name pointed before if it was taken
+    pub fn set_waypoint(&mut self, name: &str, location: GlobalLocation) -> Option<GlobalLocation> {
+        self.waypoints.points.insert(String::from(name), location)
+    }
+
+    pub fn waypoint(&self, name: &str) -> Option<GlobalLocation> {
+        self.waypoints.points.get(name).copied()
+    }
+
+    pub fn remove_waypoint(&mut self, name: &str) -> Option<GlobalLocation> {
+        self.waypoints.points.remove(name)
+    }
+
+    /// Every waypoint, in name order
+    pub fn waypoints(&self) -> impl Iterator<Item = (&str, GlobalLocation)> {
+        self.waypoints
+            .points
+            .iter()
+            .map(|(name, location)| (name.as_str(), *location))
+    }
+
+    /// The waypoints inside a box, in name order
+    pub fn waypoints_within(&self, area: Aabb) -> Vec<(&str, GlobalLocation)> {
+        self.waypoints()
+            .filter(|(_, location)| area.contains(*location))
+            .collect()
+    }
+}
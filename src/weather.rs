@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::codec::VoxelCodec;
+use super::metadata::{read_point, write_point};
+use super::occupancy::VoxelClass;
+use super::rng::WorldRng;
+use super::{
+    ChunkLocation, Dimension, GlobalLocation, Point3D, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE,
+};
+
+/// The size of a weather cell on a side, in voxels
+pub const WEATHER_CELL_SIZE: i32 = 4;
+
+const CELLS_X: i32 = CHUNK_X_SIZE as i32 / WEATHER_CELL_SIZE;
+const CELLS_Y: i32 = CHUNK_Y_SIZE as i32 / WEATHER_CELL_SIZE;
+const CELLS_Z: i32 = CHUNK_Z_SIZE as i32 / WEATHER_CELL_SIZE;
+const CELLS_PER_CHUNK: usize = (CELLS_X * CELLS_Y * CELLS_Z) as usize;
+
+/// The weather of a cell of the world
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Climate {
+    /// in degrees celsius
+    pub temperature: f32,
+    /// how wet the air and ground are, from 0 for dry to 1 for soaked
+    pub moisture: f32,
+    /// how much rain or snow is falling, from 0 for none to 1 for a downpour
+    pub precipitation: f32,
+}
+
+impl Default for Climate {
+    fn default() -> Climate {
+        Climate {
+            temperature: 15.0,
+            moisture: 0.5,
+            precipitation: 0.0,
+        }
+    }
+}
+
+impl Climate {
+    fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_f32::<LittleEndian>(self.temperature)?;
+        out.write_f32::<LittleEndian>(self.moisture)?;
+        out.write_f32::<LittleEndian>(self.precipitation)
+    }
+
+    fn read_from<R: Read>(input: &mut R) -> io::Result<Climate> {
+        Ok(Climate {
+            temperature: input.read_f32::<LittleEndian>()?,
+            moisture: input.read_f32::<LittleEndian>()?,
+            precipitation: input.read_f32::<LittleEndian>()?,
+        })
+    }
+}
+
+/// The weather of the cells of a chunk, WEATHER_CELL_SIZE voxels on a side each
+#[derive(Clone, PartialEq, Debug)]
+pub struct ChunkWeather {
+    cells: [Climate; CELLS_PER_CHUNK],
+}
+
+impl ChunkWeather {
+    /// A chunk with the same weather everywhere
+    pub fn uniform(climate: Climate) -> ChunkWeather {
+        ChunkWeather {
+            cells: [climate; CELLS_PER_CHUNK],
+        }
+    }
+
+    fn index(cell: Point3D) -> usize {
+        ((cell.z * CELLS_Y + cell.y) * CELLS_X + cell.x) as usize
+    }
+
+    /// The climate of a cell, by its position in the chunk counted in cells
+    pub fn cell(&self, cell: Point3D) -> Climate {
+        self.cells[ChunkWeather::index(cell)]
+    }
+
+    pub fn set_cell(&mut self, cell: Point3D, climate: Climate) {
+        self.cells[ChunkWeather::index(cell)] = climate;
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [Climate] {
+        &mut self.cells
+    }
+
+    /// The average climate of the chunk
+    pub fn average(&self) -> Climate {
+        let count = CELLS_PER_CHUNK as f32;
+        let sum = self.cells.iter().fold((0.0, 0.0, 0.0), |sum, cell| {
+            (
+                sum.0 + cell.temperature,
+                sum.1 + cell.moisture,
+                sum.2 + cell.precipitation,
+            )
+        });
+        Climate {
+            temperature: sum.0 / count,
+            moisture: sum.1 / count,
+            precipitation: sum.2 / count,
+        }
+    }
+}
+
+/// Moves the weather of a chunk on by a step, e.g. drying out the ground or letting a
+/// storm pass. Called with the chunk, the current tick and the world's random source, for
+/// weather that comes and goes by chance
+pub type WeatherFn =
+    Arc<dyn Fn(ChunkLocation, u64, &mut ChunkWeather, &mut dyn WorldRng) + Send + Sync>;
+
+/// How the weather changes with time
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct WeatherSettings {
+    /// how many ticks pass between steps of the weather
+    pub interval: u64,
+    /// how far each cell moves toward the average of the cells beside it every step, from
+    /// 0 for not at all to 1 for all the way, so climates blend where biomes meet
+    pub diffusion: f32,
+}
+
+impl Default for WeatherSettings {
+    fn default() -> WeatherSettings {
+        WeatherSettings {
+            interval: 20,
+            diffusion: 0.0,
+        }
+    }
+}
+
+/// The coarse weather of the world, kept for the chunks it has been set for. Chunks
+/// without weather of their own have the default climate
+#[derive(Clone, Default)]
+pub struct WeatherLayer {
+    chunks: BTreeMap<(i32, i32, i32), ChunkWeather>,
+    default: Climate,
+    settings: WeatherSettings,
+    evolve: Option<WeatherFn>,
+}
+
+impl WeatherLayer {
+    pub fn new() -> WeatherLayer {
+        WeatherLayer::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    fn key(location: ChunkLocation) -> (i32, i32, i32) {
+        (location.z, location.y, location.x)
+    }
+
+    /// The climate of a cell, counted in cells from the origin
+    fn cell(&self, cell: Point3D) -> Climate {
+        let chunk = ChunkLocation::new(
+            cell.x.div_euclid(CELLS_X),
+            cell.y.div_euclid(CELLS_Y),
+            cell.z.div_euclid(CELLS_Z),
+        );
+        match self.chunks.get(&WeatherLayer::key(chunk)) {
+            Some(weather) => weather.cell(Point3D::new(
+                cell.x.rem_euclid(CELLS_X),
+                cell.y.rem_euclid(CELLS_Y),
+                cell.z.rem_euclid(CELLS_Z),
+            )),
+            None => self.default,
+        }
+    }
+
+    /// Blends every cell with the cells beside it
+    fn diffuse(&mut self, rate: f32) {
+        let mut blended = self.chunks.clone();
+        for ((z, y, x), weather) in blended.iter_mut() {
+            for cell_z in 0..CELLS_Z {
+                for cell_y in 0..CELLS_Y {
+                    for cell_x in 0..CELLS_X {
+                        let local = Point3D::new(cell_x, cell_y, cell_z);
+                        let global = Point3D::new(x * CELLS_X, y * CELLS_Y, z * CELLS_Z) + local;
+                        let mut average = Climate {
+                            temperature: 0.0,
+                            moisture: 0.0,
+                            precipitation: 0.0,
+                        };
+                        for offset in [
+                            Point3D::new(1, 0, 0),
+                            Point3D::new(-1, 0, 0),
+                            Point3D::new(0, 1, 0),
+                            Point3D::new(0, -1, 0),
+                            Point3D::new(0, 0, 1),
+                            Point3D::new(0, 0, -1),
+                        ] {
+                            let neighbor = self.cell(global + offset);
+                            average.temperature += neighbor.temperature / 6.0;
+                            average.moisture += neighbor.moisture / 6.0;
+                            average.precipitation += neighbor.precipitation / 6.0;
+                        }
+                        let old = self.cell(global);
+                        weather.set_cell(
+                            local,
+                            Climate {
+                                temperature: old.temperature
+                                    + (average.temperature - old.temperature) * rate,
+                                moisture: old.moisture + (average.moisture - old.moisture) * rate,
+                                precipitation: old.precipitation
+                                    + (average.precipitation - old.precipitation) * rate,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        self.chunks = blended;
+    }
+
+    /// Writes the default climate and the weather of every chunk, in chunk order
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.default.write_to(out)?;
+        out.write_u32::<LittleEndian>(self.chunks.len() as u32)?;
+        for ((z, y, x), weather) in self.chunks.iter() {
+            write_point(out, ChunkLocation::new(*x, *y, *z))?;
+            for cell in weather.cells.iter() {
+                cell.write_to(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the weather back into the layer, keeping its settings and evolution
+    pub fn read_from<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        self.default = Climate::read_from(input)?;
+        self.chunks.clear();
+        for _ in 0..input.read_u32::<LittleEndian>()? {
+            let location = read_point(input)?;
+            let mut weather = ChunkWeather::uniform(self.default);
+            for cell in weather.cells.iter_mut() {
+                *cell = Climate::read_from(input)?;
+            }
+            self.chunks.insert(WeatherLayer::key(location), weather);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// The climate of the weather cell holding location
+    pub fn weather_at(&self, location: GlobalLocation) -> Climate {
+        self.weather.cell(Point3D::new(
+            location.x.div_euclid(WEATHER_CELL_SIZE),
+            location.y.div_euclid(WEATHER_CELL_SIZE),
+            location.z.div_euclid(WEATHER_CELL_SIZE),
+        ))
+    }
+
+    /// Sets the climate of the weather cell holding location, giving its chunk weather of
+    /// its own if it had none
+    pub fn set_weather_at(&mut self, location: GlobalLocation, climate: Climate) {
+        let chunk = Self::get_chunk_location(location);
+        let default = self.weather.default;
+        let local = Self::get_voxel_location(location);
+        self.weather
+            .chunks
+            .entry(WeatherLayer::key(chunk))
+            .or_insert_with(|| ChunkWeather::uniform(default))
+            .set_cell(
+                Point3D::new(
+                    local.x / WEATHER_CELL_SIZE,
+                    local.y / WEATHER_CELL_SIZE,
+                    local.z / WEATHER_CELL_SIZE,
+                ),
+                climate,
+            );
+    }
+
+    /// The weather of a chunk, None if it has the default climate
+    pub fn chunk_weather(&self, location: ChunkLocation) -> Option<&ChunkWeather> {
+        self.weather.chunks.get(&WeatherLayer::key(location))
+    }
+
+    pub fn set_chunk_weather(&mut self, location: ChunkLocation, weather: ChunkWeather) {
+        self.weather
+            .chunks
+            .insert(WeatherLayer::key(location), weather);
+    }
+
+    /// Gives a chunk the default climate again
+    pub fn clear_chunk_weather(&mut self, location: ChunkLocation) -> Option<ChunkWeather> {
+        self.weather.chunks.remove(&WeatherLayer::key(location))
+    }
+
+    /// The climate of chunks without weather of their own
+    pub fn set_default_climate(&mut self, climate: Climate) {
+        self.weather.default = climate;
+    }
+
+    pub fn default_climate(&self) -> Climate {
+        self.weather.default
+    }
+
+    pub fn set_weather_settings(&mut self, settings: WeatherSettings) {
+        self.weather.settings = settings;
+    }
+
+    pub fn weather_settings(&self) -> WeatherSettings {
+        self.weather.settings
+    }
+
+    /// Calls evolve on the weather of every chunk that has its own, once every interval
+    /// ticks
+    pub fn set_weather_evolution(&mut self, evolve: WeatherFn) {
+        self.weather.evolve = Some(evolve);
+    }
+
+    pub fn clear_weather_evolution(&mut self) {
+        self.weather.evolve = None;
+    }
+
+    /// Moves the weather on by a step: cells blend with their neighbors as the settings
+    /// say, then the evolution hook runs on every chunk. Called on every interval'th tick
+    pub fn step_weather(&mut self) {
+        let diffusion = self.weather.settings.diffusion;
+        if diffusion > 0.0 {
+            self.weather.diffuse(diffusion.min(1.0));
+        }
+        let evolve = match &self.weather.evolve {
+            Some(evolve) => Arc::clone(evolve),
+            None => return,
+        };
+        let tick = self.current_tick();
+        for ((z, y, x), weather) in self.weather.chunks.iter_mut() {
+            evolve(
+                ChunkLocation::new(*x, *y, *z),
+                tick,
+                weather,
+                &mut *self.rng,
+            );
+        }
+    }
+
+    /// Steps the weather if a step is due at the current tick
+    pub fn tick_weather(&mut self) {
+        let interval = self.weather.settings.interval.max(1);
+        if self.current_tick().is_multiple_of(interval) {
+            self.step_weather();
+        }
+    }
+}
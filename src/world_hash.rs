@@ -0,0 +1,79 @@
+use twox_hash::XxHash64;
+
+use super::codec::VoxelCodec;
+use super::occupancy::VoxelClass;
+use super::{Aabb, ChunkLocation, Dimension};
+
+/// The content hash of every loaded chunk of a region, for comparing worlds between
+/// lockstep clients
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorldHash {
+    /// a hash of all the chunk hashes, cheap to send every tick
+    pub hash: u64,
+    /// the hash of each chunk, sorted by location, sent when the hashes disagree
+    pub chunks: Vec<(ChunkLocation, u64)>,
+}
+
+impl WorldHash {
+    /// The first chunk whose content differs between the two hashes, including chunks only
+    /// one side has loaded. None if the worlds agree
+    pub fn first_difference(&self, other: &WorldHash) -> Option<ChunkLocation> {
+        if self.hash == other.hash {
+            return None;
+        }
+        let (mut ours, mut theirs) = (
+            self.chunks.iter().peekable(),
+            other.chunks.iter().peekable(),
+        );
+        loop {
+            match (ours.peek(), theirs.peek()) {
+                (Some(a), Some(b)) => {
+                    let order = chunk_order(a.0).cmp(&chunk_order(b.0));
+                    if order.is_lt() {
+                        return Some(a.0);
+                    } else if order.is_gt() {
+                        return Some(b.0);
+                    } else if a.1 != b.1 {
+                        return Some(a.0);
+                    }
+                    ours.next();
+                    theirs.next();
+                }
+                (Some(a), None) => return Some(a.0),
+                (None, Some(b)) => return Some(b.0),
+                (None, None) => return None,
+            }
+        }
+    }
+}
+
+/// The order chunks are hashed in, the same on every client
+fn chunk_order(location: ChunkLocation) -> (i32, i32, i32) {
+    (location.z, location.y, location.x)
+}
+
+impl<T: Copy + Default + VoxelClass + VoxelCodec> Dimension<T> {
+    /// Hashes the loaded chunks overlapping the region, the same way on every run and
+    /// platform. Chunks that aren't loaded are left out, so clients should compare regions
+    /// they all have loaded
+    pub fn world_hash(&self, region: Aabb) -> WorldHash {
+        let mut chunks: Vec<(ChunkLocation, u64)> = self
+            .loaded_chunks
+            .iter()
+            .filter(|(location, _)| region.intersects(&Self::get_chunk_bounds(**location)))
+            .map(|(location, chunk)| (*location, chunk.content_hash()))
+            .collect();
+        chunks.sort_by_key(|(location, _)| chunk_order(*location));
+        let mut bytes = Vec::with_capacity(chunks.len() * 20);
+        for (location, hash) in chunks.iter() {
+            bytes.extend_from_slice(&location.x.to_le_bytes());
+            bytes.extend_from_slice(&location.y.to_le_bytes());
+            bytes.extend_from_slice(&location.z.to_le_bytes());
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        WorldHash {
+            hash: XxHash64::oneshot(0, &bytes),
+            chunks,
+        }
+    }
+}